@@ -0,0 +1,674 @@
+/*
+* Whole syncs against a local stand-in for the Discogs API,
+* serving responses recorded from the real thing (tests/fixtures/api),
+* all the way into an in-memory database.
+*/
+
+use std::{
+    time::Duration,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+};
+
+use crate::app::{
+    mock::{MockServer, MockRequest, MockResponse},
+    ratelimit::{RateLimiter, Backoff},
+    request::{self, Auth, DiscogsClient, UpdateError},
+    database::{Database, admin, query::{self, SortOrder}, update::SyncSummary},
+    update,
+    edit,
+};
+use crate::utils;
+
+const USERNAME: &str = "cogsy-test";
+const ALL_PAGE_2: &str = "/users/cogsy-test/collection/folders/0/releases?per_page=100&page=2";
+
+fn fixture(path: &str) -> Option<&'static str> {
+    if path == "/oauth/identity" {
+        return Some(include_str!("../../tests/fixtures/api/identity.json"))
+    }
+    if path == "/releases/1296390" {
+        return Some(include_str!("../../tests/fixtures/api/release_1296390.json"))
+    }
+    let path = path.strip_prefix("/users/cogsy-test")?;
+    Some(match path {
+        "" => include_str!("../../tests/fixtures/api/profile.json"),
+        "/collection/folders" => include_str!("../../tests/fixtures/api/folders.json"),
+        "/collection/fields" => include_str!("../../tests/fixtures/api/fields.json"),
+        "/collection/value" => include_str!("../../tests/fixtures/api/collection_value.json"),
+        "/collection/folders/0/releases?per_page=100" =>
+            include_str!("../../tests/fixtures/api/collection_0_page1.json"),
+        "/collection/folders/0/releases?per_page=100&page=2" =>
+            include_str!("../../tests/fixtures/api/collection_0_page2.json"),
+        "/collection/folders/1/releases?per_page=100" =>
+            include_str!("../../tests/fixtures/api/collection_1.json"),
+        "/collection/folders/2162484/releases?per_page=100" =>
+            include_str!("../../tests/fixtures/api/collection_2162484.json"),
+        "/wants?per_page=100" => include_str!("../../tests/fixtures/api/wants_page1.json"),
+        "/wants?per_page=100&page=2" => include_str!("../../tests/fixtures/api/wants_page2.json"),
+        _ => return None,
+    })
+}
+
+/// Serves the fixtures, unless `answer` has something else to say about a request.
+fn stand_in<F>(answer: F) -> MockServer
+where
+    F: Fn(&MockRequest) -> Option<MockResponse> + Send + Sync + 'static
+{
+    MockServer::start(move |request| {
+        answer(request)
+            .or_else(|| fixture(&request.path).map(MockResponse::ok))
+            .unwrap_or_else(|| MockResponse::status(404, r#"{"message": "The requested resource was not found."}"#))
+    })
+}
+
+fn client(server: &MockServer) -> DiscogsClient {
+    DiscogsClient::new(server.url(), Auth::token("test"), RateLimiter::new(6000), Backoff {
+        base: Duration::from_millis(1),
+        max: Duration::from_millis(10),
+        retries: 2,
+    })
+}
+
+fn database() -> Database {
+    let db = Database::open_path(":memory:").unwrap();
+    admin::init_db(&db).unwrap();
+    db
+}
+
+fn sync(db: &Database, server: &MockServer) -> Result<SyncSummary, UpdateError> {
+    update::full_with(db, &client(server), USERNAME, false, false)
+}
+
+fn titles(db: &Database, folder: &str) -> Vec<String> {
+    query::collection(db, SortOrder::Id).unwrap()
+        .contents.get(folder).cloned().unwrap_or_default()
+        .into_iter().map(|release| release.title).collect()
+}
+
+#[test]
+fn test_full_sync_into_database() {
+    let server = stand_in(|_| None);
+    let db = database();
+
+    let summary = sync(&db, &server).unwrap();
+    assert_eq!(summary.added, 5);
+
+    let profile = query::profile(&db).unwrap();
+    assert_eq!(profile.username, USERNAME);
+    assert_eq!(profile.collection, 3);
+
+    // both pages of the All folder, in order
+    assert_eq!(titles(&db, "All"), vec!["Amber", "Geogaddi", "Music Has The Right To Children"]);
+    assert_eq!(titles(&db, "Electronic"), vec!["Music Has The Right To Children"]);
+    assert_eq!(titles(&db, "Uncategorized"), vec!["Amber", "Geogaddi"]);
+
+    // both pages of the wantlist
+    let wantlist = query::wantlist(&db, SortOrder::Id).unwrap();
+    let catnos: Vec<&str> = wantlist.iter().map(|release| release.catnos[0].as_str()).collect();
+    assert_eq!(catnos, vec!["WARPLP30", "WARPLP38"]);
+
+    // a second sync has nothing to do
+    let summary = sync(&db, &server).unwrap();
+    assert_eq!((summary.added, summary.removed, summary.modified), (0, 0, 0));
+}
+
+#[test]
+fn test_error_statuses_leave_the_database_alone() {
+    let cases = vec![
+        (401, UpdateError::AuthorizationError),
+        (404, UpdateError::NotFoundError),
+        (500, UpdateError::ServerError),
+    ];
+    for (status, expected) in cases {
+        let db = database();
+        sync(&db, &stand_in(|_| None)).unwrap();
+
+        // the folder listing fails halfway through the sync
+        let server = stand_in(move |request| {
+            if request.path.ends_with("/collection/folders") {
+                Some(MockResponse::status(status, "{}"))
+            } else {
+                None
+            }
+        });
+        let err = sync(&db, &server).unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string(), "{}", status);
+
+        assert_eq!(titles(&db, "All").len(), 3, "{}", status);
+        assert_eq!(query::wantlist(&db, SortOrder::Id).unwrap().len(), 2, "{}", status);
+    }
+}
+
+#[test]
+fn test_wantlist_is_paginated() {
+    let server = stand_in(|_| None);
+    let db = database();
+    sync(&db, &server).unwrap();
+
+    let mut paths: Vec<String> = server.requests().into_iter()
+        .map(|request| request.path)
+        .filter(|path| path.contains("/wants"))
+        .collect();
+    paths.sort();
+    assert_eq!(paths, vec![
+        "/users/cogsy-test/wants?per_page=100",
+        "/users/cogsy-test/wants?per_page=100&page=2",
+    ]);
+    let titles: Vec<String> = query::wantlist(&db, SortOrder::Id).unwrap()
+        .into_iter().map(|release| release.title).collect();
+    assert_eq!(titles, vec!["Selected Ambient Works Volume II", "Tri Repetae"]);
+}
+
+#[test]
+fn test_server_errors_are_retried() {
+    let failed = AtomicBool::new(false);
+    let server = stand_in(move |request| {
+        //* page 2 fails once, the retry gets through
+        if request.path == ALL_PAGE_2 && !failed.swap(true, Ordering::SeqCst) {
+            return Some(MockResponse::status(502, "<html>Bad Gateway</html>"))
+        }
+        None
+    });
+    let db = database();
+    sync(&db, &server).unwrap();
+
+    assert_eq!(titles(&db, "All").len(), 3);
+    let tries = server.requests().iter()
+        .filter(|request| request.path == ALL_PAGE_2)
+        .count();
+    assert_eq!(tries, 2);
+}
+
+#[test]
+fn test_malformed_json_is_rejected() {
+    let db = database();
+    sync(&db, &stand_in(|_| None)).unwrap();
+
+    for path in &[
+        "/users/cogsy-test",
+        "/users/cogsy-test/wants?per_page=100&page=2",
+        "/users/cogsy-test/collection/folders",
+        ALL_PAGE_2,
+    ] {
+        let server = stand_in(move |request| {
+            if request.path == *path {
+                Some(MockResponse::ok(r#"{"pagination": {"page": 2, "pages": 2"#))
+            } else {
+                None
+            }
+        });
+        let err = sync(&db, &server).unwrap_err();
+        assert!(matches!(err, UpdateError::JSONParseError), "{}: got {:?}", path, err);
+        assert_eq!(titles(&db, "All").len(), 3, "{}", path);
+    }
+}
+
+#[test]
+fn test_bad_releases_are_named() {
+    let db = database();
+    sync(&db, &stand_in(|_| None)).unwrap();
+
+    let page = include_str!("../../tests/fixtures/api/collection_0_page2.json");
+    let cases = vec![
+        (r#""year": 2002"#, r#""year": "2002""#, "field `basic_information.year`: invalid type: string"),
+        (r#""title": "Geogaddi","#, "", "field `basic_information.title`: missing field `title`"),
+    ];
+    for (from, to, expected) in cases {
+        let broken = page.replacen(from, to, 1);
+        assert_ne!(broken, page, "{}", from);
+        let server = stand_in(move |request| {
+            if request.path == ALL_PAGE_2 {
+                Some(MockResponse::ok(broken.clone()))
+            } else {
+                None
+            }
+        });
+        let err = sync(&db, &server).unwrap_err().to_string();
+        assert!(err.contains("release 183423"), "{}", err);
+        assert!(err.contains(expected), "{}", err);
+        assert_eq!(titles(&db, "All").len(), 3, "{}", from);
+    }
+}
+
+#[test]
+fn test_identity_is_verified() {
+    let server = stand_in(|_| None);
+    // usernames are compared the way Discogs compares them
+    assert_eq!(request::verify_identity(&client(&server), "Cogsy-Test ").unwrap(), USERNAME);
+
+    match request::verify_identity(&client(&server), "someone-else") {
+        Err(UpdateError::UsernameMismatchError(configured, actual)) => {
+            assert_eq!((configured.as_str(), actual.as_str()), ("someone-else", USERNAME));
+        }
+        other => panic!("expected a mismatch, got {:?}", other),
+    }
+
+    let server = stand_in(|request| {
+        if request.header("authorization") == Some("Discogs token=test") {
+            Some(MockResponse::status(401, r#"{"message": "You must authenticate to access this resource."}"#))
+        } else {
+            None
+        }
+    });
+    assert!(matches!(
+        request::verify_identity(&client(&server), USERNAME),
+        Err(UpdateError::AuthorizationError)
+    ));
+}
+
+#[test]
+fn test_wants_are_written_back() {
+    let server = stand_in(|request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("PUT", "/users/cogsy-test/wants/34270") => Some(MockResponse::status(
+                201, include_str!("../../tests/fixtures/api/want_added.json")
+            )),
+            ("DELETE", "/users/cogsy-test/wants/11583") => Some(MockResponse::status(204, "")),
+            ("PUT", _) | ("DELETE", _) => Some(MockResponse::status(404, r#"{"message": "Release not found."}"#)),
+            _ => None,
+        }
+    });
+    let db = database();
+    sync(&db, &server).unwrap();
+    let wanted = |db: &Database| -> Vec<i64> {
+        query::wantlist(db, SortOrder::Id).unwrap().into_iter().map(|release| release.id).collect()
+    };
+    assert_eq!(wanted(&db), vec![11583, 34270]);
+
+    // gone locally straight away, without a sync
+    let removed = edit::remove_want(&db, &client(&server), USERNAME, 11583).unwrap();
+    assert_eq!(removed.unwrap().title, "Selected Ambient Works Volume II");
+    assert_eq!(wanted(&db), vec![34270]);
+
+    let db2 = database();
+    let added = edit::add_want(&db2, &client(&server), USERNAME, 34270).unwrap();
+    assert_eq!((added.title.as_str(), added.catnos.clone()), ("Tri Repetae", vec![String::from("WARPLP38")]));
+    assert_eq!(wanted(&db2), vec![34270]);
+
+    assert!(matches!(
+        edit::add_want(&db2, &client(&server), USERNAME, 1),
+        Err(UpdateError::NoReleaseError(1))
+    ));
+    assert!(matches!(
+        edit::remove_want(&db2, &client(&server), USERNAME, 2),
+        Err(UpdateError::NotWantedError(2))
+    ));
+    assert_eq!(wanted(&db2), vec![34270]);
+
+    let writes: Vec<(String, String)> = server.requests().into_iter()
+        .filter(|request| request.method != "GET")
+        .map(|request| (request.method, request.path))
+        .collect();
+    assert_eq!(writes[..2], [
+        ("DELETE".to_string(), "/users/cogsy-test/wants/11583".to_string()),
+        ("PUT".to_string(), "/users/cogsy-test/wants/34270".to_string()),
+    ]);
+}
+
+#[test]
+fn test_collection_is_changed_on_discogs() {
+    let server = stand_in(|request| {
+        if request.path == "/users/cogsy-test/collection/releases/32654" {
+            return Some(MockResponse::ok(
+                include_str!("../../tests/fixtures/api/collection_release_32654.json")
+            ))
+        }
+        let path = request.path.strip_prefix("/users/cogsy-test/collection/folders")?;
+        match (request.method.as_str(), path) {
+            ("POST", "/2162484/releases/32654") => Some(MockResponse::status(201, 
+                r#"{"instance_id": 501234570, "resource_url": "https://api.discogs.com/users/cogsy-test/collection/folders/2162484/releases/32654/instances/501234570"}"#
+            )),
+            ("POST", "/1/releases/183423/instances/501234569") 
+            | ("DELETE", "/2162484/releases/1296390/instances/501234567")
+            | ("DELETE", "/3000001") => Some(MockResponse::status(204, "")),
+            ("POST", "") => Some(MockResponse::status(201, 
+                r#"{"id": 3000001, "name": "Ambient", "count": 0, "resource_url": "https://api.discogs.com/users/cogsy-test/collection/folders/3000001"}"#
+            )),
+            ("POST", "/3000001") => Some(MockResponse::ok(
+                r#"{"id": 3000001, "name": "Ambient Works", "count": 0, "resource_url": "https://api.discogs.com/users/cogsy-test/collection/folders/3000001"}"#
+            )),
+            _ => None,
+        }
+    });
+    let db = database();
+    sync(&db, &server).unwrap();
+    let requester = client(&server);
+
+    // the sync stored where each copy is
+    assert_eq!(query::instances(&db, 183423).unwrap(), vec![query::Instance {
+        folder: String::from("Uncategorized"),
+        folder_id: Some(1),
+        instance_id: Some(501234569),
+    }]);
+
+    // a second copy, found by name whatever its case
+    let (release, folder) = edit::add_to_folder(&db, &requester, USERNAME, 32654, "electronic").unwrap();
+    assert_eq!((release.title.as_str(), release.instance_id, folder.as_str()), ("Amber", Some(501234570), "Electronic"));
+    assert_eq!(titles(&db, "Electronic"), vec!["Amber", "Music Has The Right To Children"]);
+    assert_eq!(titles(&db, "All"), vec!["Amber", "Amber", "Geogaddi", "Music Has The Right To Children"]);
+    assert!(matches!(
+        edit::move_to_folder(&db, &requester, USERNAME, 32654, None, "Uncategorized"),
+        Err(UpdateError::MultipleInstancesError(32654, copies)) if copies == vec![
+            (501234568, String::from("Uncategorized")),
+            (501234570, String::from("Electronic")),
+        ]
+    ));
+
+    let (_, from, to) = edit::move_to_folder(&db, &requester, USERNAME, 183423, None, "Electronic").unwrap();
+    assert_eq!((from.as_str(), to.as_str()), ("Uncategorized", "Electronic"));
+    assert_eq!(titles(&db, "Uncategorized"), vec!["Amber"]);
+    assert_eq!(titles(&db, "Electronic"), vec!["Amber", "Geogaddi", "Music Has The Right To Children"]);
+    assert!(matches!(
+        edit::move_to_folder(&db, &requester, USERNAME, 183423, None, "All"),
+        Err(UpdateError::ProtectedFolderError(_))
+    ));
+
+    edit::remove_from_collection(&db, &requester, USERNAME, 1296390, None).unwrap();
+    assert_eq!(titles(&db, "Electronic"), vec!["Amber", "Geogaddi"]);
+    assert_eq!(titles(&db, "All"), vec!["Amber", "Amber", "Geogaddi"]);
+    assert!(query::owned(&db, 1296390).unwrap().is_none());
+    assert!(matches!(
+        edit::remove_from_collection(&db, &requester, USERNAME, 1296390, None),
+        Err(UpdateError::NotInCollectionError(1296390))
+    ));
+
+    assert_eq!(edit::create_folder(&db, &requester, USERNAME, "Ambient").unwrap(), "Ambient");
+    assert!(query::collection(&db, SortOrder::Id).unwrap().contents.contains_key("Ambient"));
+    assert!(matches!(
+        edit::create_folder(&db, &requester, USERNAME, "ambient"),
+        Err(UpdateError::FolderExistsError(name)) if name == "Ambient"
+    ));
+    edit::rename_folder(&db, &requester, USERNAME, "Ambient", "Ambient Works").unwrap();
+    assert!(matches!(
+        edit::delete_folder(&db, &requester, USERNAME, "Electronic"),
+        Err(UpdateError::FolderNotEmptyError(_))
+    ));
+    assert!(matches!(
+        edit::delete_folder(&db, &requester, USERNAME, "Uncategorized"),
+        Err(UpdateError::ProtectedFolderError(_))
+    ));
+    assert!(matches!(
+        edit::delete_folder(&db, &requester, USERNAME, "Ambient"),
+        Err(UpdateError::NoFolderError(_))
+    ));
+    edit::delete_folder(&db, &requester, USERNAME, "ambient works").unwrap();
+    let folders: Vec<String> = query::collection(&db, SortOrder::Id).unwrap().contents.keys().cloned().collect();
+    assert_eq!(folders, vec!["All", "Electronic", "Uncategorized"]);
+    admin::check_integrity(&db).unwrap();
+
+    let writes: Vec<(String, String, String)> = server.requests().into_iter()
+        .filter(|request| request.method != "GET")
+        .map(|request| (request.method, request.path.replace("/users/cogsy-test/collection/folders", ""), request.body))
+        .collect();
+    let expected = vec![
+        ("POST", "/2162484/releases/32654", ""),
+        ("POST", "/1/releases/183423/instances/501234569", r#"{"folder_id":2162484}"#),
+        ("DELETE", "/2162484/releases/1296390/instances/501234567", ""),
+        ("POST", "", r#"{"name":"Ambient"}"#),
+        ("POST", "/3000001", r#"{"name":"Ambient Works"}"#),
+        ("DELETE", "/3000001", ""),
+    ];
+    let expected: Vec<(String, String, String)> = expected.into_iter()
+        .map(|(method, path, body)| (method.into(), path.into(), body.into()))
+        .collect();
+    assert_eq!(writes, expected);
+}
+
+#[test]
+fn test_one_copy_is_changed_at_a_time() {
+    let server = stand_in(|request| {
+        if request.path == "/users/cogsy-test/collection/releases/32654" {
+            return Some(MockResponse::ok(
+                include_str!("../../tests/fixtures/api/collection_release_32654.json")
+            ))
+        }
+        let path = request.path.strip_prefix("/users/cogsy-test/collection/folders")?;
+        match (request.method.as_str(), path) {
+            ("POST", "/2162484/releases/32654") => Some(MockResponse::status(201, 
+                r#"{"instance_id": 501234570, "resource_url": "https://api.discogs.com/users/cogsy-test/collection/folders/2162484/releases/32654/instances/501234570"}"#
+            )),
+            ("POST", "/1/releases/32654/instances/501234568")
+            | ("DELETE", "/2162484/releases/32654/instances/501234570") => Some(MockResponse::status(204, "")),
+            _ => None,
+        }
+    });
+    let db = database();
+    sync(&db, &server).unwrap();
+    let requester = client(&server);
+    edit::add_to_folder(&db, &requester, USERNAME, 32654, "Electronic").unwrap();
+    let copy = |folder: &str, folder_id: i64, instance_id: i64| query::Instance {
+        folder: String::from(folder),
+        folder_id: Some(folder_id),
+        instance_id: Some(instance_id),
+    };
+
+    // the first copy moves, the second stays where it was
+    let (_, from, to) = edit::move_to_folder(&db, &requester, USERNAME, 32654, Some(501234568), "Electronic").unwrap();
+    assert_eq!((from.as_str(), to.as_str()), ("Uncategorized", "Electronic"));
+    assert_eq!(query::instances(&db, 32654).unwrap(), vec![
+        copy("Electronic", 2162484, 501234568),
+        copy("Electronic", 2162484, 501234570),
+    ]);
+    assert_eq!(titles(&db, "Uncategorized"), vec!["Geogaddi"]);
+
+    // the second copy goes, the first is still owned
+    let (_, folder) = edit::remove_from_collection(&db, &requester, USERNAME, 32654, Some(501234570)).unwrap();
+    assert_eq!(folder, "Electronic");
+    assert_eq!(query::instances(&db, 32654).unwrap(), vec![copy("Electronic", 2162484, 501234568)]);
+    assert!(query::owned(&db, 32654).unwrap().is_some());
+    assert!(matches!(
+        edit::remove_from_collection(&db, &requester, USERNAME, 32654, Some(501234570)),
+        Err(UpdateError::NoCopyError(32654, 501234570))
+    ));
+    admin::check_integrity(&db).unwrap();
+
+    let writes: Vec<(String, String, String)> = server.requests().into_iter()
+        .filter(|request| request.method != "GET")
+        .map(|request| (request.method, request.path.replace("/users/cogsy-test/collection/folders", ""), request.body))
+        .collect();
+    let expected = vec![
+        ("POST", "/2162484/releases/32654", ""),
+        ("POST", "/1/releases/32654/instances/501234568", r#"{"folder_id":2162484}"#),
+        ("DELETE", "/2162484/releases/32654/instances/501234570", ""),
+    ];
+    let expected: Vec<(String, String, String)> = expected.into_iter()
+        .map(|(method, path, body)| (method.into(), path.into(), body.into()))
+        .collect();
+    assert_eq!(writes, expected);
+}
+
+#[test]
+fn test_ratings_sync_both_ways() {
+    let server = stand_in(|request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/users/cogsy-test/collection/folders/1/releases/32654/instances/501234568") => {
+                Some(MockResponse::status(204, ""))
+            }
+            _ => None,
+        }
+    });
+    let db = database();
+    sync(&db, &server).unwrap();
+    let rating = |db: &Database, id: i64| query::owned(db, id).unwrap().unwrap().rating;
+    assert_eq!((rating(&db, 1296390), rating(&db, 183423), rating(&db, 32654)), (5, 4, 0));
+
+    let rated = edit::rate(&db, &client(&server), USERNAME, 32654, 3).unwrap();
+    assert_eq!(rated.rating, 3);
+    assert_eq!(rating(&db, 32654), 3);
+    let request = server.requests().into_iter().find(|request| request.method == "POST").unwrap();
+    assert_eq!(request.body, r#"{"rating":3}"#);
+
+    // only what's in the collection has a rating
+    assert!(matches!(
+        edit::rate(&db, &client(&server), USERNAME, 34270, 3),
+        Err(UpdateError::NotInCollectionError(34270))
+    ));
+
+    // Discogs still says it's unrated, and a sync takes its word for it
+    let summary = sync(&db, &server).unwrap();
+    assert_eq!((summary.added, summary.removed, summary.modified), (0, 0, 1));
+    assert_eq!(rating(&db, 32654), 0);
+}
+
+#[test]
+fn test_release_details_are_stored() {
+    let server = stand_in(|_| None);
+    let db = database();
+    sync(&db, &server).unwrap();
+
+    let release = query::owned(&db, 1296390).unwrap().unwrap();
+    assert_eq!(release.genres, vec!["Electronic"]);
+    assert_eq!(release.styles, vec!["IDM", "Downtempo"]);
+    assert_eq!(release.master_id, Some(13019));
+    assert_eq!(release.thumb, "https://i.discogs.com/thumb/1296390.jpg");
+    assert_eq!(release.cover_image, "https://i.discogs.com/cover/1296390.jpg");
+    assert_eq!(release.format("{title}: {genres} / {styles}"),
+        "Music Has The Right To Children: Electronic / IDM, Downtempo");
+    assert_eq!(release.format("{year}: {id}"), "1998: 1296390");
+    assert!(release.to_string().contains(&format!("\nFormats: {}\n", release.formats.join(", "))));
+    assert!(!release.to_string().contains(&format!("\nFormats: {}\n", release.labels.join(", "))));
+
+    let wantlist = query::wantlist(&db, SortOrder::Id).unwrap();
+    assert!(wantlist.iter().all(|release| !release.genres.is_empty()));
+}
+
+#[test]
+fn test_copies_keep_their_notes() {
+    let server = stand_in(|_| None);
+    let db = database();
+    sync(&db, &server).unwrap();
+
+    let collection = query::collection(&db, SortOrder::Id).unwrap();
+    let copy = &collection.contents["Electronic"][0];
+    assert_eq!(copy.instance_id, Some(501234567));
+    let notes: Vec<(&str, &str)> = copy.notes.iter()
+        .map(|note| (note.field.as_str(), note.value.as_str()))
+        .collect();
+    // the custom field goes by the name Discogs has for it
+    assert_eq!(notes, vec![
+        ("Media Condition", "Near Mint (NM or M-)"),
+        ("Sleeve Condition", "Very Good Plus (VG+)"),
+        ("Notes", "gatefold, slight ring wear"),
+        ("Shelf", "B3"),
+    ]);
+    let amber = collection.contents["Uncategorized"].iter().find(|r| r.id == 32654).unwrap();
+    assert!(amber.notes.is_empty());
+    assert!(query::wantlist(&db, SortOrder::Id).unwrap().iter().all(|r| r.notes.is_empty()));
+}
+
+#[test]
+fn test_notes_keep_default_names_without_the_field_list() {
+    let server = stand_in(|request| {
+        if request.path == "/users/cogsy-test/collection/fields" {
+            Some(MockResponse::status(500, ""))
+        } else {
+            None
+        }
+    });
+    let db = database();
+    sync(&db, &server).unwrap();
+
+    let collection = query::collection(&db, SortOrder::Id).unwrap();
+    let fields: Vec<&str> = collection.contents["Electronic"][0].notes.iter()
+        .map(|note| note.field.as_str())
+        .collect();
+    assert_eq!(fields, vec!["Media Condition", "Sleeve Condition", "Notes", "Field 4"]);
+}
+
+#[test]
+fn test_release_details_are_cached() {
+    let offline = Arc::new(AtomicBool::new(false));
+    let switch = offline.clone();
+    let server = stand_in(move |_| {
+        if switch.load(Ordering::SeqCst) {
+            Some(MockResponse::status(503, ""))
+        } else {
+            None
+        }
+    });
+    let db = database();
+    assert!(query::details(&db, 1296390).unwrap().is_none());
+
+    let details = update::details(&db, &client(&server), 1296390).unwrap();
+    assert_eq!((details.country.as_str(), details.released.as_str()), ("UK", "1998-04-20"));
+    assert_eq!(details.notes, "Recorded at Hexagon Sun.\r\nGatefold sleeve.");
+    let tracks: Vec<(&str, &str, &str, bool)> = details.tracklist.iter()
+        .map(|t| (t.position.as_str(), t.title.as_str(), t.duration.as_str(), t.heading))
+        .collect();
+    // index tracks are headings over their sub-tracks
+    assert_eq!(tracks, vec![
+        ("", "Side One", "", true),
+        ("A1", "Wildlife Analysis", "1:17", false),
+        ("A2", "An Eagle In Your Mind", "6:23", false),
+        ("", "Side Two", "", true),
+        ("B1", "The Color Of The Fire", "1:45", false),
+        ("B2", "Telephasic Workshop", "", false),
+    ]);
+    assert_eq!(details.credits[1].role, "Lacquer Cut By");
+    assert_eq!(details.credits[1].tracks, "A1 to B2");
+    assert_eq!(details.identifiers.len(), 3);
+    assert_eq!(details.identifiers[2].description, "");
+
+    // what's cached is what was fetched
+    let cached = query::details(&db, 1296390).unwrap().unwrap();
+    assert_eq!(cached.fetched.timestamp(), details.fetched.timestamp());
+    assert_eq!(cached.tracklist, details.tracklist);
+    assert_eq!(cached.credits, details.credits);
+    assert_eq!(cached.identifiers, details.identifiers);
+
+    // offline, the cache still answers and a failed refresh leaves it alone
+    offline.store(true, Ordering::SeqCst);
+    let requests = server.requests().len();
+    assert_eq!(update::details(&db, &client(&server), 1296390).unwrap().tracklist, details.tracklist);
+    assert_eq!(server.requests().len(), requests);
+    assert!(update::refresh_details(&db, &client(&server), 1296390).is_err());
+    assert_eq!(query::details(&db, 1296390).unwrap().unwrap().credits, details.credits);
+
+    // back online, a refresh replaces it rather than adding to it
+    offline.store(false, Ordering::SeqCst);
+    update::refresh_details(&db, &client(&server), 1296390).unwrap();
+    assert_eq!(query::details(&db, 1296390).unwrap().unwrap().tracklist.len(), 6);
+
+    assert!(matches!(
+        update::details(&db, &client(&server), 404),
+        Err(UpdateError::NoReleaseError(404))
+    ));
+}
+
+#[test]
+fn test_collection_value_is_kept_per_sync() {
+    let server = stand_in(|_| None);
+    let db = database();
+    sync(&db, &server).unwrap();
+
+    let history = query::value_history(&db).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(
+        (history[0].minimum.as_str(), history[0].median.as_str(), history[0].maximum.as_str()),
+        ("$310.15", "$612.30", "$1,203.48")
+    );
+    assert_eq!(utils::parse_amount(&history[0].maximum), Some(1203.48));
+
+    // every sync adds to the history, even when nothing changed
+    std::thread::sleep(Duration::from_millis(2));
+    sync(&db, &server).unwrap();
+    let history = query::value_history(&db).unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history[0].fetched < history[1].fetched);
+}
+
+#[test]
+fn test_sync_goes_ahead_without_the_collection_value() {
+    let server = stand_in(|request| {
+        if request.path == "/users/cogsy-test/collection/value" {
+            Some(MockResponse::status(500, ""))
+        } else {
+            None
+        }
+    });
+    let db = database();
+    let summary = sync(&db, &server).unwrap();
+    assert!(summary.added > 0);
+    assert!(query::value_history(&db).unwrap().is_empty());
+    assert_eq!(titles(&db, "Electronic"), vec!["Music Has The Right To Children"]);
+}
@@ -1,4 +1,4 @@
-use std::{io, process};
+use std::{io::{self, Write}, process, path::Path};
 use clap::{
     App as Clap,
     SubCommand,
@@ -6,15 +6,23 @@ use clap::{
     ArgMatches,
 };
 use crate::CONFIG;
+use crate::config::{Config, Discogs};
 use crate::utils;
+use crate::commands;
+use crate::screens::spending;
 use crate::app::{
     ListenLogEntry,
     Release,
     App,
     update::{self, UpdateError},
+    request::Auth,
+    oauth,
+    edit,
     database::{
+        Database,
+        DBError,
         admin,
-        query::{self, QueryType},
+        query::{self, QueryType, SpendingBy},
         update as dbupdate,
         purge,
     },
@@ -94,11 +102,96 @@ pub fn init<'a>() -> Clap<'a, 'a> {
                 .short("w")
                 .long("wantlist")
                 .takes_value(true)
+                .multiple(true)
                 .help("Use this switch to query from the wantlist.")
             )
             .arg(Arg::with_name("albumname")
-                .help("The name of the album you want to query.")
+                .multiple(true)
+                .help("The album you want to query, or fields like artist:name year:>1995")
+            )
+        )
+        .subcommand(SubCommand::with_name("want")
+            .about("Add or remove releases from your wantlist on Discogs.")
+            .subcommand(SubCommand::with_name("add")
+                .about("Adds a release to your wantlist.")
+                .arg(Arg::with_name("id")
+                    .required(true)
+                    .validator(validate_release_id)
+                    .help("The Discogs ID of the release, e.g. 11583 or r11583."))
+            )
+            .subcommand(SubCommand::with_name("remove")
+                .about("Removes a release from your wantlist.")
+                .arg(Arg::with_name("id")
+                    .required(true)
+                    .validator(validate_release_id)
+                    .help("The Discogs ID of the release, e.g. 11583 or r11583."))
+            )
+        )
+        .subcommand(SubCommand::with_name("collection")
+            .about("Add, move or remove releases in your collection on Discogs.")
+            .subcommand(SubCommand::with_name("add")
+                .about("Adds a copy of a release to a folder, Uncategorized by default.")
+                .arg(Arg::with_name("id")
+                    .required(true)
+                    .validator(validate_release_id)
+                    .help("The Discogs ID of the release, e.g. 11583 or r11583."))
+                .arg(Arg::with_name("folder")
+                    .default_value("Uncategorized")
+                    .help("The folder to add it to."))
+            )
+            .subcommand(SubCommand::with_name("move")
+                .about("Moves a release to another folder.")
+                .arg(Arg::with_name("id")
+                    .required(true)
+                    .validator(validate_copy)
+                    .help("The Discogs ID of the release, e.g. 11583 or r11583, and of the copy if you own several, e.g. 11583/501234567."))
+                .arg(Arg::with_name("folder")
+                    .required(true)
+                    .help("The folder to move it to."))
+            )
+            .subcommand(SubCommand::with_name("remove")
+                .about("Removes a release from your collection.")
+                .arg(Arg::with_name("id")
+                    .required(true)
+                    .validator(validate_copy)
+                    .help("The Discogs ID of the release, e.g. 11583 or r11583, and of the copy if you own several, e.g. 11583/501234567."))
+            )
+        )
+        .subcommand(SubCommand::with_name("folder")
+            .about("Create, rename or delete collection folders on Discogs.")
+            .subcommand(SubCommand::with_name("create")
+                .about("Creates a folder.")
+                .arg(Arg::with_name("name")
+                    .required(true)
+                    .help("The name of the new folder."))
+            )
+            .subcommand(SubCommand::with_name("rename")
+                .about("Renames a folder.")
+                .arg(Arg::with_name("name")
+                    .required(true)
+                    .help("The folder to rename."))
+                .arg(Arg::with_name("new-name")
+                    .required(true)
+                    .help("Its new name."))
             )
+            .subcommand(SubCommand::with_name("delete")
+                .about("Deletes an empty folder.")
+                .arg(Arg::with_name("name")
+                    .required(true)
+                    .help("The folder to delete."))
+            )
+        )
+        .subcommand(SubCommand::with_name("value")
+            .about("Show what your collection has been worth at each update.")
+        )
+        .subcommand(SubCommand::with_name("spending")
+            .about("Show what you've spent on releases, from the prices recorded with `price`.")
+            .arg(Arg::with_name("by")
+                .short("b")
+                .long("by")
+                .takes_value(true)
+                .possible_values(&["month", "year", "folder"])
+                .help("Only show the spending by month, year or folder."))
         )
         .subcommand(SubCommand::with_name("database")
             .about("Options for database administration.")
@@ -106,13 +199,12 @@ pub fn init<'a>() -> Clap<'a, 'a> {
                 .short("r")
                 .long("reset")
                 .takes_value(false)
-                .help("Performs database reset.")
+                .help("Rebuilds the database from Discogs, keeping your listening history.")
             )
-            .arg(Arg::with_name("orphan")
-                .short("o")
-                .long("orphan")
+            .arg(Arg::with_name("wipe-everything")
+                .long("wipe-everything")
                 .takes_value(false)
-                .help("Performs orphan table removal.")
+                .help("Deletes everything, listening history included, and starts over.")
             )
             .arg(Arg::with_name("check")
                 .short("c")
@@ -120,7 +212,28 @@ pub fn init<'a>() -> Clap<'a, 'a> {
                 .takes_value(false)
                 .help("Performs database integrity check.")
             )
-        )   
+            .arg(Arg::with_name("backup")
+                .long("backup")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Saves a copy of the database to PATH.")
+            )
+            .arg(Arg::with_name("restore")
+                .long("restore")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Replaces the database with the backup at PATH.")
+            )
+        )
+        .subcommand(SubCommand::with_name("auth")
+            .about("Authorizes cogsy with your Discogs account through OAuth.")
+        )
+        .subcommand(SubCommand::with_name("config")
+            .about("Options for your configuration.")
+            .subcommand(SubCommand::with_name("verify")
+                .about("Checks your token or OAuth credentials with Discogs.")
+            )
+        )
 }
 
 // valid argument is: wantlist=<path> or collection=<path>
@@ -138,6 +251,18 @@ fn validate_csv(s: String) -> Result<(), String> {
     }
 }
 
+fn validate_release_id(s: String) -> Result<(), String> {
+    commands::parse_release_id(&s)
+        .map(|_| ())
+        .ok_or_else(|| format!("`{}` is not a Discogs release ID", s))
+}
+
+fn validate_copy(s: String) -> Result<(), String> {
+    commands::parse_copy(&s)
+        .map(|_| ())
+        .ok_or_else(|| format!("`{}` is not a Discogs release ID, or release and copy ID", s))
+}
+
 //* CLI Mode Exit codes:
 //* 0: All good
 //* 1: Incorrect input from user
@@ -148,11 +273,21 @@ pub fn parse_and_execute(clapapp: ArgMatches, app: &App) -> Option<i32> {
     if let Some(sub_m) = clapapp.subcommand_matches("update") {
         handle_update(sub_m , app)
     } else if let Some(sub_m) = clapapp.subcommand_matches("random") {
-        handle_random(sub_m)
+        handle_random(sub_m, &app.db)
     } else if let Some(sub_m) = clapapp.subcommand_matches("listen") {
-        handle_listen(sub_m)
+        handle_listen(sub_m, &app.db)
     } else if let Some(sub_m) = clapapp.subcommand_matches("query") {
-        handle_query(sub_m)
+        handle_query(sub_m, &app.db)
+    } else if let Some(sub_m) = clapapp.subcommand_matches("want") {
+        handle_want(sub_m, app)
+    } else if let Some(sub_m) = clapapp.subcommand_matches("collection") {
+        handle_collection(sub_m, app)
+    } else if let Some(sub_m) = clapapp.subcommand_matches("folder") {
+        handle_folder(sub_m, app)
+    } else if clapapp.subcommand_matches("value").is_some() {
+        handle_value(&app.db)
+    } else if let Some(sub_m) = clapapp.subcommand_matches("spending") {
+        handle_spending(sub_m, &app.db)
     } else {
         None
     }
@@ -163,8 +298,8 @@ fn handle_update(sub_m: &ArgMatches, app: &App) -> Option<i32> {
 
     let (one, two) = get_csv(sub_m);
     let csvs = CsvUpdate::from_tuple((
-        one.map(|s| UpdateType::from_arg(s)),
-        two.map(|s| UpdateType::from_arg(s)),
+        one.map(UpdateType::from_arg),
+        two.map(UpdateType::from_arg),
     ));
 
     if sub_m.is_present("username") {
@@ -181,8 +316,8 @@ fn handle_update(sub_m: &ArgMatches, app: &App) -> Option<i32> {
             println!("{}",
             Message::set("Beginning profile update.", MessageKind::Info)
             );
-            if let Err(e) = update::profile(&app.user_id, &app.token, true) {
-                Message::error(&e.to_string());
+            if let Err(e) = update::profile(&app.db, &app.user_id, &app.auth, true) {
+                Message::error(e.to_string());
             }
         }
         if sub_m.is_present("wantlist") {
@@ -192,13 +327,18 @@ fn handle_update(sub_m: &ArgMatches, app: &App) -> Option<i32> {
             );
             if let Some(path) = csvs.wantlist.as_ref() {
                 println!("Updating wantlist from CSV file at path `{}`.", path);
-                if let Err(e) = csv::update_want(path) {
-                    Message::error(&e.to_string());
-                } else {
-                    println!("{}", Message::success("Update from CSV successful."));
+                match csv::update_want(&app.db, path) {
+                    Ok(summary) => {
+                        println!("{}", Message::success("Update from CSV successful."));
+                        println!("Wantlist: {}", summary);
+                    }
+                    Err(e) => Message::error(e.to_string()),
+                }
+            } else {
+                match update::wantlist(&app.db, &app.user_id, &app.auth, true, verbose) {
+                    Ok(summary) => println!("Wantlist: {}", summary),
+                    Err(e) => Message::error(e.to_string()),
                 }
-            } else if let Err(e) = update::wantlist(&app.user_id, &app.token, true, verbose) {
-                Message::error(&e.to_string());
             }
         }
         if sub_m.is_present("collection") {
@@ -208,13 +348,18 @@ fn handle_update(sub_m: &ArgMatches, app: &App) -> Option<i32> {
             );
             if let Some(path) = csvs.collection.as_ref() {
                 println!("Updating collection from CSV file at path `{}`.", path);
-                if let Err(e) = csv::update_coll(path) {
-                    Message::error(&e.to_string());
-                } else {
-                    println!("{}", Message::success("Update from CSV successful."));
+                match csv::update_coll(&app.db, path) {
+                    Ok(summary) => {
+                        println!("{}", Message::success("Update from CSV successful."));
+                        println!("Collection: {}", summary);
+                    }
+                    Err(e) => Message::error(e.to_string()),
+                }
+            } else {
+                match update::collection(&app.db, &app.user_id, &app.auth, true, verbose) {
+                    Ok(summary) => println!("Collection: {}", summary),
+                    Err(e) => Message::error(e.to_string()),
                 }
-            } else if let Err(e) = update::collection(&app.user_id, &app.token, true, verbose) {
-                Message::error(&e.to_string());
             }
         }
         if ran_update {return Some(0)}
@@ -225,8 +370,8 @@ fn handle_update(sub_m: &ArgMatches, app: &App) -> Option<i32> {
 
         // if either is to be updated from CSV
         if csvs.wantlist.is_some() || csvs.collection.is_some() {
-            if let Err(e) = update::profile(&app.user_id, &app.token, true) {
-                Message::error(&e.to_string())
+            if let Err(e) = update::profile(&app.db, &app.user_id, &app.auth, true) {
+                Message::error(e.to_string())
             }
             println!("{}\n", Message::success("Profile update successful."));
         }
@@ -239,39 +384,55 @@ fn handle_update(sub_m: &ArgMatches, app: &App) -> Option<i32> {
                         s1, s2
                     )
                 ));
-                if let Err(e) = csv::full_update(s1, s2) {
-                    Message::error(&e.to_string());
+                match csv::full_update(&app.db, s1, s2) {
+                    Ok(summary) => {
+                        println!("{}", Message::success("Full CSV update successful."));
+                        println!("Changes: {}", summary);
+                    }
+                    Err(e) => Message::error(e.to_string()),
                 }
-                println!("{}", Message::success("Full CSV update successful."));
             }
             (Some(s), None) => {
                 println!("{}", Message::info(format!("Updating wantlist from CSV file at {}.", s)));
-                if let Err(e) = csv::update_want(s) {
-                    Message::error(&e.to_string());
+                match csv::update_want(&app.db, s) {
+                    Ok(summary) => {
+                        println!("{}", Message::success("Wantlist update successful."));
+                        println!("Wantlist: {}\n", summary);
+                    }
+                    Err(e) => Message::error(e.to_string()),
                 }
-                println!("{}\n", Message::success("Wantlist update successful."));
                 println!("{}", Message::info("Updating collection from Discogs."));
-                if let Err(e) = update::collection(&app.user_id, &app.token, true, verbose) {
-                    Message::error(&e.to_string());
+                match update::collection(&app.db, &app.user_id, &app.auth, true, verbose) {
+                    Ok(summary) => {
+                        println!("{}", Message::success("Collection update successful."));
+                        println!("Collection: {}", summary);
+                    }
+                    Err(e) => Message::error(e.to_string()),
                 }
-                println!("{}", Message::success("Collection update successful."))
             }
             (None, Some(s)) => {
                 println!("{}", Message::info("Updating wantlist from Discogs."));
-                if let Err(e) = update::wantlist(&app.user_id, &app.token, true, verbose) {
-                    Message::error(&e.to_string());
+                match update::wantlist(&app.db, &app.user_id, &app.auth, true, verbose) {
+                    Ok(summary) => {
+                        println!("{}", Message::success("Wantlist update successful."));
+                        println!("Wantlist: {}\n", summary);
+                    }
+                    Err(e) => Message::error(e.to_string()),
                 }
-                println!("{}\n", Message::success("Wantlist update successful."));
                 println!("{}", Message::info(format!("Updating collection from CSV file at {}", s)));
-                if let Err(e) = csv::update_coll(s) {
-                    Message::error(&e.to_string());
+                match csv::update_coll(&app.db, s) {
+                    Ok(summary) => {
+                        println!("{}", Message::success("Collection update from CSV successful."));
+                        println!("Collection: {}", summary);
+                    }
+                    Err(e) => Message::error(e.to_string()),
                 }
-                println!("{}", Message::success("Collection update from CSV successful."))
             }
             (None, None) => {
-                match update::full(&app.user_id, &app.token, true, verbose) {
-                    Ok(()) => {
+                match update::full(&app.db, &app.user_id, &app.auth, true, verbose) {
+                    Ok(summary) => {
                         println!("{}", Message::success("Database update successful."));
+                        println!("Changes: {}", summary);
                     }
                     Err(e) => {
                         eprintln!("\n{}", e);
@@ -383,12 +544,168 @@ fn get_csv<'a>(sub_m: &'a ArgMatches) -> (Option<&'a str>, Option<&'a str>) {
 
 //* End of spaghetti code
 
-fn handle_random(sub_m: &ArgMatches) -> Option<i32> {
+fn handle_want(sub_m: &ArgMatches, app: &App) -> Option<i32> {
+    //* the validator has already checked the ids
+    let id = |m: &ArgMatches| commands::parse_release_id(m.value_of("id").unwrap()).unwrap();
+    let requester = update::build_client(&app.auth);
+    let result = if let Some(add_m) = sub_m.subcommand_matches("add") {
+        edit::add_want(&app.db, &requester, &app.user_id, id(add_m)).map(|release| {
+            format!("Added `{}` by {} to your wantlist.", release.title, release.artist)
+        })
+    } else if let Some(remove_m) = sub_m.subcommand_matches("remove") {
+        let id = id(remove_m);
+        edit::remove_want(&app.db, &requester, &app.user_id, id).map(|removed| match removed {
+            Some(release) => format!("Removed `{}` by {} from your wantlist.", release.title, release.artist),
+            None => format!("Removed release {} from your wantlist.", id),
+        })
+    } else {
+        println!("{}", sub_m.usage());
+        return Some(1)
+    };
+    report_edit(result)
+}
+
+fn handle_collection(sub_m: &ArgMatches, app: &App) -> Option<i32> {
+    let id = |m: &ArgMatches| commands::parse_release_id(m.value_of("id").unwrap()).unwrap();
+    let copy = |m: &ArgMatches| commands::parse_copy(m.value_of("id").unwrap()).unwrap();
+    let requester = update::build_client(&app.auth);
+    let result = if let Some(add_m) = sub_m.subcommand_matches("add") {
+        edit::add_to_folder(&app.db, &requester, &app.user_id, id(add_m), add_m.value_of("folder").unwrap())
+            .map(|(release, folder)| {
+                format!("Added `{}` by {} to {}.", release.title, release.artist, folder)
+            })
+    } else if let Some(move_m) = sub_m.subcommand_matches("move") {
+        let (id, copy) = copy(move_m);
+        edit::move_to_folder(&app.db, &requester, &app.user_id, id, copy, move_m.value_of("folder").unwrap())
+            .map(|(release, from, to)| {
+                format!("Moved `{}` by {} from {} to {}.", release.title, release.artist, from, to)
+            })
+    } else if let Some(remove_m) = sub_m.subcommand_matches("remove") {
+        let (id, copy) = copy(remove_m);
+        edit::remove_from_collection(&app.db, &requester, &app.user_id, id, copy)
+            .map(|(release, folder)| {
+                format!("Removed `{}` by {} from {}.", release.title, release.artist, folder)
+            })
+    } else {
+        println!("{}", sub_m.usage());
+        return Some(1)
+    };
+    report_edit(result)
+}
+
+fn handle_folder(sub_m: &ArgMatches, app: &App) -> Option<i32> {
+    let requester = update::build_client(&app.auth);
+    let result = if let Some(create_m) = sub_m.subcommand_matches("create") {
+        edit::create_folder(&app.db, &requester, &app.user_id, create_m.value_of("name").unwrap())
+            .map(|name| format!("Created folder {}.", name))
+    } else if let Some(rename_m) = sub_m.subcommand_matches("rename") {
+        let new_name = rename_m.value_of("new-name").unwrap();
+        edit::rename_folder(&app.db, &requester, &app.user_id, rename_m.value_of("name").unwrap(), new_name)
+            .map(|name| format!("Renamed folder {} to {}.", name, new_name.trim()))
+    } else if let Some(delete_m) = sub_m.subcommand_matches("delete") {
+        edit::delete_folder(&app.db, &requester, &app.user_id, delete_m.value_of("name").unwrap())
+            .map(|name| format!("Deleted folder {}.", name))
+    } else {
+        println!("{}", sub_m.usage());
+        return Some(1)
+    };
+    report_edit(result)
+}
+
+//* prints how a change to the Discogs account went and picks the exit code
+fn report_edit(result: Result<String, UpdateError>) -> Option<i32> {
+    match result {
+        Ok(done) => {
+            println!("{}", Message::success(done));
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            match e {
+                UpdateError::NoReleaseError(_) 
+                | UpdateError::NotWantedError(_)
+                | UpdateError::NoFolderError(_)
+                | UpdateError::FolderExistsError(_)
+                | UpdateError::FolderNotEmptyError(_)
+                | UpdateError::ProtectedFolderError(_)
+                | UpdateError::NotInCollectionError(_)
+                | UpdateError::MultipleInstancesError(..)
+                | UpdateError::NoCopyError(..)
+                | UpdateError::NotSyncedError => Some(1),
+                UpdateError::DBWriteError(_) => {db_error_msg(); Some(2)}
+                _ => Some(2),
+            }
+        }
+    }
+}
+
+fn handle_value(db: &Database) -> Option<i32> {
+    let history = match query::value_history(db) {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("{}", e);
+            db_error_msg();
+            return Some(2)
+        }
+    };
+    if history.is_empty() {
+        println!("No collection value yet, run `cogsy update` to fetch it.");
+        return Some(1)
+    }
+    println!("{:<18} {:>12} {:>12} {:>12}  Change", "Updated", "Minimum", "Median", "Maximum");
+    let mut previous: Option<f64> = None;
+    for value in &history {
+        let median = utils::parse_amount(&value.median);
+        let change = match (previous, median) {
+            (Some(from), Some(to)) => utils::change(from, to),
+            _ => String::new(),
+        };
+        println!("{:<18} {:>12} {:>12} {:>12}  {}",
+            value.fetched.with_timezone(&CONFIG.timezone()).format("%d %m %Y %R"),
+            value.minimum, value.median, value.maximum, change
+        );
+        previous = median.or(previous);
+    }
+    Some(0)
+}
+
+fn handle_spending(sub_m: &ArgMatches, db: &Database) -> Option<i32> {
+    let report = match sub_m.value_of("by") {
+        None => spending::report(db),
+        Some(by) => {
+            let (heading, by) = match by {
+                "year" => ("Year", SpendingBy::Year),
+                "folder" => ("Folder", SpendingBy::Folder),
+                _ => ("Month", SpendingBy::Month),
+            };
+            query::spending(db, by).map(|rows| {
+                if rows.is_empty() {None} else {Some(spending::table(heading, &rows))}
+            })
+        }
+    };
+    match report {
+        Ok(Some(report)) => {
+            print!("{}", report);
+            Some(0)
+        }
+        Ok(None) => {
+            println!("Nothing spent yet, record prices with the `price` command in the app.");
+            Some(1)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            db_error_msg();
+            Some(2)
+        }
+    }
+}
+
+fn handle_random(sub_m: &ArgMatches, db: &Database) -> Option<i32> {
     if sub_m.is_present("nolog") {
         println!("{}", 
             Message::info("Selecting random album without logging.")
         );
-        match query::random() {
+        match query::random(db) {
             Ok(random) => {
                 println!("You should play `{}.`", random.title);
             }
@@ -402,7 +719,7 @@ fn handle_random(sub_m: &ArgMatches) -> Option<i32> {
         println!("{}", 
             Message::info("Selecting random album with logging.")
         );
-        match query::random() {
+        match query::random(db) {
             Ok(random) => {
                 let time_now = utils::get_utc_now();
                 let entry = ListenLogEntry {
@@ -410,7 +727,7 @@ fn handle_random(sub_m: &ArgMatches) -> Option<i32> {
                     title: &random.title,
                     time: time_now,
                 };
-                match dbupdate::listenlog(entry) {
+                match dbupdate::listenlog(db, entry) {
                     Ok(()) => {
                         println!("You should play `{}`.", random.title);
                     }
@@ -430,11 +747,10 @@ fn handle_random(sub_m: &ArgMatches) -> Option<i32> {
     Some(0)
 }
 
-fn handle_listen(sub_m: &ArgMatches) -> Option<i32> {
-    let album = sub_m.value_of("albumname").unwrap().to_string()
-        .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], "");
+fn handle_listen(sub_m: &ArgMatches, db: &Database) -> Option<i32> {
+    let album = sub_m.value_of("albumname").unwrap().to_string();
 
-    match query::release(&album, QueryType::Collection) {
+    match query::release(db, &album, QueryType::Collection) {
         Ok(results) => {
             match results.len() {
                 1 => {
@@ -444,7 +760,7 @@ fn handle_listen(sub_m: &ArgMatches) -> Option<i32> {
                         title: &results[0].title,
                         time: time_now,
                     };
-                    match dbupdate::listenlog(entry) {
+                    match dbupdate::listenlog(db, entry) {
                         Ok(()) => {println!("Listening to `{}` by {}", 
                             results[0].title, results[0].artist);}
                         Err(e) => {
@@ -457,7 +773,7 @@ fn handle_listen(sub_m: &ArgMatches) -> Option<i32> {
                 n if n > 1 => {
                     println!("{}",
                     Message::info(
-                        &format!("Multiple results for `{}`, pick one:", album),
+                        format!("Multiple results for `{}`, pick one:", album),
                     )
                     );
                     for (i, release) in results.iter().enumerate() {
@@ -486,7 +802,7 @@ fn handle_listen(sub_m: &ArgMatches) -> Option<i32> {
                                 title: &results[choice - 1].title,
                                 time: time_now,
                             };
-                            match dbupdate::listenlog(entry) {
+                            match dbupdate::listenlog(db, entry) {
                                 Ok(()) => {println!("Listening to `{}` by {}", 
                                     results[choice - 1].title, 
                                     results[choice - 1].artist);
@@ -520,34 +836,30 @@ fn handle_listen(sub_m: &ArgMatches) -> Option<i32> {
     Some(0)
 }
 
-fn handle_query(sub_m: &ArgMatches) -> Option<i32> {
-    let results: Vec<Release>;
-    let query: String;
-    let querytype: QueryType;
-    //TODO: Streamline this wet-ass code
-    if sub_m.is_present("wantlist") {
-        query = sub_m.value_of("wantlist")
-        .unwrap_or_else(|| {
-            println!("{} Album name is required.", Message::set("Error:", MessageKind::Error));
-            process::exit(1);
-        }).to_string()
-        .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], "");
-
-        querytype = QueryType::Wantlist;
-        println!("Querying wantlist for: {}", query);
+fn handle_query(sub_m: &ArgMatches, db: &Database) -> Option<i32> {
+    let (argname, querytype) = if sub_m.is_present("wantlist") {
+        ("wantlist", QueryType::Wantlist)
     } else {
-        query = sub_m.value_of("albumname")
+        ("albumname", QueryType::Collection)
+    };
+    let values = sub_m.values_of(argname)
         .unwrap_or_else(|| {
             println!("{} Album name is required.", Message::set("Error:", MessageKind::Error));
             process::exit(1);
-        }).to_string()
-        .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], "");
-
-        querytype = QueryType::Collection;
-        println!("Querying collection for: {}\n", query);
+        });
+    let query = match commands::parse_filter(&requote(values)) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Some(1)
+        }
+    };
+    match querytype {
+        QueryType::Wantlist => println!("Querying wantlist for: {}", query),
+        QueryType::Collection => println!("Querying collection for: {}\n", query),
     }
-    results = match query::release(
-        &query, querytype
+    let results: Vec<Release> = match query::search(
+        db, &query, querytype
     ) {
         Ok(queryr) => queryr,
         Err(e) => {
@@ -569,41 +881,209 @@ fn handle_query(sub_m: &ArgMatches) -> Option<i32> {
     Some(0)
 }
 
+//* the shell has already eaten the quotes in `artist:"Boards of Canada"`,
+//* so put them back around any argument that has spaces in it
+fn requote<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values.map(|value| {
+        if !value.contains(char::is_whitespace) || value.contains('"') {
+            return value.to_string()
+        }
+        match value.find(':') {
+            Some(idx) if value[..idx].chars().all(|c| c.is_ascii_alphabetic()) => {
+                format!("{}:\"{}\"", &value[..idx], &value[idx + 1..])
+            }
+            _ => format!("\"{}\"", value),
+        }
+    }).collect::<Vec<String>>().join(" ")
+}
+
 pub fn handle_database(sub_m: &ArgMatches) -> Option<i32> {
+    let given = ["reset", "wipe-everything", "check", "backup", "restore"].iter()
+        .filter(|arg| sub_m.is_present(arg))
+        .count();
+    if given > 1 {
+        return database_arg_error()
+    }
     if sub_m.is_present("reset") {
-        if sub_m.is_present("orphan") || sub_m.is_present("check") {
-            return database_arg_error()
+        return handle_reset(false)
+    } else if sub_m.is_present("wipe-everything") {
+        return handle_reset(true)
+    } else if sub_m.is_present("check") {
+        return handle_check()
+    } else if let Some(path) = sub_m.value_of("backup") {
+        return handle_backup(path)
+    } else if let Some(path) = sub_m.value_of("restore") {
+        return handle_restore(path)
+    }
+    Some(0)
+}
+
+pub fn handle_auth() -> Option<i32> {
+    let config = Config::load();
+    let discogs = config.discogs.clone().unwrap_or_default();
+    let consumer = match discogs.consumer() {
+        Some(consumer) => consumer,
+        None => {
+            eprintln!("error: no consumer key and secret in the `[discogs]` section of your config.");
+            eprintln!("{}", Message::hint(
+                "Register an application at https://www.discogs.com/settings/developers and add its \
+                `consumer_key` and `consumer_secret`, or keep using a personal token."
+            ));
+            return Some(1)
         }
-        return handle_reset()
-    } else if sub_m.is_present("orphan") {
-        if sub_m.is_present("reset") || sub_m.is_present("check") {
-            return database_arg_error()
+    };
+    let api_url = config.api_url();
+
+    let request = match oauth::request_token(&api_url, &consumer) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Some(2)
         }
-        return handle_orphans()
-    } else if sub_m.is_present("check") {
-        if sub_m.is_present("orphan") || sub_m.is_present("reset") {
-            return database_arg_error()
+    };
+    println!("Open this page, allow cogsy access and enter the code Discogs gives you:");
+    println!("{}", Message::info(oauth::authorize_url(&request)));
+    print!(">>> "); io::stdout().flush().unwrap();
+    let mut verifier = String::new();
+    io::stdin().read_line(&mut verifier)
+        .expect("Oops, could not read line.");
+    if verifier.trim().is_empty() {
+        println!("Nothing was changed.");
+        return Some(1)
+    }
+
+    let access = match oauth::access_token(&api_url, &request, verifier.trim()) {
+        Ok(access) => access,
+        Err(UpdateError::AuthorizationError) => {
+            eprintln!("error: Discogs did not accept that code. Run `cogsy auth` to try again.");
+            return Some(1)
         }
-        return handle_check()
-    } else {
+        Err(e) => {
+            eprintln!("{}", e);
+            return Some(2)
+        }
+    };
+    let config = Config {
+        discogs: Some(Discogs {
+            oauth_token: Some(access.token),
+            oauth_token_secret: Some(access.token_secret),
+            ..discogs
+        }),
+        ..config
+    };
+    if let Err(e) = config.save() {
+        eprintln!("error: could not save your credentials: {}", e);
+        return Some(2)
+    }
+    println!("{}", Message::success("Authorized. Requests are now signed with OAuth."));
+    verify(&config)
+}
 
+pub fn handle_config(sub_m: &ArgMatches) -> Option<i32> {
+    if sub_m.subcommand_matches("verify").is_some() {
+        return verify(&Config::load())
+    }
+    println!("{}", sub_m.usage());
+    Some(1)
+}
+
+fn verify(config: &Config) -> Option<i32> {
+    println!("{}", Message::info("Checking your credentials with Discogs."));
+    match update::verify_identity(&update::client_for(config), &config.user.username) {
+        Ok(username) => {
+            println!("{}", Message::success(format!("Credentials OK, signed in as `{}`.", username)));
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            match e {
+                UpdateError::AuthorizationError | UpdateError::UsernameMismatchError(..) => Some(1),
+                _ => Some(2),
+            }
+        }
+    }
+}
+
+fn handle_backup(path: &str) -> Option<i32> {
+    if !Path::new(&utils::database_file()).exists() {
+        eprintln!("{}", DBError::FileNotExistErr);
+        return Some(2)
+    }
+    if Path::new(path).exists() {
+        eprintln!("error: `{}` already exists", path);
+        return Some(1)
+    }
+    let backed_up = Database::open().and_then(|db| db.backup_to(path));
+    match backed_up {
+        Ok(()) => {
+            println!("{}", Message::success(format!("Database backed up to `{}`.", path)));
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Some(2)
+        }
+    }
+}
+
+fn handle_restore(path: &str) -> Option<i32> {
+    println!("{}", Message::info(format!("Restoring database from `{}`.", path)));
+    if !Path::new(path).exists() {
+        eprintln!("error: `{}` does not exist", path);
+        return Some(1)
+    }
+    let restored = Database::open().and_then(|mut db| db.restore_from(path));
+    match restored {
+        Ok(()) => {
+            println!("{}", Message::success("Database restored."));
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Your database was left as it was.");
+            Some(2)
+        }
     }
-    Some(0)
 }
 
-fn handle_reset() -> Option<i32> {
+fn handle_reset(wipe_everything: bool) -> Option<i32> {
+    if wipe_everything {
+        println!("{}", Message::set(
+            "This deletes your listening history as well. Continue? [y/N]", 
+            MessageKind::Hint
+        ));
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)
+            .expect("Oops, could not read line.");
+        if !matches!(answer.to_lowercase().trim(), "y" | "yes") {
+            println!("Nothing was deleted.");
+            return Some(1)
+        }
+    }
     println!("{}", Message::set("Resetting database.", MessageKind::Info));
-    match purge::complete() {
+    let db = match Database::open() {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return Some(2)
+        }
+    };
+    let purged = if wipe_everything {
+        purge::complete(&db)
+    } else {
+        purge::discogs(&db)
+    };
+    match purged {
         Ok(_) => {
             println!("Database purged. Pulling data from Discogs...");
-            match update::full(&CONFIG.user.username, &CONFIG.user.token, true, false) {
-                Ok(()) => {}
+            let auth = Auth::from_config(&CONFIG);
+            match update::full(&db, &CONFIG.user.username, &auth, true, false) {
+                Ok(_) => {}
                 Err(e) => {
                     println!("\n{}", e);
                     if let UpdateError::DBWriteError(_) = e {
                         db_error_msg();
                     }
-                    std::fs::remove_file(utils::database_file()).unwrap();
                     return Some(2)
                 }
             }
@@ -619,23 +1099,21 @@ fn handle_reset() -> Option<i32> {
 
 fn handle_check() -> Option<i32> {
     println!("{}", Message::info("Performing database integrity check."));
-    match admin::check_integrity() {
-        Ok(_) => {
-            println!("{}", Message::success("No errors found."));
-            Some(0)
-        }
+    //* opening the file would create it, so there'd be nothing to check
+    if !Path::new(&utils::database_file()).exists() {
+        eprintln!("{}", DBError::FileNotExistErr);
+        return Some(2)
+    }
+    let db = match Database::open() {
+        Ok(db) => db,
         Err(e) => {
             eprintln!("error: {}", e);
-            Some(2)
+            return Some(2)
         }
-    }
-}
-
-fn handle_orphans() -> Option<i32> {
-    println!("{}", Message::info("Removing orphan tables."));
-    match admin::remove_orphans() {
+    };
+    match admin::check_integrity(&db) {
         Ok(_) => {
-            println!("{}", Message::success("Removal successful."));
+            println!("{}", Message::success("No errors found."));
             Some(0)
         }
         Err(e) => {
@@ -1,13 +1,12 @@
 use std::path::Path;
 
 use csv::{ReaderBuilder, StringRecord};
-use unidecode::unidecode;
 use chrono::{DateTime, Utc};
 
 use crate::app::{
     request::UpdateError, 
-    database::update::*,
-    Release, Folders
+    database::{Database, update::SyncSummary},
+    Release, Folders, Note,
 };
 use crate::utils;
 use crate::CONFIG;
@@ -65,12 +64,12 @@ impl Release {
     pub fn from_collection_sr(record: &StringRecord) -> Result<Self, UpdateError> {
         Ok(Self {
             id: ok_or!(record, RELEASE_ID)?.parse()?,
-            search_string: unidecode(ok_or!(record, TITLE)?)
-            .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], ""),
+            search_string: utils::search_string(ok_or!(record, TITLE)?),
             title: ok_or!(record, TITLE)?.to_string(),
             artist: ok_or!(record, ARTIST)?.to_string(),
             year: ok_or!(record, RELEASED)?.parse()?,
             labels: vecify(ok_or!(record, LABEL)?),
+            catnos: vecify(ok_or!(record, CATALOG)?),
             formats: vecify(ok_or!(record, FORMAT)?),
             date_added: {
                 let added_date = DateTime::parse_from_rfc3339(
@@ -81,19 +80,36 @@ impl Release {
                 DateTime::<Utc>::from_utc(
                     added_date.naive_utc(), Utc
                 )
-            }
+            },
+            instance_id: None,
+            rating: rating(record),
+            //* the CSV export doesn't have these
+            genres: Vec::new(),
+            styles: Vec::new(),
+            master_id: None,
+            thumb: String::new(),
+            cover_image: String::new(),
+            notes: [
+                (Note::MEDIA_CONDITION, MEDIA_COND),
+                (Note::SLEEVE_CONDITION, SLEEVE_COND),
+                (Note::NOTES, COLL_NOTES),
+            ].iter()
+                .filter_map(|(field_id, idx)| record.get(*idx)
+                    .filter(|value| !value.trim().is_empty())
+                    .map(|value| Note::new(*field_id, value.trim().to_string())))
+                .collect(),
         })
     }
 
     pub fn from_wantlist_sr(record: &StringRecord) -> Result<Self, UpdateError> {
         Ok(Self {
             id: ok_or!(record, RELEASE_ID)?.parse()?,
-            search_string: unidecode(ok_or!(record, TITLE)?)
-            .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], ""),
+            search_string: utils::search_string(ok_or!(record, TITLE)?),
             title: ok_or!(record, TITLE)?.to_string(),
             artist: ok_or!(record, ARTIST)?.to_string(),
             year: ok_or!(record, RELEASED)?.parse()?,
             labels: vecify(ok_or!(record, LABEL)?),
+            catnos: vecify(ok_or!(record, CATALOG)?),
             formats: vecify(ok_or!(record, FORMAT)?),
             date_added: {
                 let added_date = DateTime::parse_from_rfc3339(
@@ -105,36 +121,75 @@ impl Release {
                 DateTime::<Utc>::from_utc(
                     added_date.naive_utc(), Utc
                 )
-            }
+            },
+            instance_id: None,
+            rating: rating(record),
+            //* the CSV export doesn't have these
+            genres: Vec::new(),
+            styles: Vec::new(),
+            master_id: None,
+            thumb: String::new(),
+            cover_image: String::new(),
+            notes: Vec::new(),
         })
     }
 }
 
-pub fn full_update<P: AsRef<Path>>(wantlist: P, collection: P) -> Result<(), UpdateError> {
-    update_coll(collection)?;
-    update_want(wantlist)?;
+//* custom fields come after the standard columns, named "Collection <field>"
+fn custom_notes(headers: &StringRecord, record: &StringRecord) -> Vec<Note> {
+    headers.iter().zip(record.iter())
+        .skip(COLL_NOTES + 1)
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(header, value)| Note {
+            field_id: None,
+            field: header.strip_prefix("Collection ").unwrap_or(header).to_string(),
+            value: value.trim().to_string(),
+        })
+        .collect()
+}
 
-    Ok(())
+//* blank for releases that aren't rated
+fn rating(record: &StringRecord) -> u8 {
+    record.get(RATING)
+        .and_then(|rating| rating.trim().parse::<u8>().ok())
+        .map(|rating| rating.min(5))
+        .unwrap_or(0)
+}
+
+pub fn full_update<P: AsRef<Path>>(db: &Database, wantlist: P, collection: P) -> Result<SyncSummary, UpdateError> {
+    let collection = parse_collection_csv(collection)?;
+    let wantlist = parse_wantlist_csv(wantlist)?;
+
+    db.snapshot()?;
+    let mut tx = db.begin()?;
+
+    let summary = tx.update_collection(collection)?
+        .merge(tx.update_wantlist(wantlist)?);
+    tx.commit()?;
+
+    Ok(summary)
 }
 
-pub fn update_coll<P: AsRef<Path>>(path: P) -> Result<(), UpdateError> {
+pub fn update_coll<P: AsRef<Path>>(db: &Database, path: P) -> Result<SyncSummary, UpdateError> {
     let collection = parse_collection_csv(path)?;
 
-    let mut handle = DBHandle::new()?;
+    let mut tx = db.begin()?;
 
-    handle.update_collection(collection)?;
+    let summary = tx.update_collection(collection)?;
+    tx.commit()?;
 
-    Ok(())
+    Ok(summary)
 }
 
-pub fn update_want<P: AsRef<Path>>(path: P) -> Result<(), UpdateError> {
+pub fn update_want<P: AsRef<Path>>(db: &Database, path: P) -> Result<SyncSummary, UpdateError> {
     let wantlist = parse_wantlist_csv(path)?;
 
-    let mut handle = DBHandle::new()?;
+    let mut tx = db.begin()?;
 
-    handle.update_wantlist(wantlist)?;
+    let summary = tx.update_wantlist(wantlist)?;
+    tx.commit()?;
 
-    Ok(())
+    Ok(summary)
 }
 
 pub fn parse_collection_csv<P: AsRef<Path>>(path: P) -> Result<Folders, UpdateError> {
@@ -142,12 +197,14 @@ pub fn parse_collection_csv<P: AsRef<Path>>(path: P) -> Result<Folders, UpdateEr
         .flexible(true)
         .from_path(path)?;
 
-    validate_coll_headers(reader.headers()?)?;
+    let headers = reader.headers()?.clone();
+    validate_coll_headers(&headers)?;
 
     let mut ret = Folders::new();
     for record in reader.records() {
         let record = record?;
-        let release = Release::from_collection_sr(&record)?;
+        let mut release = Release::from_collection_sr(&record)?;
+        release.notes.extend(custom_notes(&headers, &record));
         // insert into its own folder
         ret.insert(
             record.get(COL_FOLDER)
@@ -220,32 +277,65 @@ fn vecify(s: &str) -> Vec<String> {
 mod tests {
     use super::*;
     use csv::Reader;
+    use crate::app::database::{admin, query::{self, SortOrder}};
+
+    const COLLECTION: &str = "tests/fixtures/csv/collection.csv";
+    const WANTLIST: &str = "tests/fixtures/csv/wantlist.csv";
 
     #[test]
     fn test_csv_header_validation() {
-        let mut reader = Reader::from_path("discogs_collection.csv").unwrap();
+        let mut reader = Reader::from_path(COLLECTION).unwrap();
 
         validate_coll_headers(reader.headers().unwrap()).unwrap();
 
-        let mut reader = Reader::from_path("discogs_wantlist.csv").unwrap();
+        let mut reader = Reader::from_path(WANTLIST).unwrap();
 
         validate_want_headers(reader.headers().unwrap()).unwrap();
     }
 
     #[test]
     fn test_csv_parsing() {
+        let csv_folders = parse_collection_csv(COLLECTION).unwrap();
 
-        let csv_folders = parse_collection_csv("discogs_collection.csv").unwrap();
-        
-        println!("{:#?}", csv_folders);
+        assert_eq!(csv_folders.contents["All"].len(), 2);
+        assert_eq!(csv_folders.contents["Electronic"][0].id, 1296390);
 
-        let csv_wantlist = parse_wantlist_csv("discogs_wantlist.csv").unwrap();
+        let csv_wantlist = parse_wantlist_csv(WANTLIST).unwrap();
 
-        println!("{:#?}", csv_wantlist);
+        assert_eq!(csv_wantlist.len(), 1);
+        assert_eq!(csv_wantlist[0].title, "Selected Ambient Works Volume II");
     }
 
     #[test]
     fn test_csv_full_update() {
-        full_update("discogs_wantlist.csv", "discogs_collection.csv").unwrap();
+        let db = Database::open_path(":memory:").unwrap();
+        admin::init_db(&db).unwrap();
+
+        let summary = full_update(&db, WANTLIST, COLLECTION).unwrap();
+        assert_eq!(summary.added, 3);
+
+        let collection = query::collection(&db, SortOrder::Id).unwrap();
+        assert_eq!(collection.contents["All"].len(), 2);
+        assert_eq!(collection.contents["Uncategorized"][0].title, "Amber");
+        // a blank rating is no rating
+        assert_eq!(collection.contents["Uncategorized"][0].rating, 0);
+        assert_eq!(collection.contents["Electronic"][0].rating, 5);
+        // conditions and notes, with blank ones left out and custom fields by name
+        let notes = |release: &Release| -> Vec<(Option<i64>, String, String)> {
+            release.notes.iter()
+                .map(|note| (note.field_id, note.field.clone(), note.value.clone()))
+                .collect()
+        };
+        assert_eq!(notes(&collection.contents["Electronic"][0]), vec![
+            (Some(1), String::from("Media Condition"), String::from("Near Mint (NM or M-)")),
+            (Some(2), String::from("Sleeve Condition"), String::from("Very Good Plus (VG+)")),
+            (None, String::from("Shelf"), String::from("B3")),
+        ]);
+        assert_eq!(notes(&collection.contents["Uncategorized"][0]), vec![
+            (Some(1), String::from("Media Condition"), String::from("Mint (M)")),
+            (Some(2), String::from("Sleeve Condition"), String::from("Mint (M)")),
+            (Some(3), String::from("Notes"), String::from("first press")),
+        ]);
+        assert_eq!(query::wantlist(&db, SortOrder::Id).unwrap().len(), 1);
     }
-}
\ No newline at end of file
+}
@@ -1,22 +1,59 @@
 /*
-*the database API exposes four main modules:
+*the database API exposes six main modules:
 *   mod admin: contains functions that administer the database
-*       check_integrity(), init_db(), init_folders()
-*       WARNING: ORPHAN TABLES ARE A DEFINITE POSSIBILITY.
+*       check_integrity(), init_db()
+*   mod migrate: brings the schema up to date, keyed on `user_version`
+*       every schema change gets a new migration, never an edit
+*       to an old one
 *   mod update: called by the request module query() to load in data
-*       also exposes functions that update userid and token
-*       and log listening history
+*       only the releases that changed since the last sync are written
+*       each sync is a single transaction, committed after an integrity check
+*       also logs listening history
 *   mod query: queries from the local database
+*       release searches go through an FTS5 index, best match first
 *       every query returns an iterator or indexable set of iterators
 *       wrapped in a custom type (Folders, Release)
+*   mod backup: consistent copies of the database through SQLite's backup API
+*       backups, restores, and the snapshots taken before anything destructive
 *   mod purge: deleting stuff from the database
-*       folders(): systematically deleting collection folders
-*       table(): clears a specified folder
+*       discogs(): drops everything pulled from Discogs, keeps listenlog
 *       complete(): yeets the entire fucking database
 *       (think sudo rm -rf)
 */
 
-use crate::app::request::UpdateError;
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+
+use crate::app::{Note, request::UpdateError};
+use crate::utils;
+
+/// The one connection to the database, opened at startup and shared 
+/// by everything that reads or writes it. Statements go through 
+/// `prepare_cached`, so the ones that run repeatedly are only compiled once.
+#[derive(Debug)]
+pub struct Database {
+    conn: Connection,
+    //* None for in-memory databases, which have nowhere to put snapshots
+    path: Option<PathBuf>,
+}
+
+impl Database {
+    /// Opens the user's database file, creating it if it isn't there.
+    pub fn open() -> Result<Self, DBError> {
+        Self::open_path(utils::database_file())
+    }
+
+    /// Opens the database at `path`. 
+    /// `:memory:` gives a private in-memory database that is gone once dropped.
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Self, DBError> {
+        let path = path.as_ref();
+        Ok(Database {
+            conn: Connection::open(path)?,
+            path: if path == Path::new(":memory:") {None} else {Some(path.to_path_buf())},
+        })
+    }
+}
 
 impl From<rusqlite::Error> for UpdateError {
     fn from(error: rusqlite::Error) -> Self {
@@ -30,11 +67,30 @@ impl From<DBError> for UpdateError {
     }
 }
 
+//* the notes on a copy are kept in one column as JSON, null if there aren't any
+fn notes_to_sql(notes: &[Note]) -> Option<String> {
+    if notes.is_empty() {
+        None
+    } else {
+        serde_json::to_string(notes).ok()
+    }
+}
+
+fn notes_from_sql(raw: Option<String>) -> rusqlite::Result<Vec<Note>> {
+    match raw {
+        Some(raw) => serde_json::from_str(&raw).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+        }),
+        None => Ok(Vec::new()),
+    }
+}
+
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum DBError {
     SQliteErr(String),
-    OrphanTableErr,
     FileNotExistErr,
+    SchemaVersionErr(u32),
     OtherErr(String)
 }
 
@@ -52,12 +108,15 @@ impl std::fmt::Display for DBError {
             Self::SQliteErr(s) => {
                 write!(f, "{}", s)
             }
-            Self::OrphanTableErr => {
-                write!(f, "error: orphan tables detected")
-            }
             Self::FileNotExistErr => {
                 write!(f, "error: no such database file")
             }
+            Self::SchemaVersionErr(v) => {
+                write!(f,
+                    "error: database schema version {} is newer than this version of cogsy supports ({})",
+                    v, migrate::SCHEMA_VERSION
+                )
+            }
             Self::OtherErr(s) => {
                 write!(f, "{}", s)
             }
@@ -71,18 +130,137 @@ impl From<rusqlite::Error> for DBError {
     }
 }
 
+impl From<std::io::Error> for DBError {
+    fn from(error: std::io::Error) -> Self {
+        DBError::OtherErr(error.to_string())
+    }
+}
+
 pub mod admin {
     use rusqlite::{
         Connection,
         NO_PARAMS,
     };
+    use super::{Database, DBError, migrate};
+
+    pub fn init_db(db: &Database) -> Result<(), DBError> {
+        migrate::run(&db.conn)
+    }
+
+    pub fn check_integrity(db: &Database) -> Result<(), DBError> {
+        check_integrity_of(&db.conn)
+    }
+
+    /// Runs the integrity check on an open connection, 
+    /// which may be in the middle of a transaction.
+    pub fn check_integrity_of(conn: &Connection) -> Result<(), DBError> {
+        let version = migrate::version(conn)?;
+        if version != migrate::SCHEMA_VERSION {
+            return Err(format!(
+                "schema is at version {}, expected {}", 
+                version, migrate::SCHEMA_VERSION
+            ).as_str().into())
+        }
+        for table in &[
+            "profile", "wantlist", "listenlog", "folders", 
+            "releases", "folder_items", "releases_fts", "wantlist_fts",
+            "details", "tracks", "credits", "identifiers", "collection_value",
+            "purchases",
+        ] {
+            match conn.prepare(&format!("SELECT * FROM {};", table)) {
+                Ok(_) => {},
+                Err(e) => return Err(format!("{}: {}", table, e).as_str().into())
+            }
+        }
+        let dangling: u32 = conn.query_row(
+            "SELECT COUNT(*) FROM folder_items
+            WHERE release_id NOT IN (SELECT id FROM releases)
+            OR folder NOT IN (SELECT name FROM folders);",
+            NO_PARAMS,
+            |row| row.get(0)
+        )?;
+        if dangling > 0 {
+            return Err(format!(
+                "folder_items: {} item(s) refer to a missing release or folder", 
+                dangling
+            ).as_str().into())
+        }
+        Ok(())
+    }
+}
+
+pub mod migrate {
+    use rusqlite::{
+        Connection,
+        NO_PARAMS,
+        params,
+    };
     use super::DBError;
-    use crate::utils;
 
-    pub fn init_db() -> Result<(), DBError> {
-        let conn = Connection::open(utils::database_file())?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS profile (
+    /*
+    Migration n (counting from 1) takes the database from version n - 1
+    to version n. Version 0 is either a brand new file or a database
+    created before migrations existed, so the first migrations have to cope
+    with tables that may already be there.
+    Never edit a migration once it has shipped; add a new one instead.
+    */
+    type Migration = fn(&Connection) -> Result<(), DBError>;
+
+    const MIGRATIONS: &[Migration] = &[
+        v1_initial,
+        v2_normalized_collection,
+        v3_search_index,
+        v4_discogs_ids,
+        v5_ratings,
+        v6_release_details,
+        v7_instance_notes,
+        v8_details_cache,
+        v9_collection_value,
+        v10_purchases,
+        v11_purchase_ids,
+        v12_purchase_amounts,
+    ];
+
+    /// The schema version this build of cogsy expects.
+    pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+    /// Reads the schema version stored in the database header.
+    pub fn version(conn: &Connection) -> Result<u32, DBError> {
+        Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    /// Upgrades the database in place to `SCHEMA_VERSION`.
+    pub fn run(conn: &Connection) -> Result<(), DBError> {
+        upgrade(conn, SCHEMA_VERSION)
+    }
+
+    // each migration runs in its own transaction together with the
+    // version bump, so a failure leaves the database at the last good version
+    pub(super) fn upgrade(conn: &Connection, target: u32) -> Result<(), DBError> {
+        let current = version(conn)?;
+        if current > SCHEMA_VERSION {
+            return Err(DBError::SchemaVersionErr(current))
+        }
+        for (idx, migration) in MIGRATIONS.iter()
+            .enumerate()
+            .take(target as usize)
+            .skip(current as usize) {
+            let tx = conn.unchecked_transaction()?;
+            migration(&tx).map_err(|e| {
+                DBError::OtherErr(format!("migration to version {} failed: {}", idx + 1, e))
+            })?;
+            tx.pragma_update(None, "user_version", &(idx as u32 + 1))?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // the schema as it was before migrations were introduced.
+    // a database from back then already has these tables and keeps them,
+    // but only if they're laid out the way the later migrations expect
+    fn v1_initial(conn: &Connection) -> Result<(), DBError> {
+        let tables = [
+            ("profile", "
                 username TEXT PRIMARY KEY,
                 real_name TEXT,
                 registered TEXT,
@@ -91,12 +269,8 @@ pub mod admin {
                 wantlist INTEGER,
                 rated INTEGER,
                 average_rating REAL
-            )",
-            NO_PARAMS
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS wantlist (
+            "),
+            ("wantlist", "
                 idx INTEGER PRIMARY KEY,
                 hash INTEGER,
                 id INTEGER,
@@ -107,202 +281,689 @@ pub mod admin {
                 labels TEXT,
                 formats TEXT,
                 date_added TEXT
-            )",
-            NO_PARAMS
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS folders (
+            "),
+            ("folders", "
                 name TEXT PRIMARY KEY
-            )",
-            NO_PARAMS
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS listenlog (
+            "),
+            ("listenlog", "
                 datetime TEXT PRIMARY KEY,
                 album_id INTEGER,
                 title TEXT
-            )",
-            NO_PARAMS
-        )?;
-
+            "),
+        ];
+
+        for (name, columns) in &tables {
+            let existing = columns_of(conn, "main", name)?;
+            if existing.is_empty() {
+                conn.execute(&format!("CREATE TABLE {} ({})", name, columns), NO_PARAMS)?;
+                continue
+            }
+            //* the expected columns are read back the same way, so spacing and case don't matter
+            conn.execute(&format!("CREATE TEMP TABLE v1_expected ({})", columns), NO_PARAMS)?;
+            let expected = columns_of(conn, "temp", "v1_expected")?;
+            conn.execute("DROP TABLE temp.v1_expected", NO_PARAMS)?;
+            if existing != expected {
+                return Err(DBError::OtherErr(format!(
+                    "table `{}` doesn't have the columns cogsy expects", name
+                )))
+            }
+        }
         Ok(())
     }
 
-    pub fn init_folder(foldername: String, conn: &Connection) 
-        -> Result <(), DBError> {
-        let sqlcommand = format!(
-            "CREATE TABLE IF NOT EXISTS \"{}\" (
-                idx INTEGER PRIMARY KEY,
+    // the name, type, NOT NULL and primary key position of each column,
+    // empty if there's no such table
+    fn columns_of(conn: &Connection, schema: &str, table: &str) -> Result<Vec<(String, String, bool, u32)>, DBError> {
+        let mut stmt = conn.prepare(
+            "SELECT name, type, \"notnull\", pk FROM pragma_table_info(?1, ?2) ORDER BY cid;"
+        )?;
+        let columns = stmt.query_map(params![table, schema], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?.collect::<Result<_, _>>()?;
+        Ok(columns)
+    }
+
+    // replaces the one-table-per-folder layout with a single releases table
+    // and a folder membership table, so orphan tables can no longer happen
+    fn v2_normalized_collection(conn: &Connection) -> Result<(), DBError> {
+        conn.execute_batch(
+            "CREATE TABLE releases (
+                id INTEGER PRIMARY KEY,
                 hash INTEGER,
-                id INTEGER,
                 search_string TEXT,
                 title TEXT NOT NULL,
                 artist TEXT,
                 year INTEGER,
                 labels TEXT,
-                formats TEXT,
+                formats TEXT
+            );
+            CREATE TABLE folder_items (
+                idx INTEGER PRIMARY KEY,
+                folder TEXT NOT NULL REFERENCES folders(name),
+                release_id INTEGER NOT NULL REFERENCES releases(id),
                 date_added TEXT
-            )", foldername);
+            );
+            CREATE INDEX folder_items_folder ON folder_items(folder);"
+        )?;
+
+        let mut folder_names: Vec<String> = Vec::new();
+        {
+            let mut stmt = conn.prepare("SELECT name FROM folders;")?;
+            for folder in stmt.query_map(NO_PARAMS, |row| row.get(0))? {
+                folder_names.push(folder?);
+            }
+        }
+        let legacy_tables = {
+            let mut stmt = conn.prepare("SELECT name FROM sqlite_master
+            WHERE type = 'table'
+            AND name NOT LIKE 'sqlite_%'
+            AND name NOT IN 
+            ('profile', 'wantlist', 'folders', 'listenlog', 'releases', 'folder_items');")?;
+            let mut tables: Vec<String> = Vec::new();
+            for table in stmt.query_map(NO_PARAMS, |row| row.get(0))? {
+                tables.push(table?);
+            }
+            tables
+        };
 
-        conn.execute(&sqlcommand, NO_PARAMS)?;
+        conn.execute("DELETE FROM folders;", NO_PARAMS)?;
+        for table in &legacy_tables {
+            // tables without an entry in `folders` were orphans, drop them unread
+            if folder_names.contains(table) {
+                let name = table.strip_suffix('_').unwrap_or(table);
+                conn.execute("INSERT OR IGNORE INTO folders (name) VALUES (?1);", &[name])?;
+                conn.execute(&format!(
+                    "INSERT OR IGNORE INTO releases
+                    (id, hash, search_string, title, artist, year, labels, formats)
+                    SELECT id, hash, search_string, title, artist, year, labels, formats
+                    FROM \"{}\" ORDER BY idx;", table), NO_PARAMS
+                )?;
+                conn.execute(&format!(
+                    "INSERT INTO folder_items (folder, release_id, date_added)
+                    SELECT ?1, id, date_added FROM \"{}\" ORDER BY idx;", table), &[name]
+                )?;
+            }
+            conn.execute(&format!("DROP TABLE \"{}\";", table), NO_PARAMS)?;
+        }
         Ok(())
     }
 
-    //TODO: Account for orphan folder tables
-    pub fn check_integrity() -> Result<(), DBError> {
-        match Connection::open(utils::database_file()) {
-            Ok(conn) => {
-                match conn.prepare("SELECT * FROM profile;") {
-                    Ok(_) => {},
-                    Err(e) => return Err(format!("profile: {}", e).as_str().into())
-                }
-                match conn.prepare("SELECT * FROM wantlist;") {
-                    Ok(_) => {},
-                    Err(e) => return Err(format!("wantlist: {}", e).as_str().into())
-                }
-                match conn.prepare("SELECT * FROM listenlog;") {
-                    Ok(_) => {},
-                    Err(e) => return Err(format!("listenlog: {}", e).as_str().into())
-                }
-                match conn.prepare("SELECT * FROM folders;") {
-                    Ok(mut stmt) => {
-                        let collection_check = stmt.query_map(
-                            NO_PARAMS, 
-                            |row| row.get(0))?;
-                        let mut folder_names: Vec<String> = Vec::new();
-                        for folder in collection_check {
-                            folder_names.push(folder?);
-                        }
-                        for folder in &folder_names {
-                            let sqlcommand = format!("SELECT * FROM \"{}\"", folder);
-                            match conn.prepare(&sqlcommand) {
-                                Ok(_) => {},
-                                Err(e) => return Err(format!("{}: {}", folder, e).as_str().into())
-                            }
-                        }
-                        check_orphans(&conn, &folder_names)?;
+    // adds catalogue numbers and full-text indexes over the releases
+    // and the wantlist; triggers keep the indexes in step with the tables.
+    // search_string is indexed too, so `dont` still finds `Don't`.
+    // the default ranking is weighted towards title and artist matches
+    fn v3_search_index(conn: &Connection) -> Result<(), DBError> {
+        conn.execute_batch(
+            "ALTER TABLE releases ADD COLUMN catnos TEXT;
+            ALTER TABLE wantlist ADD COLUMN catnos TEXT;"
+        )?;
+        for (table, rowid) in &[("releases", "id"), ("wantlist", "idx")] {
+            conn.execute_batch(&format!(
+                "CREATE VIRTUAL TABLE {table}_fts USING fts5(
+                    title, artist, labels, formats, catnos, search_string,
+                    content='{table}', content_rowid='{rowid}'
+                );
+                CREATE TRIGGER {table}_fts_insert AFTER INSERT ON {table} BEGIN
+                    INSERT INTO {table}_fts (rowid, title, artist, labels, formats, catnos, search_string)
+                    VALUES (new.{rowid}, new.title, new.artist, new.labels, new.formats, new.catnos, new.search_string);
+                END;
+                CREATE TRIGGER {table}_fts_delete AFTER DELETE ON {table} BEGIN
+                    INSERT INTO {table}_fts ({table}_fts, rowid, title, artist, labels, formats, catnos, search_string)
+                    VALUES ('delete', old.{rowid}, old.title, old.artist, old.labels, old.formats, old.catnos, old.search_string);
+                END;
+                CREATE TRIGGER {table}_fts_update AFTER UPDATE ON {table} BEGIN
+                    INSERT INTO {table}_fts ({table}_fts, rowid, title, artist, labels, formats, catnos, search_string)
+                    VALUES ('delete', old.{rowid}, old.title, old.artist, old.labels, old.formats, old.catnos, old.search_string);
+                    INSERT INTO {table}_fts (rowid, title, artist, labels, formats, catnos, search_string)
+                    VALUES (new.{rowid}, new.title, new.artist, new.labels, new.formats, new.catnos, new.search_string);
+                END;
+                INSERT INTO {table}_fts ({table}_fts, rank) 
+                VALUES ('rank', 'bm25(10.0, 5.0, 2.0, 1.0, 2.0, 10.0)');
+                INSERT INTO {table}_fts ({table}_fts) VALUES ('rebuild');",
+                table = table, rowid = rowid
+            ))?;
+        }
+        Ok(())
+    }
 
-                    }
-                    Err(e) => return Err(format!("folders: {}", e).as_str().into())
-                }
-                Ok(())
-            },
-            Err(_) => Err(DBError::FileNotExistErr)
+    // keeps the Discogs IDs of folders and of each copy in them, which
+    // changing the collection through the API needs. both stay null
+    // until the next sync fills them in
+    fn v4_discogs_ids(conn: &Connection) -> Result<(), DBError> {
+        conn.execute_batch(
+            "ALTER TABLE folders ADD COLUMN id INTEGER;
+            ALTER TABLE folder_items ADD COLUMN instance_id INTEGER;
+            CREATE INDEX folder_items_instance ON folder_items(instance_id);"
+        )?;
+        Ok(())
+    }
+
+    // a rating per release, 0 for unrated
+    fn v5_ratings(conn: &Connection) -> Result<(), DBError> {
+        conn.execute(
+            "ALTER TABLE releases ADD COLUMN rating INTEGER NOT NULL DEFAULT 0;",
+            NO_PARAMS
+        )?;
+        Ok(())
+    }
+
+    // genres, styles, the master release and cover art, for the
+    // collection and the wantlist alike; old rows get them on the next sync
+    fn v6_release_details(conn: &Connection) -> Result<(), DBError> {
+        for table in &["releases", "wantlist"] {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {0} ADD COLUMN genres TEXT;
+                ALTER TABLE {0} ADD COLUMN styles TEXT;
+                ALTER TABLE {0} ADD COLUMN master_id INTEGER;
+                ALTER TABLE {0} ADD COLUMN thumb TEXT;
+                ALTER TABLE {0} ADD COLUMN cover_image TEXT;",
+                table
+            ))?;
         }
+        Ok(())
+    }
+
+    // notes and conditions per copy, which is what a folder entry is
+    fn v7_instance_notes(conn: &Connection) -> Result<(), DBError> {
+        conn.execute_batch("ALTER TABLE folder_items ADD COLUMN notes TEXT;")?;
+        Ok(())
+    }
+
+    // full release details from /releases/{id}, fetched one release at a time
+    // when they're asked for, so they can be read again without a connection
+    fn v8_details_cache(conn: &Connection) -> Result<(), DBError> {
+        conn.execute_batch(
+            "CREATE TABLE details (
+                release_id INTEGER PRIMARY KEY,
+                country TEXT,
+                released TEXT,
+                notes TEXT,
+                fetched TEXT NOT NULL
+            );
+            CREATE TABLE tracks (
+                release_id INTEGER NOT NULL REFERENCES details(release_id),
+                idx INTEGER NOT NULL,
+                position TEXT,
+                title TEXT NOT NULL,
+                duration TEXT,
+                heading INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (release_id, idx)
+            );
+            CREATE TABLE credits (
+                release_id INTEGER NOT NULL REFERENCES details(release_id),
+                idx INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                role TEXT,
+                tracks TEXT,
+                PRIMARY KEY (release_id, idx)
+            );
+            CREATE TABLE identifiers (
+                release_id INTEGER NOT NULL REFERENCES details(release_id),
+                idx INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                value TEXT NOT NULL,
+                description TEXT,
+                PRIMARY KEY (release_id, idx)
+            );"
+        )?;
+        Ok(())
+    }
+
+    // one row per sync; Discogs only knows the current value, so the
+    // history is local and survives a reset like the listening log does
+    fn v9_collection_value(conn: &Connection) -> Result<(), DBError> {
+        conn.execute(
+            "CREATE TABLE collection_value (
+                fetched TEXT PRIMARY KEY,
+                minimum TEXT NOT NULL,
+                median TEXT NOT NULL,
+                maximum TEXT NOT NULL
+            )",
+            NO_PARAMS
+        )?;
+        Ok(())
+    }
+
+    // what was paid for each release; local like the value history.
+    // no reference to releases, a purchase outlives the release leaving the collection
+    fn v10_purchases(conn: &Connection) -> Result<(), DBError> {
+        conn.execute(
+            "CREATE TABLE purchases (
+                release_id INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                amount REAL NOT NULL,
+                currency TEXT NOT NULL,
+                store TEXT,
+                PRIMARY KEY (release_id, date)
+            )",
+            NO_PARAMS
+        )?;
+        Ok(())
+    }
+
+    // purchases get a key of their own: two copies bought on the same day
+    // are two purchases, not the same one recorded twice
+    fn v11_purchase_ids(conn: &Connection) -> Result<(), DBError> {
+        conn.execute_batch(
+            "CREATE TABLE purchases_new (
+                id INTEGER PRIMARY KEY,
+                release_id INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                amount REAL NOT NULL,
+                currency TEXT NOT NULL,
+                store TEXT
+            );
+            INSERT INTO purchases_new (release_id, date, amount, currency, store)
+                SELECT release_id, date, amount, currency, store FROM purchases ORDER BY rowid;
+            DROP TABLE purchases;
+            ALTER TABLE purchases_new RENAME TO purchases;
+            CREATE INDEX purchases_release ON purchases(release_id);"
+        )?;
+        Ok(())
+    }
+
+    // amounts become whole hundredths of the currency, so adding them up is exact
+    fn v12_purchase_amounts(conn: &Connection) -> Result<(), DBError> {
+        conn.execute_batch(
+            "CREATE TABLE purchases_new (
+                id INTEGER PRIMARY KEY,
+                release_id INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                amount INTEGER NOT NULL,
+                currency TEXT NOT NULL,
+                store TEXT
+            );
+            INSERT INTO purchases_new (id, release_id, date, amount, currency, store)
+                SELECT id, release_id, date, CAST(ROUND(amount * 100) AS INTEGER), currency, store
+                FROM purchases;
+            DROP TABLE purchases;
+            ALTER TABLE purchases_new RENAME TO purchases;
+            CREATE INDEX purchases_release ON purchases(release_id);"
+        )?;
+        Ok(())
     }
 
-    fn check_orphans(conn: &Connection, folders: &[String]) -> Result<(), DBError> {
-        let mut stmt = conn.prepare("SELECT name FROM sqlite_master
-        WHERE type ='table'
-        AND name NOT LIKE 'sqlite_%';")?;
+    #[cfg(test)]
+    mod tests {
+        use super::*;
 
-        let mut tables: Vec<String> = Vec::new();
-        for table in stmt.query_map(NO_PARAMS, |row| row.get(0))? {
-            tables.push(table?);
+        /// Builds an in-memory database from a fixture of an older schema.
+        fn fixture(sql: &str) -> Connection {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(sql).unwrap();
+            conn
         }
-        if 
-        !(tables.contains(&"profile".into()) &&
-        tables.contains(&"wantlist".into()) &&
-        tables.contains(&"folders".into()) &&
-        tables.contains(&"listenlog".into())){
-            return Err("Integrity check: Missing core table(s)".into())
+
+        fn count(conn: &Connection, table: &str) -> u32 {
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM \"{}\"", table),
+                NO_PARAMS,
+                |row| row.get(0)
+            ).unwrap()
         }
-        if tables.len() - 4 != folders.len() {
-            return Err(DBError::OrphanTableErr)
+
+        fn matches(conn: &Connection, table: &str, query: &str) -> Vec<i64> {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT rowid FROM {0} WHERE {0} MATCH ?1 ORDER BY rowid;", table
+            )).unwrap();
+            stmt.query_map(&[query], |row| row.get(0))
+                .unwrap().map(|r| r.unwrap()).collect()
         }
-        Ok(())
-    }
 
-    // this was so cobbled together but i really can't be arsed to fix it.
-    pub fn remove_orphans() -> Result<(), DBError> {
-        let conn = Connection::open(utils::database_file())?;
-        let folders = { 
-            let mut stmt = conn.prepare("SELECT * FROM folders;")?;
-            
-            let collection_check = stmt.query_map(
-                NO_PARAMS, 
-                |row| row.get(0))?;
-            let mut folder_names: Vec<String> = Vec::new();
-            for folder in collection_check {
-                folder_names.push(folder?);
-            }
-            for folder in &folder_names {
-                let sqlcommand = format!("SELECT * FROM \"{}\"", folder);
-                match conn.prepare(&sqlcommand) {
-                    Ok(_) => {},
-                    Err(e) => return Err(format!("{}: {}", folder, e).as_str().into())
-                }
+        #[test]
+        fn test_v1_from_empty_file() {
+            let conn = Connection::open_in_memory().unwrap();
+            upgrade(&conn, 1).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 1);
+            for table in &["profile", "wantlist", "folders", "listenlog"] {
+                assert_eq!(count(&conn, table), 0);
             }
-            folder_names
-        };
-        let all_tables = {
-            let mut stmt = conn.prepare("SELECT name FROM sqlite_master
-            WHERE type ='table'
-            AND name NOT LIKE 'sqlite_%';")?;
+        }
 
-            let mut tables: Vec<String> = Vec::new();
-            for table in stmt.query_map(NO_PARAMS, |row| row.get(0))? {
-                tables.push(table?);
+        #[test]
+        fn test_v1_from_legacy_database() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v0.sql"));
+            assert_eq!(version(&conn).unwrap(), 0);
+
+            upgrade(&conn, 1).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 1);
+            assert_eq!(count(&conn, "profile"), 1);
+            assert_eq!(count(&conn, "wantlist"), 2);
+            assert_eq!(count(&conn, "folders"), 2);
+            assert_eq!(count(&conn, "All_"), 3);
+            assert_eq!(count(&conn, "listenlog"), 2);
+        }
+
+        #[test]
+        fn test_v1_rejects_a_table_it_cant_use() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute("CREATE TABLE listenlog (datetime TEXT, album TEXT)", NO_PARAMS).unwrap();
+
+            let e = upgrade(&conn, 1).unwrap_err();
+
+            assert!(e.to_string().contains("listenlog"));
+            assert_eq!(version(&conn).unwrap(), 0);
+            let tables: u32 = conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table';", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(tables, 1);
+        }
+
+        #[test]
+        fn test_v2_from_v1() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v1.sql"));
+            assert_eq!(version(&conn).unwrap(), 1);
+
+            upgrade(&conn, 2).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 2);
+            let mut stmt = conn.prepare("SELECT name FROM folders ORDER BY name;").unwrap();
+            let folders: Vec<String> = stmt.query_map(NO_PARAMS, |row| row.get(0))
+                .unwrap().map(|f| f.unwrap()).collect();
+            assert_eq!(folders, vec!["All", "Uncategorized"]);
+
+            // the duplicate copy collapses into one release but keeps its folder entry
+            assert_eq!(count(&conn, "releases"), 3);
+            assert_eq!(count(&conn, "folder_items"), 7);
+            let in_all: u32 = conn.query_row(
+                "SELECT COUNT(*) FROM folder_items WHERE folder = 'All' AND release_id = 1003;",
+                NO_PARAMS,
+                |row| row.get(0)
+            ).unwrap();
+            assert_eq!(in_all, 2);
+
+            let tables: u32 = conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'
+                AND name IN ('All_', 'Uncategorized_', 'Old Folder_');",
+                NO_PARAMS,
+                |row| row.get(0)
+            ).unwrap();
+            assert_eq!(tables, 0);
+            assert_eq!(count(&conn, "listenlog"), 2);
+            assert_eq!(count(&conn, "wantlist"), 2);
+        }
+
+        #[test]
+        fn test_v3_from_v2() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            assert_eq!(version(&conn).unwrap(), 2);
+
+            upgrade(&conn, 3).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 3);
+            // existing rows are indexed straight away
+            assert_eq!(matches(&conn, "releases_fts", "skam"), vec![1001]);
+            assert_eq!(matches(&conn, "releases_fts", "vinyl"), vec![1001, 1002]);
+            assert_eq!(matches(&conn, "wantlist_fts", "status"), vec![2]);
+
+            // and the triggers keep them up to date
+            conn.execute(
+                "UPDATE releases SET catnos = 'WARPLP55' WHERE id = 1001;", NO_PARAMS
+            ).unwrap();
+            conn.execute("DELETE FROM wantlist WHERE id = 8041;", NO_PARAMS).unwrap();
+            assert_eq!(matches(&conn, "releases_fts", "warplp55"), vec![1001]);
+            assert_eq!(matches(&conn, "releases_fts", "skam"), vec![1001]);
+            assert!(matches(&conn, "wantlist_fts", "status").is_empty());
+
+            assert_eq!(count(&conn, "folder_items"), 7);
+            assert_eq!(count(&conn, "listenlog"), 2);
+        }
+
+        #[test]
+        fn test_v4_from_v3() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 3).unwrap();
+
+            upgrade(&conn, 4).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 4);
+            let missing: u32 = conn.query_row(
+                "SELECT COUNT(*) FROM folder_items JOIN folders ON folders.name = folder_items.folder
+                WHERE folder_items.instance_id IS NULL AND folders.id IS NULL;",
+                NO_PARAMS,
+                |row| row.get(0)
+            ).unwrap();
+            assert_eq!(missing, 7);
+            assert_eq!(count(&conn, "releases"), 3);
+        }
+
+        #[test]
+        fn test_v5_from_v4() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 4).unwrap();
+
+            upgrade(&conn, 5).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 5);
+            let unrated: u32 = conn.query_row(
+                "SELECT COUNT(*) FROM releases WHERE rating = 0;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(unrated, 3);
+        }
+
+        #[test]
+        fn test_v6_from_v5() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 5).unwrap();
+
+            upgrade(&conn, 6).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 6);
+            for table in &["releases", "wantlist"] {
+                let empty: u32 = conn.query_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE genres IS NULL AND master_id IS NULL;", table),
+                    NO_PARAMS,
+                    |row| row.get(0)
+                ).unwrap();
+                assert_eq!(empty, count(&conn, table));
             }
-            tables
-        };
+            assert_eq!(count(&conn, "releases"), 3);
+            assert_eq!(count(&conn, "wantlist"), 2);
+        }
 
-        if all_tables.len() - 4 != folders.len() {
-            for table in &all_tables {
-                if !is_core(table) && !folders.contains(table) {
-                    conn.execute(&format!("DROP TABLE \"{}\"", table), NO_PARAMS)?;
-                }
+        #[test]
+        fn test_v7_from_v6() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 6).unwrap();
+            let items = count(&conn, "folder_items");
+
+            upgrade(&conn, 7).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 7);
+            let empty: u32 = conn.query_row(
+                "SELECT COUNT(*) FROM folder_items WHERE notes IS NULL;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(empty, items);
+        }
+
+        #[test]
+        fn test_v8_from_v7() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 7).unwrap();
+
+            upgrade(&conn, 8).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 8);
+            for table in &["details", "tracks", "credits", "identifiers"] {
+                assert_eq!(count(&conn, table), 0);
             }
+            // nothing that was there is touched
+            assert_eq!(count(&conn, "releases"), 3);
+            assert_eq!(count(&conn, "wantlist"), 2);
+        }
+
+        #[test]
+        fn test_v9_from_v8() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 8).unwrap();
+
+            upgrade(&conn, 9).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 9);
+            assert_eq!(count(&conn, "collection_value"), 0);
+            assert_eq!(count(&conn, "releases"), 3);
+        }
+
+        #[test]
+        fn test_v10_from_v9() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 9).unwrap();
+
+            upgrade(&conn, 10).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 10);
+            assert_eq!(count(&conn, "purchases"), 0);
+            assert_eq!(count(&conn, "releases"), 3);
+        }
+
+        #[test]
+        fn test_v11_from_v10() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 10).unwrap();
+            conn.execute_batch(
+                "INSERT INTO purchases (release_id, date, amount, currency, store)
+                VALUES (1001, '2026-01-20', 10.0, 'USD', NULL),
+                (1002, '2026-01-21', 12.5, 'EUR', 'Rough Trade');"
+            ).unwrap();
+
+            upgrade(&conn, 11).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 11);
+            assert_eq!(count(&conn, "purchases"), 2);
+            // the same release and day can now be bought twice
+            conn.execute(
+                "INSERT INTO purchases (release_id, date, amount, currency)
+                VALUES (1001, '2026-01-20', 10.0, 'USD');",
+                NO_PARAMS
+            ).unwrap();
+            let ids: Vec<i64> = {
+                let mut stmt = conn.prepare("SELECT id FROM purchases ORDER BY id;").unwrap();
+                let ids = stmt.query_map(NO_PARAMS, |row| row.get(0)).unwrap();
+                ids.map(|id| id.unwrap()).collect()
+            };
+            assert_eq!(ids, vec![1, 2, 3]);
+        }
+
+        #[test]
+        fn test_v12_from_v11() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v2.sql"));
+            upgrade(&conn, 11).unwrap();
+            conn.execute_batch(
+                "INSERT INTO purchases (release_id, date, amount, currency)
+                VALUES (1001, '2026-01-20', 0.29, 'USD'),
+                (1002, '2026-01-21', 12.5, 'EUR');"
+            ).unwrap();
+
+            upgrade(&conn, 12).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), 12);
+            let amounts: Vec<(i64, i64)> = {
+                let mut stmt = conn.prepare("SELECT id, amount FROM purchases ORDER BY id;").unwrap();
+                let amounts = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+                amounts.map(|amount| amount.unwrap()).collect()
+            };
+            assert_eq!(amounts, vec![(1, 29), (2, 1250)]);
+        }
+
+        #[test]
+        fn test_run_is_idempotent() {
+            let conn = fixture(include_str!("../../tests/fixtures/db/v0.sql"));
+            run(&conn).unwrap();
+            run(&conn).unwrap();
+
+            assert_eq!(version(&conn).unwrap(), SCHEMA_VERSION);
+            assert_eq!(count(&conn, "releases"), 3);
+            assert_eq!(count(&conn, "listenlog"), 2);
         }
-        Ok(())
-    }
 
-    fn is_core(table: &str) -> bool {
-        table == "profile" ||
-        table == "wantlist" ||
-        table == "folders" ||
-        table == "listenlog"
+        #[test]
+        fn test_newer_schema_is_rejected() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.pragma_update(None, "user_version", &(SCHEMA_VERSION + 1)).unwrap();
+
+            match run(&conn) {
+                Err(DBError::SchemaVersionErr(v)) => assert_eq!(v, SCHEMA_VERSION + 1),
+                other => panic!("expected SchemaVersionErr, got {:?}", other),
+            }
+        }
     }
 }
 
 pub mod update {
     use rusqlite::{
         Connection,
+        Transaction,
+        params,
+        NO_PARAMS,
     };
-    use std::mem;
+    use std::fmt;
+    use std::collections::{HashMap, HashSet};
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hasher, Hash};
-    use crate::utils;
     use crate::app::{
         Release, 
         Folders, 
         Profile,
         ListenLogEntry,
+        ReleaseDetails,
+        CollectionValue,
+        Purchase,
         request::UpdateError,
     };
     use super::{
         admin,
-        purge,
+        Database,
         DBError,
     };
 
-    pub struct DBHandle {
-        conn: Connection,
+    /// A sync in progress. Nothing it writes is visible until `commit()`,
+    /// and dropping it without committing rolls everything back.
+    pub struct DBTransaction<'a> {
+        tx: Transaction<'a>,
+    }
+
+    /// What a sync changed, counted in releases.
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct SyncSummary {
+        pub added: usize,
+        pub removed: usize,
+        pub modified: usize,
+    }
+
+    impl SyncSummary {
+        pub fn merge(self, other: SyncSummary) -> Self {
+            SyncSummary {
+                added: self.added + other.added,
+                removed: self.removed + other.removed,
+                modified: self.modified + other.modified,
+            }
+        }
+    }
+
+    impl fmt::Display for SyncSummary {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} added, {} removed, {} modified", 
+                self.added, self.removed, self.modified
+            )
+        }
     }
 
-    impl DBHandle {
-        pub fn new() -> Result<Self, DBError> {
-            let connection = Connection::open(utils::database_file())?;
-            Ok(DBHandle {
-                conn: connection,
+    impl Database {
+        /// Starts a sync. The connection is shared, so this is a plain
+        /// BEGIN; there is never more than one sync running at a time.
+        pub fn begin(&self) -> Result<DBTransaction<'_>, DBError> {
+            Ok(DBTransaction {
+                tx: self.conn.unchecked_transaction()?,
             })
         }
+    }
 
+    impl DBTransaction<'_> {
         pub fn update_profile(&mut self, profile: Profile) -> Result<(), UpdateError> {
-            purge::table("profile")?;
-            self.conn.execute("INSERT INTO profile 
+            self.tx.execute("DELETE FROM profile;", NO_PARAMS)?;
+            self.tx.execute("INSERT INTO profile 
             (username, 
             real_name, 
             registered, 
@@ -325,126 +986,885 @@ pub mod update {
             Ok(())
         }
 
-        pub fn update_collection(&mut self, mut collection: Folders) -> Result<(), UpdateError> {
-            purge::folders()?;
-            for (name, folder) in collection.contents.iter_mut() {
-                let mut sanitized_name = name.clone();
-                sanitized_name.push('_');
-                self.conn.execute(
-                    "INSERT INTO folders (name) VALUES (?1)",
-                    &[&sanitized_name]
-                )?;
-                let mut new = Vec::new();
-                mem::swap(&mut new, folder);
-                admin::init_folder(sanitized_name.clone(), &self.conn)?;
-                add_releases(&self.conn, &sanitized_name, new)?;
-            }
+        /// Adds to the value history; earlier values are kept.
+        pub fn add_value(&mut self, value: CollectionValue) -> Result<(), UpdateError> {
+            self.tx.execute(
+                "INSERT OR REPLACE INTO collection_value (fetched, minimum, median, maximum)
+                VALUES (?1, ?2, ?3, ?4);",
+                params![value.fetched.to_rfc3339(), value.minimum, value.median, value.maximum]
+            )?;
             Ok(())
         }
 
-        pub fn update_wantlist(&mut self, wantlist: Vec<Release>) -> Result<(), UpdateError> {
-            purge::table("wantlist")?;
-            add_releases(&self.conn, "wantlist", wantlist)?;
+        pub fn update_collection(&mut self, collection: Folders) -> Result<SyncSummary, UpdateError> {
+            Ok(sync_collection(&self.tx, collection)?)
+        }
+
+        pub fn update_wantlist(&mut self, wantlist: Vec<Release>) -> Result<SyncSummary, UpdateError> {
+            Ok(sync_wantlist(&self.tx, wantlist)?)
+        }
+
+        /// Runs the integrity check against the written data 
+        /// and commits only if it passes.
+        pub fn commit(self) -> Result<(), UpdateError> {
+            admin::check_integrity_of(&self.tx)?;
+            self.tx.commit()?;
             Ok(())
         }
     }
 
-    pub fn listenlog(entry: ListenLogEntry) -> Result<(), DBError> {
-        let conn = Connection::open(utils::database_file())?;
-        conn.execute(
+    pub fn listenlog(db: &Database, entry: ListenLogEntry) -> Result<(), DBError> {
+        db.conn.prepare_cached(
             "INSERT INTO listenlog
             (datetime,
             album_id,
             title) VALUES
-            (?1, ?2, ?3);",
-            &[
-                entry.time.to_rfc3339(),
-                entry.id.to_string(),
-                entry.title.into(),
-            ]
-        )?;
+            (?1, ?2, ?3);"
+        )?.execute(&[
+            entry.time.to_rfc3339(),
+            entry.id.to_string(),
+            entry.title.into(),
+        ])?;
         Ok(())
     }
 
-    fn add_releases(conn: &Connection, foldername: &str, folder: Vec<Release>)
-        -> Result<(), DBError> {
-        let mut idx: u64 = 1;
-        let mut hasher = DefaultHasher::new();
-        for release in folder {
-            let mut stringified_labels = String::new();
-            for label in &release.labels {
-                stringified_labels.push_str(&label);
-                stringified_labels.push(':');
-            }
+    /// Records what was paid for a release. Every purchase is kept,
+    /// even two of the same release on the same day.
+    pub fn add_purchase(db: &Database, purchase: &Purchase) -> Result<(), DBError> {
+        db.conn.prepare_cached(
+            "INSERT INTO purchases (release_id, date, amount, currency, store)
+            VALUES (?1, ?2, ?3, ?4, ?5);"
+        )?.execute(params![
+            purchase.release_id,
+            purchase.date,
+            purchase.amount,
+            purchase.currency,
+            purchase.store,
+        ])?;
+        Ok(())
+    }
 
-            let mut stringified_formats = String::new();
-            for format in &release.formats {
-                stringified_formats.push_str(&format);
-                stringified_formats.push(':');
-            }
+    /// Replaces purchase `id`, as given by `query::last_purchase`, to put a mistake right.
+    pub fn correct_purchase(db: &Database, id: i64, purchase: &Purchase) -> Result<(), DBError> {
+        db.conn.prepare_cached(
+            "UPDATE purchases SET release_id = ?2, date = ?3, amount = ?4, currency = ?5, store = ?6
+            WHERE id = ?1;"
+        )?.execute(params![
+            id,
+            purchase.release_id,
+            purchase.date,
+            purchase.amount,
+            purchase.currency,
+            purchase.store,
+        ])?;
+        Ok(())
+    }
 
-            stringified_labels.pop().unwrap();
-            stringified_formats.pop().unwrap();
+    /*
+    Syncing diffs the incoming data against what is already stored
+    instead of clearing and rewriting everything:
+    - releases are matched on their Discogs ID and only rewritten
+      if their stored hash differs
+    - folder entries are matched on (release, date added), so unchanged
+      entries keep their row
+    - anything no longer present upstream is deleted
+    */
+    fn sync_collection(conn: &Connection, collection: Folders) -> Result<SyncSummary, DBError> {
+        let mut summary = SyncSummary::default();
 
-            //hashing the release
-            release.hash(&mut hasher);
-            let hash = hasher.finish();
+        let stored = stored_hashes(conn, "SELECT id, hash FROM releases;")?;
+        let mut incoming: HashMap<i64, &Release> = HashMap::new();
+        for release in collection.contents.values().flatten() {
+            incoming.entry(release.id).or_insert(release);
+        }
 
-            //inserting the data
-            let mut stmt = conn.prepare(
-                &format!("INSERT INTO \"{}\"
-                (idx,
+        {
+            let mut insert = conn.prepare_cached(
+                "INSERT INTO releases
+                (id,
                 hash,
-                id,
                 search_string,
                 title,
                 artist,
                 year,
                 labels,
                 formats,
-                date_added) VALUES
-                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10);", 
-                foldername)
+                catnos,
+                rating,
+                genres,
+                styles,
+                master_id,
+                thumb,
+                cover_image) VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15);"
             )?;
-            stmt.execute(
-                &[
-                    &idx.to_string(),
-                    &hash.to_string(),
-                    &release.id.to_string(),
-                    &release.search_string,
-                    &release.title,
-                    &release.artist,
-                    &release.year.to_string(),
-                    &stringified_labels,
-                    &stringified_formats,
-                    &release.date_added.to_rfc3339(),
-                ]
+            let mut update = conn.prepare_cached(
+                "UPDATE releases SET
+                hash = ?2,
+                search_string = ?3,
+                title = ?4,
+                artist = ?5,
+                year = ?6,
+                labels = ?7,
+                formats = ?8,
+                catnos = ?9,
+                rating = ?10,
+                genres = ?11,
+                styles = ?12,
+                master_id = ?13,
+                thumb = ?14,
+                cover_image = ?15
+                WHERE id = ?1;"
             )?;
-            idx += 1;
+            for (id, release) in &incoming {
+                let hash = metadata_hash(release);
+                let stmt = match stored.get(id) {
+                    Some(old) if *old == hash => continue,
+                    Some(_) => {summary.modified += 1; &mut update}
+                    None => {summary.added += 1; &mut insert}
+                };
+                stmt.execute(params![
+                    id,
+                    hash,
+                    release.search_string,
+                    release.title,
+                    release.artist,
+                    release.year,
+                    release.labels.join(":"),
+                    release.formats.join(":"),
+                    release.catnos.join(":"),
+                    release.rating,
+                    release.genres.join(":"),
+                    release.styles.join(":"),
+                    release.master_id,
+                    release.thumb,
+                    release.cover_image,
+                ])?;
+            }
         }
-        Ok(())
+
+        sync_folders(conn, &collection)?;
+
+        let mut delete = conn.prepare_cached("DELETE FROM releases WHERE id = ?1;")?;
+        for id in stored.keys().filter(|id| !incoming.contains_key(id)) {
+            delete.execute(&[id])?;
+            summary.removed += 1;
+        }
+
+        Ok(summary)
     }
-}
+
+    //* (release id, date added) -> (row, instance id, notes) of every entry that has them
+    type StoredEntries = HashMap<(i64, String), Vec<(i64, Option<i64>, Option<String>)>>;
+
+    fn sync_folders(conn: &Connection, collection: &Folders) -> Result<(), DBError> {
+        let mut stored_folders: HashSet<String> = HashSet::new();
+        {
+            let mut stmt = conn.prepare_cached("SELECT name FROM folders;")?;
+            for name in stmt.query_map(NO_PARAMS, |row| row.get(0))? {
+                stored_folders.insert(name?);
+            }
+        }
+        for name in stored_folders.iter().filter(|name| !collection.contents.contains_key(*name)) {
+            conn.execute("DELETE FROM folder_items WHERE folder = ?1;", &[name])?;
+            conn.execute("DELETE FROM folders WHERE name = ?1;", &[name])?;
+        }
+
+        let mut select = conn.prepare_cached(
+            "SELECT idx, release_id, date_added, instance_id, notes FROM folder_items WHERE folder = ?1;"
+        )?;
+        let mut insert = conn.prepare_cached(
+            "INSERT INTO folder_items
+            (folder,
+            release_id,
+            date_added,
+            instance_id,
+            notes) VALUES
+            (?1, ?2, ?3, ?4, ?5);"
+        )?;
+        //* CSV imports don't know instance IDs, so they keep whatever is there
+        let mut set_copy = conn.prepare_cached(
+            "UPDATE folder_items SET instance_id = COALESCE(?2, instance_id), notes = ?3 WHERE idx = ?1;"
+        )?;
+        let mut delete = conn.prepare_cached("DELETE FROM folder_items WHERE idx = ?1;")?;
+
+        for (name, folder) in &collection.contents {
+            if !stored_folders.contains(name) {
+                conn.execute("INSERT INTO folders (name) VALUES (?1);", &[name])?;
+            }
+            //* CSV imports don't know folder IDs, so they keep whatever is there
+            if let Some(id) = collection.ids.get(name) {
+                conn.execute("UPDATE folders SET id = ?2 WHERE name = ?1;", params![name, id])?;
+            }
+
+            // a folder can hold the same release more than once,
+            // so each key maps to every row that matches it
+            let mut existing: StoredEntries = HashMap::new();
+            for row in select.query_map(&[name], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?, 
+                    row.get::<_, i64>(1)?, 
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })? {
+                let (idx, release_id, date_added, instance_id, notes) = row?;
+                existing.entry((release_id, date_added)).or_default().push((idx, instance_id, notes));
+            }
+
+            for release in folder {
+                let date_added = release.date_added.to_rfc3339();
+                let kept = existing.get_mut(&(release.id, date_added.clone()))
+                    .and_then(|rows| {
+                        //* the row of the same copy if there is one, any of them otherwise
+                        let same = rows.iter().position(|(_, instance, _)| *instance == release.instance_id);
+                        match same {
+                            Some(idx) => Some(rows.remove(idx)),
+                            None => rows.pop(),
+                        }
+                    });
+                let notes = super::notes_to_sql(&release.notes);
+                match kept {
+                    None => {
+                        insert.execute(params![name, release.id, date_added, release.instance_id, notes])?;
+                    }
+                    //* rows from before instance IDs were stored get theirs now
+                    Some((idx, stored, stored_notes)) 
+                        if (release.instance_id.is_some() && stored != release.instance_id) 
+                        || stored_notes != notes => {
+                        set_copy.execute(params![idx, release.instance_id, notes])?;
+                    }
+                    Some(_) => {}
+                }
+            }
+            for (idx, _, _) in existing.values().flatten() {
+                delete.execute(&[idx])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn sync_wantlist(conn: &Connection, wantlist: Vec<Release>) -> Result<SyncSummary, DBError> {
+        let mut summary = SyncSummary::default();
+
+        let stored = stored_hashes(conn, "SELECT id, hash FROM wantlist;")?;
+        let mut incoming: HashSet<i64> = HashSet::new();
+
+        for release in wantlist {
+            if !incoming.insert(release.id) {
+                continue
+            }
+            let hash = hash(&release);
+            let sql = match stored.get(&release.id) {
+                Some(old) if *old == hash => continue,
+                Some(_) => {summary.modified += 1; UPDATE_WANT}
+                None => {summary.added += 1; INSERT_WANT}
+            };
+            write_want(conn, sql, &release, hash)?;
+        }
+
+        let mut delete = conn.prepare_cached("DELETE FROM wantlist WHERE id = ?1;")?;
+        for id in stored.keys().filter(|id| !incoming.contains(id)) {
+            delete.execute(&[id])?;
+            summary.removed += 1;
+        }
+
+        Ok(summary)
+    }
+
+    const INSERT_WANT: &str = 
+        "INSERT INTO wantlist
+        (hash,
+        id,
+        search_string,
+        title,
+        artist,
+        year,
+        labels,
+        formats,
+        date_added,
+        catnos,
+        genres,
+        styles,
+        master_id,
+        thumb,
+        cover_image) VALUES
+        (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15);";
+
+    const UPDATE_WANT: &str = 
+        "UPDATE wantlist SET
+        hash = ?1,
+        search_string = ?3,
+        title = ?4,
+        artist = ?5,
+        year = ?6,
+        labels = ?7,
+        formats = ?8,
+        date_added = ?9,
+        catnos = ?10,
+        genres = ?11,
+        styles = ?12,
+        master_id = ?13,
+        thumb = ?14,
+        cover_image = ?15
+        WHERE id = ?2;";
+
+    fn write_want(conn: &Connection, sql: &str, release: &Release, hash: i64) -> Result<(), DBError> {
+        conn.prepare_cached(sql)?.execute(params![
+            hash,
+            release.id,
+            release.search_string,
+            release.title,
+            release.artist,
+            release.year,
+            release.labels.join(":"),
+            release.formats.join(":"),
+            release.date_added.to_rfc3339(),
+            release.catnos.join(":"),
+            release.genres.join(":"),
+            release.styles.join(":"),
+            release.master_id,
+            release.thumb,
+            release.cover_image,
+        ])?;
+        Ok(())
+    }
+
+    /// Writes one release to the wantlist right after it was added on Discogs,
+    /// instead of waiting for the next sync. Returns false if it was already there.
+    pub fn add_want(db: &Database, release: &Release) -> Result<bool, DBError> {
+        let stored: i64 = db.conn.prepare_cached("SELECT COUNT(*) FROM wantlist WHERE id = ?1;")?
+            .query_row([release.id], |row| row.get(0))?;
+        let sql = if stored > 0 {UPDATE_WANT} else {INSERT_WANT};
+        write_want(&db.conn, sql, release, hash(release))?;
+        Ok(stored == 0)
+    }
+
+    /// The other way around. Returns false if it wasn't there.
+    pub fn remove_want(db: &Database, id: i64) -> Result<bool, DBError> {
+        let removed = db.conn.prepare_cached("DELETE FROM wantlist WHERE id = ?1;")?
+            .execute([id])?;
+        Ok(removed > 0)
+    }
+
+    /*
+    The changes below are made on Discogs first and written here as soon as
+    it accepts them, the same way as add_want() and remove_want().
+    Copies are found by their instance ID. The All folder lists every copy
+    on Discogs, so it gets them here too and never loses one to a move.
+    */
+
+    /// Writes a copy of `release` that was just added to `folder`.
+    /// The release itself is stored as well if this is the first copy of it.
+    pub fn add_instance(db: &Database, folder: &str, release: &Release) -> Result<(), DBError> {
+        let tx = db.conn.unchecked_transaction()?;
+        tx.prepare_cached(
+            "INSERT OR IGNORE INTO releases
+            (id,
+            hash,
+            search_string,
+            title,
+            artist,
+            year,
+            labels,
+            formats,
+            catnos,
+            rating,
+            genres,
+            styles,
+            master_id,
+            thumb,
+            cover_image) VALUES
+            (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15);"
+        )?.execute(params![
+            release.id,
+            metadata_hash(release),
+            release.search_string,
+            release.title,
+            release.artist,
+            release.year,
+            release.labels.join(":"),
+            release.formats.join(":"),
+            release.catnos.join(":"),
+            release.rating,
+            release.genres.join(":"),
+            release.styles.join(":"),
+            release.master_id,
+            release.thumb,
+            release.cover_image,
+        ])?;
+        let mut folders = vec![folder.to_string()];
+        if let Some(all) = all_folder(&tx)? {
+            if all != folder {
+                folders.push(all);
+            }
+        }
+        for name in folders {
+            tx.prepare_cached(
+                "INSERT INTO folder_items (folder, release_id, date_added, instance_id, notes) 
+                VALUES (?1, ?2, ?3, ?4, ?5);"
+            )?.execute(params![
+                name, release.id, release.date_added.to_rfc3339(), release.instance_id,
+                super::notes_to_sql(&release.notes),
+            ])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Moves a copy to another folder.
+    pub fn move_instance(db: &Database, instance_id: i64, folder: &str) -> Result<(), DBError> {
+        db.conn.prepare_cached(
+            "UPDATE folder_items SET folder = ?2 
+            WHERE instance_id = ?1
+            AND folder NOT IN (SELECT name FROM folders WHERE id = 0);"
+        )?.execute(params![instance_id, folder])?;
+        Ok(())
+    }
+
+    /// Takes a copy out of the collection, and the release with it if it was the last one.
+    pub fn remove_instance(db: &Database, instance_id: i64) -> Result<(), DBError> {
+        let tx = db.conn.unchecked_transaction()?;
+        let release_ids: Vec<i64> = {
+            let mut stmt = tx.prepare_cached(
+                "SELECT DISTINCT release_id FROM folder_items WHERE instance_id = ?1;"
+            )?;
+            let ids = stmt.query_map([instance_id], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            ids
+        };
+        tx.prepare_cached("DELETE FROM folder_items WHERE instance_id = ?1;")?
+            .execute([instance_id])?;
+        for id in release_ids {
+            tx.prepare_cached(
+                "DELETE FROM releases WHERE id = ?1 
+                AND id NOT IN (SELECT release_id FROM folder_items);"
+            )?.execute([id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Stores the rating of a release in the collection.
+    pub fn set_rating(db: &Database, release: &Release) -> Result<(), DBError> {
+        db.conn.prepare_cached("UPDATE releases SET rating = ?2, hash = ?3 WHERE id = ?1;")?
+            .execute(params![release.id, release.rating, metadata_hash(release)])?;
+        Ok(())
+    }
+
+    pub fn add_folder(db: &Database, name: &str, id: i64) -> Result<(), DBError> {
+        db.conn.prepare_cached("INSERT INTO folders (name, id) VALUES (?1, ?2);")?
+            .execute(params![name, id])?;
+        Ok(())
+    }
+
+    pub fn rename_folder(db: &Database, name: &str, new_name: &str) -> Result<(), DBError> {
+        let tx = db.conn.unchecked_transaction()?;
+        tx.prepare_cached("UPDATE folders SET name = ?2 WHERE name = ?1;")?
+            .execute(&[name, new_name])?;
+        tx.prepare_cached("UPDATE folder_items SET folder = ?2 WHERE folder = ?1;")?
+            .execute(&[name, new_name])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn remove_folder(db: &Database, name: &str) -> Result<(), DBError> {
+        let tx = db.conn.unchecked_transaction()?;
+        tx.prepare_cached("DELETE FROM folder_items WHERE folder = ?1;")?
+            .execute(&[name])?;
+        tx.prepare_cached("DELETE FROM folders WHERE name = ?1;")?
+            .execute(&[name])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Caches the details of a release, replacing whatever was cached for it.
+    pub fn cache_details(db: &Database, details: &ReleaseDetails) -> Result<(), DBError> {
+        let tx = db.conn.unchecked_transaction()?;
+        for table in &["tracks", "credits", "identifiers", "details"] {
+            tx.prepare_cached(&format!("DELETE FROM {} WHERE release_id = ?1;", table))?
+                .execute([details.id])?;
+        }
+        tx.prepare_cached(
+            "INSERT INTO details (release_id, country, released, notes, fetched)
+            VALUES (?1, ?2, ?3, ?4, ?5);"
+        )?.execute(params![
+            details.id, details.country, details.released, details.notes, details.fetched.to_rfc3339()
+        ])?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO tracks (release_id, idx, position, title, duration, heading)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6);"
+            )?;
+            for (idx, track) in details.tracklist.iter().enumerate() {
+                insert.execute(params![
+                    details.id, idx as i64, track.position, track.title, track.duration, track.heading
+                ])?;
+            }
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO credits (release_id, idx, name, role, tracks)
+                VALUES (?1, ?2, ?3, ?4, ?5);"
+            )?;
+            for (idx, credit) in details.credits.iter().enumerate() {
+                insert.execute(params![details.id, idx as i64, credit.name, credit.role, credit.tracks])?;
+            }
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO identifiers (release_id, idx, kind, value, description)
+                VALUES (?1, ?2, ?3, ?4, ?5);"
+            )?;
+            for (idx, identifier) in details.identifiers.iter().enumerate() {
+                insert.execute(params![
+                    details.id, idx as i64, identifier.kind, identifier.value, identifier.description
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn all_folder(conn: &Connection) -> Result<Option<String>, DBError> {
+        let mut stmt = conn.prepare_cached("SELECT name FROM folders WHERE id = 0;")?;
+        let mut names = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+        Ok(names.next().transpose()?)
+    }
+
+    fn stored_hashes(conn: &Connection, sql: &str) -> Result<HashMap<i64, i64>, DBError> {
+        let mut stmt = conn.prepare_cached(sql)?;
+        let mut hashes = HashMap::new();
+        for row in stmt.query_map(NO_PARAMS, |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?))
+        })? {
+            let (id, hash) = row?;
+            hashes.insert(id, hash.unwrap_or_default());
+        }
+        Ok(hashes)
+    }
+
+    // DefaultHasher is not guaranteed to be stable across Rust releases;
+    // if it changes, the next sync just rewrites every row once.
+    fn hash(release: &Release) -> i64 {
+        let mut hasher = DefaultHasher::new();
+        release.hash(&mut hasher);
+        hasher.finish() as i64
+    }
+
+    // only the fields stored in `releases`; the date added
+    // belongs to the folder entry and is diffed there
+    fn metadata_hash(release: &Release) -> i64 {
+        let mut hasher = DefaultHasher::new();
+        release.id.hash(&mut hasher);
+        release.search_string.hash(&mut hasher);
+        release.title.hash(&mut hasher);
+        release.artist.hash(&mut hasher);
+        release.year.hash(&mut hasher);
+        release.labels.hash(&mut hasher);
+        release.formats.hash(&mut hasher);
+        release.catnos.hash(&mut hasher);
+        //* fields stored later only count once they're set, so releases
+        //* without them hash as they did before they were stored
+        if release.rating > 0 {
+            release.rating.hash(&mut hasher);
+        }
+        if !release.genres.is_empty() || !release.styles.is_empty() {
+            release.genres.hash(&mut hasher);
+            release.styles.hash(&mut hasher);
+        }
+        if release.master_id.is_some() {
+            release.master_id.hash(&mut hasher);
+        }
+        if !release.thumb.is_empty() || !release.cover_image.is_empty() {
+            release.thumb.hash(&mut hasher);
+            release.cover_image.hash(&mut hasher);
+        }
+        hasher.finish() as i64
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::{TimeZone, Utc};
+        use crate::app::{Note, database::migrate};
+
+        fn database() -> Connection {
+            let conn = Connection::open_in_memory().unwrap();
+            migrate::run(&conn).unwrap();
+            conn
+        }
+
+        fn open_in_memory() -> Database {
+            let db = Database::open_path(":memory:").unwrap();
+            migrate::run(&db.conn).unwrap();
+            db
+        }
+
+        fn release(id: i64, title: &str, day: u32) -> Release {
+            Release {
+                id,
+                search_string: title.into(),
+                title: title.into(),
+                artist: String::from("Boards Of Canada"),
+                year: 1998,
+                labels: vec![String::from("Warp Records")],
+                catnos: vec![String::from("WARPLP55")],
+                formats: vec![String::from("Vinyl 2LP")],
+                date_added: Utc.ymd(2020, 3, day).and_hms(0, 0, 0),
+                instance_id: None,
+                rating: 0,
+                genres: vec![String::from("Electronic")],
+                styles: vec![String::from("IDM"), String::from("Downtempo")],
+                master_id: Some(5617),
+                thumb: String::new(),
+                cover_image: String::new(),
+                notes: Vec::new(),
+            }
+        }
+
+        fn folders(contents: Vec<(&str, Vec<Release>)>) -> Folders {
+            let mut folders = Folders::new();
+            for (name, releases) in contents {
+                folders.push(name.into(), releases);
+            }
+            folders
+        }
+
+        fn rowids(conn: &Connection, sql: &str) -> Vec<i64> {
+            let mut stmt = conn.prepare_cached(sql).unwrap();
+            let ids = stmt.query_map(NO_PARAMS, |row| row.get(0)).unwrap()
+                .map(|r| r.unwrap()).collect();
+            ids
+        }
+
+        #[test]
+        fn test_collection_sync_applies_only_changes() {
+            let conn = database();
+            let first = vec![release(1, "Twoism", 1), release(2, "Hi Scores", 2), release(3, "Geogaddi", 3)];
+            let summary = sync_collection(&conn, folders(vec![
+                ("All", first.clone()), ("Uncategorized", first.clone()),
+            ])).unwrap();
+            assert_eq!(summary, SyncSummary {added: 3, removed: 0, modified: 0});
+
+            let items_before = rowids(&conn, "SELECT idx FROM folder_items WHERE release_id = 1;");
+
+            let mut second = first;
+            second.remove(1);
+            second[1].title = String::from("Geogaddi (Remastered)");
+            second.push(release(4, "Tomorrow's Harvest", 4));
+            let summary = sync_collection(&conn, folders(vec![
+                ("All", second.clone()), ("Favourites", second),
+            ])).unwrap();
+            assert_eq!(summary, SyncSummary {added: 1, removed: 1, modified: 1});
+
+            // untouched entries keep their rows
+            let items_after = rowids(&conn, 
+                "SELECT idx FROM folder_items WHERE release_id = 1 AND folder = 'All';"
+            );
+            assert!(items_before.contains(&items_after[0]));
+
+            assert_eq!(rowids(&conn, "SELECT id FROM releases ORDER BY id;"), vec![1, 3, 4]);
+            let folders: Vec<String> = {
+                let mut stmt = conn.prepare_cached("SELECT name FROM folders ORDER BY name;").unwrap();
+                let names = stmt.query_map(NO_PARAMS, |row| row.get(0)).unwrap()
+                    .map(|r| r.unwrap()).collect();
+                names
+            };
+            assert_eq!(folders, vec!["All", "Favourites"]);
+            assert_eq!(rowids(&conn, "SELECT COUNT(*) FROM folder_items;"), vec![6]);
+        }
+
+        #[test]
+        fn test_collection_sync_keeps_duplicate_copies() {
+            let conn = database();
+            let copies = vec![release(1, "Twoism", 1), release(1, "Twoism", 2)];
+            sync_collection(&conn, folders(vec![("All", copies.clone())])).unwrap();
+            let summary = sync_collection(&conn, folders(vec![("All", copies)])).unwrap();
+
+            assert_eq!(summary, SyncSummary::default());
+            assert_eq!(rowids(&conn, "SELECT COUNT(*) FROM folder_items;"), vec![2]);
+        }
+
+        #[test]
+        fn test_collection_sync_fills_in_instance_ids() {
+            let conn = database();
+            // rows from before instance IDs were stored, or from a CSV import
+            sync_collection(&conn, folders(vec![("All", vec![release(1, "Twoism", 1)])])).unwrap();
+            let before = rowids(&conn, "SELECT idx FROM folder_items;");
+
+            let mut copy = release(1, "Twoism", 1);
+            copy.instance_id = Some(11);
+            let mut synced = folders(vec![("All", vec![copy])]);
+            synced.ids.insert(String::from("All"), 0);
+            sync_collection(&conn, synced).unwrap();
+            assert_eq!(rowids(&conn, "SELECT idx FROM folder_items;"), before);
+            assert_eq!(rowids(&conn, "SELECT instance_id FROM folder_items;"), vec![11]);
+            assert_eq!(rowids(&conn, "SELECT id FROM folders;"), vec![0]);
+
+            // and a CSV import afterwards doesn't know them, so it leaves them be
+            sync_collection(&conn, folders(vec![("All", vec![release(1, "Twoism", 1)])])).unwrap();
+            assert_eq!(rowids(&conn, "SELECT instance_id FROM folder_items;"), vec![11]);
+            assert_eq!(rowids(&conn, "SELECT id FROM folders;"), vec![0]);
+        }
+
+        #[test]
+        fn test_copies_keep_their_own_notes() {
+            let conn = database();
+            let copy = |instance_id: i64, condition: &str| {
+                let mut copy = release(1, "Twoism", 1);
+                copy.instance_id = Some(instance_id);
+                copy.notes = vec![Note::new(Note::MEDIA_CONDITION, condition.into())];
+                copy
+            };
+            let conditions = |conn: &Connection| -> Vec<(i64, String)> {
+                let mut stmt = conn.prepare(
+                    "SELECT instance_id, notes FROM folder_items ORDER BY instance_id;"
+                ).unwrap();
+                let rows = stmt.query_map(NO_PARAMS, |row| {
+                    Ok((row.get(0)?, super::super::notes_from_sql(row.get(1)?)?))
+                }).unwrap();
+                rows.map(|row| {
+                    let (instance_id, mut notes) = row.unwrap();
+                    (instance_id, notes.remove(0).value)
+                }).collect()
+            };
+            // two copies of the same pressing, added the same day
+            sync_collection(&conn, folders(vec![("All", vec![copy(11, "Mint (M)"), copy(12, "Good (G)")])])).unwrap();
+            let before = rowids(&conn, "SELECT idx FROM folder_items ORDER BY instance_id;");
+            assert_eq!(conditions(&conn), vec![(11, String::from("Mint (M)")), (12, String::from("Good (G)"))]);
+
+            // in any order, each row stays with its copy and takes its new notes
+            sync_collection(&conn, folders(vec![("All", vec![copy(12, "Fair (F)"), copy(11, "Mint (M)")])])).unwrap();
+            assert_eq!(rowids(&conn, "SELECT idx FROM folder_items ORDER BY instance_id;"), before);
+            assert_eq!(conditions(&conn), vec![(11, String::from("Mint (M)")), (12, String::from("Fair (F)"))]);
+
+            // notes that were taken off are gone here too
+            let mut bare = copy(11, "");
+            bare.notes.clear();
+            sync_collection(&conn, folders(vec![("All", vec![bare, copy(12, "Fair (F)")])])).unwrap();
+            let mut stmt = conn.prepare("SELECT COUNT(*) FROM folder_items WHERE notes IS NULL;").unwrap();
+            assert_eq!(stmt.query_row(NO_PARAMS, |row| row.get::<_, i64>(0)).unwrap(), 1);
+        }
+
+        #[test]
+        fn test_failed_integrity_check_rolls_back() {
+            let handle = open_in_memory();
+            let mut tx = handle.begin().unwrap();
+            tx.update_collection(folders(vec![("All", vec![release(1, "Twoism", 1)])])).unwrap();
+            tx.commit().unwrap();
+
+            let mut tx = handle.begin().unwrap();
+            tx.update_collection(folders(vec![("All", vec![release(2, "Hi Scores", 2)])])).unwrap();
+            tx.update_wantlist(vec![release(3, "Geogaddi", 3)]).unwrap();
+            // leave a dangling folder entry behind so the check fails
+            tx.tx.execute(
+                "INSERT INTO folder_items (folder, release_id, date_added) VALUES ('All', 9, '');",
+                NO_PARAMS
+            ).unwrap();
+            assert!(tx.commit().is_err());
+
+            assert_eq!(rowids(&handle.conn, "SELECT id FROM releases;"), vec![1]);
+            assert_eq!(rowids(&handle.conn, "SELECT COUNT(*) FROM wantlist;"), vec![0]);
+        }
+
+        #[test]
+        fn test_dropped_transaction_rolls_back() {
+            let handle = open_in_memory();
+            {
+                let mut tx = handle.begin().unwrap();
+                tx.update_wantlist(vec![release(1, "Twoism", 1)]).unwrap();
+                // an error partway through a sync returns before commit()
+            }
+            assert_eq!(rowids(&handle.conn, "SELECT COUNT(*) FROM wantlist;"), vec![0]);
+        }
+
+        #[test]
+        fn test_single_wants_are_added_and_removed() {
+            let handle = open_in_memory();
+            sync_wantlist(&handle.conn, vec![release(1, "Twoism", 1)]).unwrap();
+
+            assert!(add_want(&handle, &release(2, "Hi Scores", 2)).unwrap());
+            assert!(!add_want(&handle, &release(2, "Hi Scores", 2)).unwrap());
+            assert_eq!(rowids(&handle.conn, "SELECT id FROM wantlist ORDER BY id;"), vec![1, 2]);
+
+            // a sync afterwards finds nothing to do
+            let summary = sync_wantlist(&handle.conn, vec![release(1, "Twoism", 1), release(2, "Hi Scores", 2)]).unwrap();
+            assert_eq!(summary, SyncSummary::default());
+
+            assert!(remove_want(&handle, 1).unwrap());
+            assert!(!remove_want(&handle, 1).unwrap());
+            assert_eq!(rowids(&handle.conn, "SELECT id FROM wantlist;"), vec![2]);
+        }
+
+        #[test]
+        fn test_wantlist_sync_applies_only_changes() {
+            let conn = database();
+            sync_wantlist(&conn, vec![release(1, "Twoism", 1), release(2, "Hi Scores", 2)]).unwrap();
+            let before = rowids(&conn, "SELECT idx FROM wantlist WHERE id = 1;");
+
+            let mut changed = release(2, "Hi Scores", 2);
+            changed.year = 1996;
+            let summary = sync_wantlist(&conn, vec![
+                release(1, "Twoism", 1), changed, release(3, "Geogaddi", 3),
+            ]).unwrap();
+            assert_eq!(summary, SyncSummary {added: 1, removed: 0, modified: 1});
+            assert_eq!(rowids(&conn, "SELECT idx FROM wantlist WHERE id = 1;"), before);
+
+            let summary = sync_wantlist(&conn, vec![release(3, "Geogaddi", 3)]).unwrap();
+            assert_eq!(summary, SyncSummary {added: 0, removed: 2, modified: 0});
+        }
+    }
+}
 
 pub mod query {
+    use std::fmt;
+    use std::collections::BTreeMap;
     use chrono::{
         DateTime,
         Utc,
     };
     use rusqlite::{
-        Connection,
+        Row,
         Statement,
+        ToSql,
         NO_PARAMS,
     };
-    use super::DBError;
+    use super::{Database, DBError};
     use crate::app::{
         Release, 
         Folders, 
         Profile,
         ListenLog,
+        ReleaseDetails,
+        CollectionValue,
+        Purchase,
+        Spending,
+        Track,
+        Credit,
+        Identifier,
     };
     use crate::utils;
 
+    // every collection query reads releases through their folder entries,
+    // so the columns line up with the wantlist table for get_releases()
+    const COLLECTION_SELECT: &str = "SELECT 
+        releases.id, 
+        releases.search_string, 
+        releases.title, 
+        releases.artist, 
+        releases.year, 
+        releases.labels, 
+        releases.formats, 
+        releases.catnos, 
+        folder_items.date_added, 
+        folder_items.instance_id, 
+        releases.rating, 
+        releases.genres, 
+        releases.styles, 
+        releases.master_id, 
+        releases.thumb, 
+        releases.cover_image, 
+        folder_items.notes 
+        FROM folder_items JOIN releases ON releases.id = folder_items.release_id";
+
     /*
     profile(), collection() and wantlist() are called when the app starts
     they must not fail, so they will panic if they do
@@ -469,6 +1889,116 @@ pub mod query {
         Date,
     }
 
+    /// A search over the collection or wantlist; every term has to match.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Filter {
+        pub terms: Vec<Term>,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Term {
+        /// Matched against every indexed field, ranked
+        Text(String),
+        /// Matched literally against a single field
+        Field(Field, String),
+        Year(Comparison, u32),
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Field {
+        Title,
+        Artist,
+        Label,
+        Format,
+        Catno,
+        Genre,
+        Style,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Comparison {
+        Eq,
+        Lt,
+        Le,
+        Gt,
+        Ge,
+    }
+
+    impl Filter {
+        /// A plain search for some text, as typed by the user.
+        pub fn text(query: &str) -> Self {
+            Filter {
+                terms: vec![Term::Text(query.to_string())],
+            }
+        }
+
+        // all the free text terms, searched for together
+        fn free_text(&self) -> String {
+            self.terms.iter()
+                .filter_map(|term| match term {
+                    Term::Text(text) => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<&str>>()
+                .join(" ")
+        }
+    }
+
+    impl fmt::Display for Filter {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let quoted = |s: &str| if s.contains(char::is_whitespace) {
+                format!("\"{}\"", s)
+            } else {
+                s.to_string()
+            };
+            let terms: Vec<String> = self.terms.iter().map(|term| match term {
+                Term::Text(text) => quoted(text),
+                Term::Field(field, value) => format!("{}:{}", field.name(), quoted(value)),
+                Term::Year(Comparison::Eq, year) => format!("year:{}", year),
+                Term::Year(comparison, year) => format!("year:{}{}", comparison.as_str(), year),
+            }).collect();
+            write!(f, "{}", terms.join(" "))
+        }
+    }
+
+    impl Field {
+        fn name(self) -> &'static str {
+            match self {
+                Field::Title => "title",
+                Field::Artist => "artist",
+                Field::Label => "label",
+                Field::Format => "format",
+                Field::Catno => "catno",
+                Field::Genre => "genre",
+                Field::Style => "style",
+            }
+        }
+
+        fn column(self) -> &'static str {
+            match self {
+                Field::Title => "title",
+                Field::Artist => "artist",
+                Field::Label => "labels",
+                Field::Format => "formats",
+                Field::Catno => "catnos",
+                Field::Genre => "genres",
+                Field::Style => "styles",
+            }
+        }
+    }
+
+    impl Comparison {
+        fn as_str(self) -> &'static str {
+            match self {
+                Comparison::Eq => "=",
+                Comparison::Lt => "<",
+                Comparison::Le => "<=",
+                Comparison::Gt => ">",
+                Comparison::Ge => ">=",
+            }
+        }
+    }
+
     impl SortOrder {
         pub fn as_str(self) -> &'static str {
             use SortOrder::*;
@@ -484,10 +2014,8 @@ pub mod query {
         }
     }
 
-    pub fn profile() -> Result<Profile, DBError> {
-        let conn = Connection::open(utils::database_file())?;
-
-        let profile = conn.query_row(
+    pub fn profile(db: &Database) -> Result<Profile, DBError> {
+        let profile = db.conn.query_row(
             "SELECT * FROM profile", NO_PARAMS, |row| {
                 Ok(Profile {
                     username: row.get(0)?,
@@ -504,12 +2032,10 @@ pub mod query {
         Ok(profile)
     }
 
-    pub fn collection(order: SortOrder) -> Result<Folders, DBError> {
-        let conn = Connection::open(utils::database_file())?;
-
+    pub fn collection(db: &Database, order: SortOrder) -> Result<Folders, DBError> {
         
         let mut folder_names: Vec<String> = Vec::new();
-        let mut stmt = conn.prepare("SELECT name FROM folders;")?;
+        let mut stmt = db.conn.prepare_cached("SELECT name FROM folders;")?;
         let folderquery = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
         for folder in folderquery {
             folder_names.push(folder?);
@@ -517,48 +2043,221 @@ pub mod query {
         
         let sortby = order.as_str();
         let mut folders = Folders::new();
+        let mut stmt = db.conn.prepare_cached(&format!(
+            "{} WHERE folder_items.folder = ?1 {};", 
+            COLLECTION_SELECT, sortby
+        ))?;
 
-        for mut name in folder_names {
-            let mut stmt = conn.prepare(&format!("SELECT * FROM \"{}\" {};", name, sortby))?;
-            let folder = get_releases(&mut stmt, QueryType::Collection)?;
-            name.pop().unwrap();
+        for name in folder_names {
+            let folder = get_releases(&mut stmt, &[&name])?;
             folders.push(name, folder);
         }
         Ok(folders)
     }
 
-    pub fn wantlist(order: SortOrder) -> Result<Vec<Release>, DBError> {
-        let conn = Connection::open(utils::database_file())?;
+    pub fn wantlist(db: &Database, order: SortOrder) -> Result<Vec<Release>, DBError> {
         let sortby = order.as_str();
-        let mut stmt = conn.prepare(&format!("SELECT * FROM wantlist {};", sortby))?;
-        let wantlist = get_releases(&mut stmt, QueryType::Wantlist)?;
+        let mut stmt = db.conn.prepare_cached(&format!("SELECT * FROM wantlist {};", sortby))?;
+        let wantlist = get_releases(&mut stmt, NO_PARAMS)?;
         Ok(wantlist)
     }
 
+    /// One release in the wantlist, by Discogs ID.
+    pub fn want(db: &Database, id: i64) -> Result<Option<Release>, DBError> {
+        let mut stmt = db.conn.prepare_cached("SELECT * FROM wantlist WHERE id = ?1;")?;
+        Ok(get_releases(&mut stmt, [id])?.pop())
+    }
+
+    /// A copy of a release in the collection, outside the All folder.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Instance {
+        pub folder: String,
+        pub folder_id: Option<i64>,
+        pub instance_id: Option<i64>,
+    }
+
+    /// Every folder by name, with its Discogs ID if a sync has stored it.
+    pub fn folder_ids(db: &Database) -> Result<BTreeMap<String, Option<i64>>, DBError> {
+        let mut stmt = db.conn.prepare_cached("SELECT name, id FROM folders;")?;
+        let folders = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(folders)
+    }
+
+    /// How many copies are in `folder`.
+    pub fn folder_size(db: &Database, folder: &str) -> Result<usize, DBError> {
+        let size: u32 = db.conn.prepare_cached(
+            "SELECT COUNT(*) FROM folder_items WHERE folder = ?1;"
+        )?.query_row(&[folder], |row| row.get(0))?;
+        Ok(size as usize)
+    }
+
+    /// Where the copies of release `id` are, oldest first.
+    pub fn instances(db: &Database, id: i64) -> Result<Vec<Instance>, DBError> {
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT folder_items.folder, folders.id, folder_items.instance_id 
+            FROM folder_items JOIN folders ON folders.name = folder_items.folder
+            WHERE folder_items.release_id = ?1 AND folders.id IS NOT 0
+            ORDER BY folder_items.date_added, folder_items.idx;"
+        )?;
+        let instances = stmt.query_map([id], |row| Ok(Instance {
+            folder: row.get(0)?,
+            folder_id: row.get(1)?,
+            instance_id: row.get(2)?,
+        }))?.collect::<Result<_, _>>()?;
+        Ok(instances)
+    }
+
+    /// One release in the collection, by Discogs ID.
+    pub fn owned(db: &Database, id: i64) -> Result<Option<Release>, DBError> {
+        let mut stmt = db.conn.prepare_cached(&format!(
+            "{} WHERE releases.id = ?1 GROUP BY releases.id;", COLLECTION_SELECT
+        ))?;
+        Ok(get_releases(&mut stmt, [id])?.pop())
+    }
+
     //returns a vec of releases to support multiple results
-    pub fn release(query: &str, querytype: QueryType) -> Result<Vec<Release>, DBError> {
-        let table_to_query: String = match querytype {
-            QueryType::Collection => "All_".to_string(),
-            QueryType::Wantlist => "wantlist".to_string()
+    pub fn release(db: &Database, query: &str, querytype: QueryType) -> Result<Vec<Release>, DBError> {
+        search(db, &Filter::text(query), querytype)
+    }
+
+    //* free text with any words in it goes through the full-text index, best match first.
+    //* the index drops punctuation, so the results are then checked against
+    //* the text itself to keep quotes, % and _ meaningful.
+    //* everything else is a bound parameter, with LIKE wildcards escaped
+    pub fn search(db: &Database, filter: &Filter, querytype: QueryType) -> Result<Vec<Release>, DBError> {
+        let (table, index, rowid) = match querytype {
+            QueryType::Collection => ("releases", "releases_fts", "releases.id"),
+            QueryType::Wantlist => ("wantlist", "wantlist_fts", "wantlist.idx"),
         };
-        let conn = Connection::open(utils::database_file())?;
-        let mut stmt = conn.prepare(&format!(
-            "SELECT * FROM {} WHERE search_string LIKE '%{}%' ORDER BY title ASC;",
-            table_to_query, query
-        ))?;
-        let results = get_releases(&mut stmt, querytype)?;
-        
+        let text = filter.free_text();
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        let mut clauses: Vec<String> = Vec::new();
+        let mut join = String::new();
+        let ranked = match fts_query(&text) {
+            Some(fts_query) => {
+                params.push(Box::new(fts_query));
+                join = format!(
+                    "JOIN (SELECT rowid, rank FROM {0} WHERE {0} MATCH ?1) AS hits 
+                    ON hits.rowid = {1}", index, rowid
+                );
+                true
+            }
+            None => {
+                //* a query made only of stripped characters (e.g. `'`) would match everything
+                if !text.trim().is_empty() {
+                    let normalized = match utils::search_string(text.trim()) {
+                        s if s.trim().is_empty() => text.trim().to_string(),
+                        s => s,
+                    };
+                    params.push(Box::new(like_pattern(normalized.trim())));
+                    params.push(Box::new(like_pattern(text.trim())));
+                    clauses.push(format!(
+                        "({0}.search_string LIKE ?1 ESCAPE '\\' OR {0}.title LIKE ?2 ESCAPE '\\')", 
+                        table
+                    ));
+                }
+                false
+            }
+        };
+        for term in &filter.terms {
+            match term {
+                Term::Text(_) => {}
+                Term::Field(field, value) => {
+                    params.push(Box::new(like_pattern(value.trim())));
+                    let n = params.len();
+                    clauses.push(match field {
+                        Field::Title => format!(
+                            "({0}.title LIKE ?{1} ESCAPE '\\' OR {0}.search_string LIKE ?{1} ESCAPE '\\')",
+                            table, n
+                        ),
+                        _ => format!("{}.{} LIKE ?{} ESCAPE '\\'", table, field.column(), n),
+                    });
+                }
+                Term::Year(comparison, year) => {
+                    params.push(Box::new(*year));
+                    clauses.push(format!(
+                        "{}.year {} ?{}", table, comparison.as_str(), params.len()
+                    ));
+                }
+            }
+        }
+        let conditions = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+        let order = if ranked {"hits.rank ASC, title ASC"} else {"title ASC"};
+        let mut stmt = match querytype {
+            QueryType::Collection => db.conn.prepare_cached(&format!(
+                "{} {} {} GROUP BY releases.id ORDER BY {};",
+                COLLECTION_SELECT, join, conditions, order
+            ))?,
+            QueryType::Wantlist => db.conn.prepare_cached(&format!(
+                "SELECT wantlist.* FROM wantlist {} {} ORDER BY {};",
+                join, conditions, order
+            ))?,
+        };
+        let mut results = get_releases(&mut stmt, &params)?;
+        if ranked {
+            results.retain(|release| contains_terms(release, &text));
+        }
+
         Ok(results)
     }
 
-    pub fn all_titles() -> Result<Vec<String>, DBError> {
-        let conn = Connection::open(utils::database_file())?;
-        let mut stmt = conn.prepare(
-            "SELECT title FROM All_ ORDER BY title ASC;"
+    // every word in the query has to prefix-match something;
+    // words are quoted so nothing in them is read as FTS syntax
+    fn fts_query(query: &str) -> Option<String> {
+        let terms: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| !t.is_empty())
+            .map(|t| format!("\"{}\"*", t))
+            .collect();
+        if terms.is_empty() {
+            None
+        } else {
+            Some(terms.join(" "))
+        }
+    }
+
+    fn contains_terms(release: &Release, query: &str) -> bool {
+        let haystack = [
+            release.title.as_str(),
+            release.artist.as_str(),
+            release.search_string.as_str(),
+            &release.labels.join("\n"),
+            &release.formats.join("\n"),
+            &release.catnos.join("\n"),
+        ].join("\n").to_lowercase();
+
+        query.split_whitespace().all(|term| {
+            let normalized = utils::search_string(term).to_lowercase();
+            haystack.contains(&term.to_lowercase())
+                || (!normalized.is_empty() && haystack.contains(&normalized))
+        })
+    }
+
+    fn like_pattern(query: &str) -> String {
+        let mut pattern = String::with_capacity(query.len() + 2);
+        pattern.push('%');
+        for c in query.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('%');
+        pattern
+    }
+
+    pub fn all_titles(db: &Database) -> Result<Vec<String>, DBError> {
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT title FROM releases ORDER BY title ASC;"
         )?;
         let titles = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
         let mut titlevec = Vec::<String>::with_capacity(
-            size(QueryType::Collection).unwrap_or(100)
+            size(db, QueryType::Collection).unwrap_or(100)
         );
         for title in titles {
             titlevec.push(title?);
@@ -566,9 +2265,8 @@ pub mod query {
         Ok(titlevec)
     }
 
-    pub fn listenlog() -> Result<ListenLog, DBError> {
-        let conn = Connection::open(utils::database_file())?;
-        let mut stmt = conn.prepare(
+    pub fn listenlog(db: &Database) -> Result<ListenLog, DBError> {
+        let mut stmt = db.conn.prepare_cached(
             "SELECT * FROM listenlog;"
         )?;
         let results_iter = stmt.query_map(NO_PARAMS, |row| {
@@ -584,10 +2282,8 @@ pub mod query {
         Ok(listenlog)
     }
 
-    pub fn listenlog_by_title(title: &str) -> Result<ListenLog, DBError> {
-        let conn = Connection::open(utils::database_file())?;
-
-        let mut stmt = conn.prepare(
+    pub fn listenlog_by_title(db: &Database, title: &str) -> Result<ListenLog, DBError> {
+        let mut stmt = db.conn.prepare_cached(
             "SELECT * FROM listenlog WHERE title=(?1)"
         )?;
 
@@ -604,22 +2300,187 @@ pub mod query {
         Ok(listenlog)
     }
 
-    pub fn random() -> Result<Release, DBError> {
-        let conn = Connection::open(utils::database_file())?;
-        let mut stmt = conn.prepare(
-            "SELECT * FROM All_ ORDER BY RANDOM() LIMIT 1",
+    /// The cached details of a release, if they've been fetched.
+    pub fn details(db: &Database, id: i64) -> Result<Option<ReleaseDetails>, DBError> {
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT country, released, notes, fetched FROM details WHERE release_id = ?1;"
+        )?;
+        let mut rows = stmt.query_map([id], |row| {
+            Ok(ReleaseDetails {
+                id,
+                country: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                released: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                notes: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                tracklist: Vec::new(),
+                credits: Vec::new(),
+                identifiers: Vec::new(),
+                fetched: row.get(3)?,
+            })
+        })?;
+        let mut details = match rows.next() {
+            Some(details) => details?,
+            None => return Ok(None),
+        };
+
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT position, title, duration, heading FROM tracks WHERE release_id = ?1 ORDER BY idx;"
+        )?;
+        for track in stmt.query_map([id], |row| {
+            Ok(Track {
+                position: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                title: row.get(1)?,
+                duration: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                heading: row.get(3)?,
+            })
+        })? {
+            details.tracklist.push(track?);
+        }
+
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT name, role, tracks FROM credits WHERE release_id = ?1 ORDER BY idx;"
+        )?;
+        for credit in stmt.query_map([id], |row| {
+            Ok(Credit {
+                name: row.get(0)?,
+                role: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                tracks: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            })
+        })? {
+            details.credits.push(credit?);
+        }
+
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT kind, value, description FROM identifiers WHERE release_id = ?1 ORDER BY idx;"
+        )?;
+        for identifier in stmt.query_map([id], |row| {
+            Ok(Identifier {
+                kind: row.get(0)?,
+                value: row.get(1)?,
+                description: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            })
+        })? {
+            details.identifiers.push(identifier?);
+        }
+        Ok(Some(details))
+    }
+
+    /// Every collection value stored so far, oldest first.
+    pub fn value_history(db: &Database) -> Result<Vec<CollectionValue>, DBError> {
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT fetched, minimum, median, maximum FROM collection_value ORDER BY fetched;"
+        )?;
+        let values = stmt.query_map(NO_PARAMS, |row| {
+            Ok(CollectionValue {
+                fetched: row.get(0)?,
+                minimum: row.get(1)?,
+                median: row.get(2)?,
+                maximum: row.get(3)?,
+            })
+        })?;
+        Ok(values.collect::<Result<_, _>>()?)
+    }
+
+    /// What was paid for release `id`, earliest first.
+    pub fn purchases(db: &Database, id: i64) -> Result<Vec<Purchase>, DBError> {
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT release_id, amount, currency, date, store FROM purchases
+            WHERE release_id = ?1 ORDER BY date, id;"
+        )?;
+        let purchases = stmt.query_map([id], purchase)?;
+        Ok(purchases.collect::<Result<_, _>>()?)
+    }
+
+    /// The purchase of release `id` recorded last and its ID, if there's been one.
+    pub fn last_purchase(db: &Database, id: i64) -> Result<Option<(i64, Purchase)>, DBError> {
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT release_id, amount, currency, date, store, id FROM purchases
+            WHERE release_id = ?1 ORDER BY id DESC LIMIT 1;"
+        )?;
+        let mut purchases = stmt.query_map([id], |row| Ok((row.get(5)?, purchase(row)?)))?;
+        Ok(purchases.next().transpose()?)
+    }
+
+    fn purchase(row: &Row) -> Result<Purchase, rusqlite::Error> {
+        Ok(Purchase {
+            release_id: row.get(0)?,
+            amount: row.get(1)?,
+            currency: row.get(2)?,
+            date: row.get(3)?,
+            store: row.get(4)?,
+        })
+    }
+
+    /// The currency the last purchase was recorded in, if there's been one.
+    pub fn last_currency(db: &Database) -> Result<Option<String>, DBError> {
+        let mut stmt = db.conn.prepare_cached(
+            "SELECT currency FROM purchases ORDER BY id DESC LIMIT 1;"
         )?;
-        let mut selection = get_releases(&mut stmt, QueryType::Collection)?;
-        Ok(selection.remove(0))
+        let mut currencies = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+        Ok(currencies.next().transpose()?)
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum SpendingBy {
+        Month,
+        Year,
+        Folder,
     }
 
-    pub fn size(querytype: QueryType) -> Result<usize, DBError> {
-        let conn = Connection::open(utils::database_file())?;
+    //* the All folder holds everything, so it'd only repeat the total.
+    //* a release with copies in several folders counts once, under its own group,
+    //* so the folders still add up to what was spent
+    const SPENDING_BY_FOLDER: &str = "SELECT 
+        COALESCE(items.folder, 'No folder') AS grp, currency, SUM(amount), COUNT(*)
+        FROM purchases LEFT JOIN (
+            SELECT folder_items.release_id,
+                CASE WHEN COUNT(DISTINCT folder_items.folder) > 1
+                    THEN 'Several folders'
+                    ELSE MAX(folder_items.folder)
+                END AS folder
+            FROM folder_items JOIN folders ON folders.name = folder_items.folder
+            WHERE folders.id IS NOT 0
+            GROUP BY folder_items.release_id
+        ) AS items ON items.release_id = purchases.release_id
+        GROUP BY grp, currency
+        ORDER BY MAX(items.folder IS NULL), grp, currency;";
+
+    /// What's been spent in each month, year or folder, oldest or alphabetically first.
+    /// Amounts in different currencies are kept apart rather than converted.
+    pub fn spending(db: &Database, by: SpendingBy) -> Result<Vec<Spending>, DBError> {
+        let sql = match by {
+            SpendingBy::Month => "SELECT substr(date, 1, 7) AS grp, currency, SUM(amount), COUNT(*)
+                FROM purchases GROUP BY grp, currency ORDER BY grp, currency;",
+            SpendingBy::Year => "SELECT substr(date, 1, 4) AS grp, currency, SUM(amount), COUNT(*)
+                FROM purchases GROUP BY grp, currency ORDER BY grp, currency;",
+            SpendingBy::Folder => SPENDING_BY_FOLDER,
+        };
+        let mut stmt = db.conn.prepare_cached(sql)?;
+        let spending = stmt.query_map(NO_PARAMS, |row| {
+            Ok(Spending {
+                group: row.get(0)?,
+                currency: row.get(1)?,
+                total: row.get(2)?,
+                purchases: row.get(3)?,
+            })
+        })?;
+        Ok(spending.collect::<Result<_, _>>()?)
+    }
+
+    pub fn random(db: &Database) -> Result<Release, DBError> {
+        let mut stmt = db.conn.prepare_cached(&format!(
+            "{} GROUP BY releases.id ORDER BY RANDOM() LIMIT 1;",
+            COLLECTION_SELECT
+        ))?;
+        let mut selection = get_releases(&mut stmt, NO_PARAMS)?;
+        selection.pop().ok_or_else(|| "error: the collection is empty".into())
+    }
+
+    pub fn size(db: &Database, querytype: QueryType) -> Result<usize, DBError> {
         let query = match querytype {
             QueryType::Collection => "collection",
             QueryType::Wantlist => "wantlist"
         };
-        let size: u32 = conn.query_row(
+        let size: u32 = db.conn.query_row(
             &format!("SELECT {} FROM profile;", query),
             NO_PARAMS,
             |row| row.get(0)
@@ -627,12 +2488,23 @@ pub mod query {
         Ok(size as usize)
     }
 
-    fn get_releases(stmt: &mut Statement, querytype: QueryType) -> Result<Vec<Release>, DBError> {
-        let mut folder: Vec<Release> = Vec::with_capacity(size(querytype).unwrap_or(100));
-
-            let contents = stmt.query_map(NO_PARAMS, |row| {
-                let labels_raw: String = row.get(7)?;
-                let formats_raw: String = row.get(8)?;
+    fn get_releases<P>(stmt: &mut Statement, params: P) 
+        -> Result<Vec<Release>, DBError> 
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        let mut folder: Vec<Release> = Vec::new();
+        //* the wantlist has no copies, so no instance IDs, ratings or notes either
+        let has_instances = stmt.column_index("instance_id").is_ok();
+        let has_notes = stmt.column_index("notes").is_ok();
+        let has_ratings = stmt.column_index("rating").is_ok();
+
+            let contents = stmt.query_map(params, |row| {
+                let labels_raw: String = row.get("labels")?;
+                let formats_raw: String = row.get("formats")?;
+                //* null until the first sync after the catnos column was added
+                let catnos_raw: Option<String> = row.get("catnos")?;
 
                 let labels = labels_raw.as_str()
                     .split(':')
@@ -642,16 +2514,28 @@ pub mod query {
                     .split(':')
                     .map(|s| s.to_string())
                     .collect();
+                let catnos = split_list(catnos_raw);
                 
                 Ok(Release {
-                    id: row.get(2)?,
-                    search_string: String::new(),
-                    title: row.get(4)?,
-                    artist: row.get(5)?,
-                    year: row.get(6)?,
+                    id: row.get("id")?,
+                    search_string: row.get::<_, Option<String>>("search_string")?
+                        .unwrap_or_default(),
+                    title: row.get("title")?,
+                    artist: row.get("artist")?,
+                    year: row.get("year")?,
                     labels,
+                    catnos,
                     formats,
-                    date_added: row.get(9)?,
+                    date_added: row.get("date_added")?,
+                    instance_id: if has_instances {row.get("instance_id")?} else {None},
+                    rating: if has_ratings {row.get("rating")?} else {0},
+                    //* null until the first sync after these columns were added
+                    genres: split_list(row.get("genres")?),
+                    styles: split_list(row.get("styles")?),
+                    master_id: row.get("master_id")?,
+                    thumb: row.get::<_, Option<String>>("thumb")?.unwrap_or_default(),
+                    cover_image: row.get::<_, Option<String>>("cover_image")?.unwrap_or_default(),
+                    notes: if has_notes {super::notes_from_sql(row.get("notes")?)?} else {Vec::new()},
                 })
             })?;
             for release in contents {
@@ -659,48 +2543,745 @@ pub mod query {
             }
             Ok(folder)
     }
+
+    //* lists are stored joined with colons; null or empty is an empty list
+    fn split_list(raw: Option<String>) -> Vec<String> {
+        raw.as_deref()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.split(':').map(|s| s.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rusqlite::params;
+        use crate::app::database::migrate;
+
+        const TITLES: &[&str] = &[
+            "Don't Stop",
+            "100% Silk",
+            "100 Silk Road",
+            "Under_score",
+            "Understatement",
+        ];
+
+        fn database() -> Database {
+            let db = Database::open_path(":memory:").unwrap();
+            migrate::run(&db.conn).unwrap();
+            db.conn.execute("INSERT INTO folders (name, id) VALUES ('All', 0);", NO_PARAMS).unwrap();
+            for (id, title) in TITLES.iter().enumerate() {
+                insert(&db, id as i64, title, "Artist", "Label", "");
+            }
+            db
+        }
+
+        fn insert(db: &Database, id: i64, title: &str, artist: &str, labels: &str, catnos: &str) {
+            let search_string = utils::search_string(title);
+            db.conn.execute(
+                "INSERT INTO releases 
+                (id, hash, search_string, title, artist, year, labels, formats, catnos)
+                VALUES (?1, 0, ?2, ?3, ?4, 2000, ?5, 'Vinyl', ?6);",
+                params![id, search_string, title, artist, labels, catnos],
+            ).unwrap();
+            db.conn.execute(
+                "INSERT INTO folder_items (folder, release_id, date_added)
+                VALUES ('All', ?1, '2020-03-01T00:00:00+00:00');",
+                params![id],
+            ).unwrap();
+            db.conn.execute(
+                "INSERT INTO wantlist 
+                (hash, id, search_string, title, artist, year, labels, formats, date_added, catnos)
+                VALUES (0, ?1, ?2, ?3, ?4, 2000, ?5, 'Vinyl', 
+                '2020-03-01T00:00:00+00:00', ?6);",
+                params![id, search_string, title, artist, labels, catnos],
+            ).unwrap();
+        }
+
+        fn titles(db: &Database, query: &str, querytype: QueryType) -> Vec<String> {
+            search(db, &Filter::text(query), querytype).unwrap()
+                .into_iter()
+                .map(|r| r.title)
+                .collect()
+        }
+
+        #[test]
+        fn test_apostrophes_match_literally() {
+            let db = database();
+            for querytype in &[QueryType::Collection, QueryType::Wantlist] {
+                assert_eq!(titles(&db, "Don't Stop", *querytype), vec!["Don't Stop"]);
+                assert_eq!(titles(&db, "Dont Stop", *querytype), vec!["Don't Stop"]);
+                assert_eq!(titles(&db, "'", *querytype), vec!["Don't Stop"]);
+                assert!(titles(&db, "'; DROP TABLE releases; --", *querytype).is_empty());
+            }
+            assert_eq!(query_count(&db), TITLES.len());
+        }
+
+        #[test]
+        fn test_percent_signs_match_literally() {
+            let db = database();
+            for querytype in &[QueryType::Collection, QueryType::Wantlist] {
+                assert_eq!(titles(&db, "100%", *querytype), vec!["100% Silk"]);
+                assert_eq!(titles(&db, "%", *querytype), vec!["100% Silk"]);
+            }
+        }
+
+        #[test]
+        fn test_underscores_match_literally() {
+            let db = database();
+            for querytype in &[QueryType::Collection, QueryType::Wantlist] {
+                assert_eq!(titles(&db, "Under_", *querytype), vec!["Under_score"]);
+                assert_eq!(titles(&db, "_", *querytype), vec!["Under_score"]);
+            }
+        }
+
+        #[test]
+        fn test_double_quotes_do_not_break_the_query() {
+            let db = database();
+            assert_eq!(
+                titles(&db, "\"Silk\"", QueryType::Collection), 
+                vec!["100% Silk", "100 Silk Road"]
+            );
+        }
+
+        #[test]
+        fn test_search_covers_artist_label_and_catno() {
+            let db = database();
+            insert(&db, 10, "Geogaddi", "Boards Of Canada", "Warp Records", "WARPLP101");
+            insert(&db, 11, "Drukqs", "Aphex Twin", "Warp Records", "WARPCD92");
+            insert(&db, 12, "Dummy", "Portishead", "Go! Beat", "828 522-1");
+            for querytype in &[QueryType::Collection, QueryType::Wantlist] {
+                assert_eq!(titles(&db, "boards of canada", *querytype), vec!["Geogaddi"]);
+                assert_eq!(titles(&db, "warp", *querytype), vec!["Drukqs", "Geogaddi"]);
+                assert_eq!(titles(&db, "warpcd", *querytype), vec!["Drukqs"]);
+                assert_eq!(titles(&db, "warp aphex", *querytype), vec!["Drukqs"]);
+                assert_eq!(titles(&db, "go! beat", *querytype), vec!["Dummy"]);
+            }
+        }
+
+        #[test]
+        fn test_title_matches_rank_first() {
+            let db = database();
+            insert(&db, 10, "Blue Lines", "Massive Attack", "Wild Bunch", "WBRLP1");
+            insert(&db, 11, "Mezzanine", "Massive Attack", "Blue Note", "WBRLP4");
+            insert(&db, 12, "Kind Of Blue", "Miles Davis", "Columbia", "CS 8163");
+            // a title hit beats a label hit, and the shorter title wins among titles
+            assert_eq!(
+                titles(&db, "blue", QueryType::Collection),
+                vec!["Blue Lines", "Kind Of Blue", "Mezzanine"]
+            );
+        }
+
+        #[test]
+        fn test_field_filters() {
+            let db = database();
+            insert(&db, 10, "Geogaddi", "Boards Of Canada", "Warp Records", "WARPLP101");
+            insert(&db, 11, "Twoism", "Boards Of Canada", "Music70", "M70-001");
+            db.conn.execute("UPDATE releases SET year = 1995 WHERE id = 11;", NO_PARAMS).unwrap();
+            db.conn.execute("UPDATE wantlist SET year = 1995 WHERE id = 11;", NO_PARAMS).unwrap();
+
+            let filter = Filter {
+                terms: vec![
+                    Term::Field(Field::Artist, String::from("boards of canada")),
+                    Term::Year(Comparison::Gt, 1995),
+                    Term::Field(Field::Format, String::from("vinyl")),
+                    Term::Field(Field::Label, String::from("warp")),
+                ]
+            };
+            for querytype in &[QueryType::Collection, QueryType::Wantlist] {
+                let found: Vec<String> = search(&db, &filter, *querytype).unwrap()
+                    .into_iter().map(|r| r.title).collect();
+                assert_eq!(found, vec!["Geogaddi"]);
+
+                let older = Filter {
+                    terms: vec![
+                        Term::Field(Field::Artist, String::from("Boards")),
+                        Term::Year(Comparison::Le, 1995),
+                    ]
+                };
+                let found: Vec<String> = search(&db, &older, *querytype).unwrap()
+                    .into_iter().map(|r| r.title).collect();
+                assert_eq!(found, vec!["Twoism"]);
+
+                // free text and fields combine
+                let mixed = Filter {
+                    terms: vec![
+                        Term::Text(String::from("100")),
+                        Term::Field(Field::Title, String::from("silk road")),
+                    ]
+                };
+                let found: Vec<String> = search(&db, &mixed, *querytype).unwrap()
+                    .into_iter().map(|r| r.title).collect();
+                assert_eq!(found, vec!["100 Silk Road"]);
+            }
+        }
+
+        #[test]
+        fn test_genre_and_style_filters() {
+            let db = database();
+            insert(&db, 10, "Geogaddi", "Boards Of Canada", "Warp Records", "WARPLP101");
+            insert(&db, 11, "Mezzanine", "Massive Attack", "Virgin", "WBRLP4");
+            for table in &["releases", "wantlist"] {
+                db.conn.execute(&format!(
+                    "UPDATE {} SET genres = 'Electronic', styles = 'IDM:Downtempo' WHERE id = 10;", table
+                ), NO_PARAMS).unwrap();
+                db.conn.execute(&format!(
+                    "UPDATE {} SET genres = 'Electronic:Rock', styles = 'Trip Hop' WHERE id = 11;", table
+                ), NO_PARAMS).unwrap();
+            }
+
+            for querytype in &[QueryType::Collection, QueryType::Wantlist] {
+                let found = |filter: Filter| -> Vec<String> {
+                    search(&db, &filter, *querytype).unwrap()
+                        .into_iter().map(|r| r.title).collect()
+                };
+                let mut electronic = found(Filter {
+                    terms: vec![Term::Field(Field::Genre, String::from("electronic"))]
+                });
+                electronic.sort();
+                assert_eq!(electronic, vec!["Geogaddi", "Mezzanine"]);
+                assert_eq!(
+                    found(Filter {terms: vec![Term::Field(Field::Style, String::from("downtempo"))]}),
+                    vec!["Geogaddi"]
+                );
+                assert_eq!(
+                    found(Filter {terms: vec![
+                        Term::Field(Field::Genre, String::from("rock")),
+                        Term::Field(Field::Style, String::from("trip hop")),
+                    ]}),
+                    vec!["Mezzanine"]
+                );
+            }
+            // and they come back out as lists
+            let geogaddi = search(&db, &Filter::text("geogaddi"), QueryType::Collection).unwrap().remove(0);
+            assert_eq!(geogaddi.genres, vec!["Electronic"]);
+            assert_eq!(geogaddi.styles, vec!["IDM", "Downtempo"]);
+        }
+
+        #[test]
+        fn test_spending_by_month_year_and_folder() {
+            use chrono::NaiveDate;
+            use crate::app::database::update;
+
+            let db = database();
+            db.conn.execute(
+                "INSERT INTO folders (name, id) VALUES ('Favourites', 2), ('Jazz', 3);", NO_PARAMS
+            ).unwrap();
+            //* release 4 has a copy in each folder
+            db.conn.execute(
+                "INSERT INTO folder_items (folder, release_id, date_added)
+                VALUES ('Favourites', 1, '2020-03-01T00:00:00+00:00'),
+                ('Favourites', 4, '2020-03-01T00:00:00+00:00'),
+                ('Jazz', 4, '2020-03-02T00:00:00+00:00');",
+                NO_PARAMS
+            ).unwrap();
+            let purchase = |release_id: i64, amount: i64, currency: &str, date: (i32, u32, u32)| Purchase {
+                release_id,
+                amount,
+                currency: currency.to_string(),
+                date: NaiveDate::from_ymd(date.0, date.1, date.2),
+                store: None,
+            };
+            for bought in &[
+                purchase(0, 2000, "USD", (2025, 12, 30)),
+                purchase(1, 1550, "USD", (2026, 1, 2)),
+                purchase(2, 1000, "USD", (2026, 1, 20)),
+                purchase(3, 1200, "EUR", (2026, 1, 21)),
+                purchase(4, 800, "USD", (2026, 1, 25)),
+                //* no longer in the collection
+                purchase(99, 500, "USD", (2026, 2, 1)),
+            ] {
+                update::add_purchase(&db, bought).unwrap();
+            }
+
+            let spent = |by: SpendingBy| -> Vec<(String, String, i64, u32)> {
+                spending(&db, by).unwrap().into_iter()
+                    .map(|s| (s.group, s.currency, s.total, s.purchases))
+                    .collect()
+            };
+            let row = |group: &str, currency: &str, total: i64, purchases: u32| {
+                (group.to_string(), currency.to_string(), total, purchases)
+            };
+            assert_eq!(spent(SpendingBy::Month), vec![
+                row("2025-12", "USD", 2000, 1),
+                row("2026-01", "EUR", 1200, 1),
+                row("2026-01", "USD", 3350, 3),
+                row("2026-02", "USD", 500, 1),
+            ]);
+            assert_eq!(spent(SpendingBy::Year), vec![
+                row("2025", "USD", 2000, 1),
+                row("2026", "EUR", 1200, 1),
+                row("2026", "USD", 3850, 4),
+            ]);
+            assert_eq!(spent(SpendingBy::Folder), vec![
+                row("Favourites", "USD", 1550, 1),
+                row("Several folders", "USD", 800, 1),
+                row("No folder", "EUR", 1200, 1),
+                row("No folder", "USD", 3500, 3),
+            ]);
+            // every purchase is in exactly one folder, so the folders add up to the years
+            let total = |by: SpendingBy, currency: &str| -> (i64, u32) {
+                spending(&db, by).unwrap().into_iter()
+                    .filter(|s| s.currency == currency)
+                    .fold((0, 0), |(total, count), s| (total + s.total, count + s.purchases))
+            };
+            for currency in &["USD", "EUR"] {
+                assert_eq!(total(SpendingBy::Folder, currency), total(SpendingBy::Year, currency));
+            }
+
+            assert_eq!(last_currency(&db).unwrap(), Some(String::from("USD")));
+        }
+
+        #[test]
+        fn test_purchases_are_kept_until_corrected() {
+            use chrono::NaiveDate;
+            use crate::app::database::update;
+
+            let db = database();
+            let bought = Purchase {
+                release_id: 2,
+                amount: 1000,
+                currency: String::from("USD"),
+                date: NaiveDate::from_ymd(2026, 1, 20),
+                store: None,
+            };
+            // two copies on the same day are two purchases
+            update::add_purchase(&db, &bought).unwrap();
+            update::add_purchase(&db, &bought).unwrap();
+            assert_eq!(purchases(&db, 2).unwrap(), vec![bought.clone(), bought.clone()]);
+
+            // a correction only touches the one recorded last
+            let (id, last) = last_purchase(&db, 2).unwrap().unwrap();
+            assert_eq!(last, bought);
+            let corrected = Purchase {
+                amount: 1100,
+                store: Some(String::from("Rough Trade")),
+                ..bought.clone()
+            };
+            update::correct_purchase(&db, id, &corrected).unwrap();
+            assert_eq!(purchases(&db, 2).unwrap(), vec![bought, corrected]);
+            assert!(last_purchase(&db, 3).unwrap().is_none());
+        }
+
+        fn query_count(db: &Database) -> usize {
+            db.conn.query_row("SELECT COUNT(*) FROM releases;", NO_PARAMS, |row| row.get::<_, i64>(0))
+                .unwrap() as usize
+        }
+    }
 }
 
-pub mod purge {
-    use std::error::Error;
-    use std::fs;
+pub mod backup {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
     use rusqlite::{
         Connection,
-        NO_PARAMS
+        DatabaseName,
+        NO_PARAMS,
+        backup::{Backup, Progress},
     };
-    use super::DBError;
+    use super::{Database, DBError, admin, migrate};
     use crate::utils;
 
-    pub fn folders() -> Result<(), DBError> {
-        let conn = Connection::open(utils::database_file())?;
+    /// How many snapshots are kept before the oldest ones are deleted.
+    pub const SNAPSHOTS_KEPT: usize = 5;
 
-        let mut folder_names: Vec<String> = Vec::new();
-        let mut stmt = conn.prepare("SELECT name FROM folders;")?;
-        let folderquery = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
-        for folder in folderquery {
-            folder_names.push(folder?);
+    impl Database {
+        /// Copies the database to `path`. This goes through SQLite's backup API,
+        /// so the copy is consistent even if something is writing at the same time.
+        pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<(), DBError> {
+            self.conn.backup(DatabaseName::Main, path, None)?;
+            Ok(())
         }
-        for name in folder_names {
-            let sqlcommand = format!("DROP TABLE \"{}\"", name);
-            conn.execute(&sqlcommand, NO_PARAMS)?;
+
+        /// Replaces the contents of the database with the backup at `path`.
+        /// The backup is loaded, migrated and integrity checked on the side first,
+        /// and the current contents are snapshotted before anything is overwritten.
+        pub fn restore_from<P: AsRef<Path>>(&mut self, path: P) -> Result<(), DBError> {
+            //* opening a path that isn't there would create an empty database
+            if !path.as_ref().exists() {
+                return Err(DBError::FileNotExistErr)
+            }
+            let mut restored = Connection::open_in_memory()?;
+            restored.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+            migrate::run(&restored)?;
+            admin::check_integrity_of(&restored)?;
+
+            self.snapshot()?;
+            self.conn.flush_prepared_statement_cache();
+            Backup::new(&restored, &mut self.conn)?
+                .run_to_completion(100, Duration::from_millis(0), None)?;
+            Ok(())
         }
-        table("folders")?;
-        Ok(())
+
+        /// Saves a copy of the database to the snapshot directory next to it,
+        /// deleting the oldest snapshots past `SNAPSHOTS_KEPT`.
+        /// Returns where the snapshot went, or `None` if there was nothing to save.
+        pub fn snapshot(&self) -> Result<Option<PathBuf>, DBError> {
+            let path = match &self.path {
+                Some(path) => path,
+                None => return Ok(None),
+            };
+            let tables: u32 = self.conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master;", NO_PARAMS, |row| row.get(0)
+            )?;
+            if tables == 0 {
+                return Ok(None)
+            }
+            let dir = snapshot_dir(path);
+            fs::create_dir_all(&dir)?;
+
+            let snapshot = dir.join(format!("{}-{}.db", 
+                file_stem(path), 
+                utils::get_utc_now().format("%Y%m%d-%H%M%S%.3f")
+            ));
+            self.backup_to(&snapshot)?;
+
+            let mut old = snapshots(path)?;
+            if old.len() > SNAPSHOTS_KEPT {
+                for stale in old.drain(..old.len() - SNAPSHOTS_KEPT) {
+                    fs::remove_file(stale)?;
+                }
+            }
+            Ok(Some(snapshot))
+        }
+    }
+
+    /// Lists the snapshots taken of the database at `path`, oldest first.
+    pub fn snapshots(path: &Path) -> Result<Vec<PathBuf>, DBError> {
+        let dir = snapshot_dir(path);
+        if !dir.exists() {
+            return Ok(Vec::new())
+        }
+        let prefix = format!("{}-", file_stem(path));
+        let mut found = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?.path();
+            let name = entry.file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            if name.starts_with(&prefix) && name.ends_with(".db") {
+                found.push(entry);
+            }
+        }
+        //* the timestamps sort the same way as the strings do
+        found.sort();
+        Ok(found)
+    }
+
+    fn snapshot_dir(path: &Path) -> PathBuf {
+        path.parent().unwrap_or_else(|| Path::new(".")).join("snapshots")
     }
 
-    //* DO NOT CALL THIS ON THE FOLDERS TABLE OUTSIDE OF PURGE::FOLDERS
-    //* YOU **WILL** GET ORPHAN TABLES
-    pub fn table(tablename: &str) -> Result<(), DBError> {
-        let conn = Connection::open(utils::database_file())?;
-        let sqlcommand = format!("DELETE FROM \"{}\"", tablename);
-        conn.execute(&sqlcommand, NO_PARAMS)?;
+    fn file_stem(path: &Path) -> &str {
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("cogsy_data")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::process;
+
+        // a scratch directory per test, so the real data directory is never touched
+        fn scratch(name: &str) -> PathBuf {
+            let dir = std::env::temp_dir()
+                .join(format!("cogsy-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        fn listens(db: &Database) -> u32 {
+            db.conn.query_row(
+                "SELECT COUNT(*) FROM listenlog;", NO_PARAMS, |row| row.get(0)
+            ).unwrap()
+        }
+
+        #[test]
+        fn test_backup_and_restore() {
+            let dir = scratch("restore");
+            let mut db = Database::open_path(dir.join("cogsy_data.db")).unwrap();
+            db.conn.execute_batch(include_str!("../../tests/fixtures/db/v1.sql")).unwrap();
+            admin::init_db(&db).unwrap();
+            let before = listens(&db);
+
+            db.backup_to(dir.join("backup.db")).unwrap();
+            db.conn.execute("DELETE FROM listenlog;", NO_PARAMS).unwrap();
+            assert_eq!(listens(&db), 0);
+
+            db.restore_from(dir.join("backup.db")).unwrap();
+            assert_eq!(listens(&db), before);
+            admin::check_integrity(&db).unwrap();
+            // what was there before the restore is kept as a snapshot
+            assert_eq!(snapshots(&dir.join("cogsy_data.db")).unwrap().len(), 1);
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn test_broken_backup_is_not_restored() {
+            let dir = scratch("broken");
+            let mut db = Database::open_path(dir.join("cogsy_data.db")).unwrap();
+            admin::init_db(&db).unwrap();
+            db.conn.execute("INSERT INTO listenlog VALUES ('2021-01-01T00:00:00+00:00', 1, 'Amber');", NO_PARAMS)
+                .unwrap();
+
+            let broken = Database::open_path(dir.join("broken.db")).unwrap();
+            admin::init_db(&broken).unwrap();
+            broken.conn.execute("DROP TABLE folder_items;", NO_PARAMS).unwrap();
+            drop(broken);
+
+            assert!(db.restore_from(dir.join("broken.db")).is_err());
+            assert!(db.restore_from(dir.join("missing.db")).is_err());
+            assert_eq!(listens(&db), 1);
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn test_snapshots_rotate() {
+            let dir = scratch("rotate");
+            let path = dir.join("cogsy_data.db");
+            let db = Database::open_path(&path).unwrap();
+            assert_eq!(db.snapshot().unwrap(), None);
+
+            admin::init_db(&db).unwrap();
+            for _ in 0..SNAPSHOTS_KEPT + 2 {
+                db.snapshot().unwrap().unwrap();
+                std::thread::sleep(Duration::from_millis(2));
+            }
+            assert_eq!(snapshots(&path).unwrap().len(), SNAPSHOTS_KEPT);
+            assert_eq!(Database::open_path(":memory:").unwrap().snapshot().unwrap(), None);
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
+
+pub mod purge {
+    use rusqlite::{
+        Connection,
+        Transaction,
+        NO_PARAMS,
+        types::Value,
+    };
+    use super::{Database, DBError, migrate};
+
+    /// Tables holding data that only exists on this machine and
+    /// cannot be pulled from Discogs again. Resets leave them alone.
+    pub const LOCAL_TABLES: &[&str] = &["listenlog", "collection_value", "purchases"];
+
+    /*
+    Resetting leaves the schema version where it is and never runs a
+    migration again. Every table that came from Discogs is dropped and
+    created again empty, the way it looks at the database's version, which
+    is read off a scratch database migrated to the same version.
+    A database that isn't fully upgraded stays at its version,
+    and `admin::init_db` upgrades it the usual way afterwards.
+    */
+
+    /// Empties everything that came from Discogs and keeps the local-only tables.
+    pub fn discogs(db: &Database) -> Result<(), DBError> {
+        let version = migrate::version(&db.conn)?;
+        if version > migrate::SCHEMA_VERSION {
+            return Err(DBError::SchemaVersionErr(version))
+        }
+        let scratch = Connection::open_in_memory()?;
+        migrate::upgrade(&scratch, version)?;
+
+        db.snapshot()?;
+        db.conn.flush_prepared_statement_cache();
+        let tx = db.conn.unchecked_transaction()?;
+        drop_tables(&tx, LOCAL_TABLES)?;
+        recreate_tables(&scratch, &tx)?;
+        tx.commit()?;
         Ok(())
     }
 
-    //* This will have to be called if orphan folders are detected.
-    pub fn complete() -> Result<(), Box<dyn Error>> {
-        //i'd rather systematically drop tables in the folder, but this will do for now
-        fs::remove_file(utils::database_file())?;
+    /// Drops every table, listening history included.
+    pub fn complete(db: &Database) -> Result<(), DBError> {
+        db.snapshot()?;
+        db.conn.flush_prepared_statement_cache();
+        let tx = db.conn.unchecked_transaction()?;
+        drop_tables(&tx, &[])?;
+        //* an empty file is version 0, the migrations start over from there
+        tx.pragma_update(None, "user_version", &0)?;
+        tx.commit()?;
         Ok(()) //You just deleted your entire database. Congrats.
     }
-}
\ No newline at end of file
+
+    //* tables are dropped through the open connection rather than deleting the file,
+    //* which would leave everyone holding the connection writing to an unlinked file
+    fn drop_tables(tx: &Transaction, keep: &[&str]) -> Result<(), DBError> {
+        //* virtual tables first, they take their shadow tables with them
+        for query in &[
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND sql LIKE 'CREATE VIRTUAL TABLE%';",
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%';",
+        ] {
+            let tables: Vec<String> = {
+                let mut stmt = tx.prepare(query)?;
+                let names = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+                names.collect::<Result<_, _>>()?
+            };
+            for table in tables.iter().filter(|t| !keep.contains(&t.as_str())) {
+                tx.execute(&format!("DROP TABLE IF EXISTS \"{}\";", table), NO_PARAMS)?;
+            }
+        }
+        Ok(())
+    }
+
+    // creates the tables, indexes and triggers `schema` has, apart from the local tables
+    fn recreate_tables(schema: &Connection, tx: &Transaction) -> Result<(), DBError> {
+        let entries: Vec<(String, String, String, String)> = {
+            let mut stmt = schema.prepare(
+                "SELECT type, name, tbl_name, sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY rowid;"
+            )?;
+            let entries = stmt.query_map(NO_PARAMS, |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?;
+            entries.collect::<Result<_, _>>()?
+        };
+        let virtual_tables: Vec<&str> = entries.iter()
+            .filter(|(_, _, _, sql)| sql.starts_with("CREATE VIRTUAL TABLE"))
+            .map(|(_, name, _, _)| name.as_str())
+            .collect();
+        for (kind, name, table, sql) in &entries {
+            //* shadow tables come with their virtual table
+            let shadow = kind == "table" && virtual_tables.iter()
+                .any(|vt| name.starts_with(&format!("{}_", vt)));
+            if !shadow && !LOCAL_TABLES.contains(&table.as_str()) {
+                tx.execute_batch(sql)?;
+            }
+        }
+        //* full-text settings like the ranking live in the config shadow table
+        for vt in &virtual_tables {
+            let mut stmt = schema.prepare(&format!("SELECT k, v FROM \"{}_config\";", vt))?;
+            let settings = stmt.query_map(NO_PARAMS, |row| Ok((row.get::<_, Value>(0)?, row.get::<_, Value>(1)?)))?;
+            for setting in settings {
+                let (k, v) = setting?;
+                tx.execute(&format!("INSERT OR REPLACE INTO \"{}_config\" (k, v) VALUES (?1, ?2);", vt), &[k, v])?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::app::database::{admin, migrate};
+
+        #[test]
+        fn test_complete_leaves_an_empty_database() {
+            let db = Database::open_path(":memory:").unwrap();
+            db.conn.execute_batch(include_str!("../../tests/fixtures/db/v1.sql")).unwrap();
+            admin::init_db(&db).unwrap();
+
+            complete(&db).unwrap();
+
+            assert_eq!(migrate::version(&db.conn).unwrap(), 0);
+            let tables: u32 = db.conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(tables, 0);
+
+            // and the same connection can start over
+            admin::init_db(&db).unwrap();
+            admin::check_integrity(&db).unwrap();
+        }
+
+        #[test]
+        fn test_discogs_keeps_the_value_history() {
+            let db = Database::open_path(":memory:").unwrap();
+            admin::init_db(&db).unwrap();
+            db.conn.execute(
+                "INSERT INTO collection_value VALUES ('2026-01-01T00:00:00+00:00', '$1.00', '$2.00', '$3.00');",
+                NO_PARAMS
+            ).unwrap();
+
+            discogs(&db).unwrap();
+            admin::init_db(&db).unwrap();
+            admin::check_integrity(&db).unwrap();
+
+            let kept: u32 = db.conn.query_row(
+                "SELECT COUNT(*) FROM collection_value;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(kept, 1);
+        }
+
+        #[test]
+        fn test_discogs_keeps_the_listenlog() {
+            let db = Database::open_path(":memory:").unwrap();
+            db.conn.execute_batch(include_str!("../../tests/fixtures/db/v1.sql")).unwrap();
+            admin::init_db(&db).unwrap();
+            let listens: u32 = db.conn.query_row(
+                "SELECT COUNT(*) FROM listenlog;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert!(listens > 0);
+
+            //* a broken table is what sends a database through here
+            db.conn.execute("DROP TABLE folder_items;", NO_PARAMS).unwrap();
+            assert!(admin::check_integrity(&db).is_err());
+
+            discogs(&db).unwrap();
+            // nothing is migrated again, the broken table is simply there again
+            assert_eq!(migrate::version(&db.conn).unwrap(), migrate::SCHEMA_VERSION);
+            admin::check_integrity(&db).unwrap();
+
+            let kept: u32 = db.conn.query_row(
+                "SELECT COUNT(*) FROM listenlog;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(kept, listens);
+            let releases: u32 = db.conn.query_row(
+                "SELECT COUNT(*) FROM releases;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(releases, 0);
+        }
+
+        #[test]
+        fn test_discogs_keeps_the_schema_version() {
+            let db = Database::open_path(":memory:").unwrap();
+            db.conn.execute_batch(include_str!("../../tests/fixtures/db/v2.sql")).unwrap();
+            migrate::upgrade(&db.conn, 5).unwrap();
+
+            discogs(&db).unwrap();
+
+            // the tables look the way version 5 has them, so the rest of the migrations still apply
+            assert_eq!(migrate::version(&db.conn).unwrap(), 5);
+            admin::init_db(&db).unwrap();
+            admin::check_integrity(&db).unwrap();
+            let releases: u32 = db.conn.query_row(
+                "SELECT COUNT(*) FROM releases;", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(releases, 0);
+        }
+
+        #[test]
+        fn test_discogs_keeps_the_search_ranking() {
+            let db = Database::open_path(":memory:").unwrap();
+            admin::init_db(&db).unwrap();
+            let ranking = |db: &Database| -> String {
+                db.conn.query_row(
+                    "SELECT v FROM releases_fts_config WHERE k = 'rank';", NO_PARAMS, |row| row.get(0)
+                ).unwrap()
+            };
+            let before = ranking(&db);
+
+            discogs(&db).unwrap();
+
+            assert_eq!(ranking(&db), before);
+            db.conn.execute(
+                "INSERT INTO releases (id, hash, search_string, title, artist) 
+                VALUES (1, 0, 'geogaddi', 'Geogaddi', 'Boards Of Canada');",
+                NO_PARAMS
+            ).unwrap();
+            let found: u32 = db.conn.query_row(
+                "SELECT COUNT(*) FROM releases_fts WHERE releases_fts MATCH 'geogaddi';", NO_PARAMS, |row| row.get(0)
+            ).unwrap();
+            assert_eq!(found, 1);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+
+use crate::app::{
+    Release,
+    database::{Database, query::{self, Instance}, update as dbupdate},
+    request::*,
+    response,
+};
+
+/*
+* Changes made to the user's Discogs account.
+* Each one goes to Discogs first and is written to the local database
+* as soon as Discogs accepts it, so there's no need to sync afterwards.
+*/
+
+/// Adds release `id` to the wantlist (PUT /users/{u}/wants/{id}).
+pub fn add_want(db: &Database, requester: &DiscogsClient, username: &str, id: i64) -> Result<Release, UpdateError> {
+    let url = build_url(requester, ParseType::Want(id), username);
+    let text = read_response(requester.send(|client| client.put(&url))?)
+        .map_err(|e| match e {
+            UpdateError::NotFoundError => UpdateError::NoReleaseError(id),
+            e => e,
+        })?;
+    let entry: Value = parse_response(&text, "the new want")?;
+    let release = parse_release(&entry)?;
+    dbupdate::add_want(db, &release)?;
+    Ok(release)
+}
+
+/// Removes release `id` from the wantlist (DELETE /users/{u}/wants/{id}).
+/// Gives back the release as it was stored, if it was.
+pub fn remove_want(db: &Database, requester: &DiscogsClient, username: &str, id: i64) -> Result<Option<Release>, UpdateError> {
+    let url = build_url(requester, ParseType::Want(id), username);
+    let removed = read_response(requester.send(|client| client.delete(&url))?);
+    let stored = query::want(db, id)?;
+    match removed {
+        Ok(_) => {}
+        //* already gone upstream, so it shouldn't be here either
+        Err(UpdateError::NotFoundError) => {
+            dbupdate::remove_want(db, id)?;
+            return Err(UpdateError::NotWantedError(id))
+        }
+        Err(e) => return Err(e),
+    }
+    dbupdate::remove_want(db, id)?;
+    Ok(stored)
+}
+
+//* Discogs' own folders: All (0) lists everything, Uncategorized (1) is where new copies go
+const ALL: i64 = 0;
+const UNCATEGORIZED: i64 = 1;
+
+/// Adds a copy of release `id` to `folder`. Gives back the copy
+/// and the name of the folder as Discogs has it.
+pub fn add_to_folder(db: &Database, requester: &DiscogsClient, username: &str, id: i64, folder: &str) -> Result<(Release, String), UpdateError> {
+    let (folder, folder_id) = find_folder(db, folder)?;
+    if folder_id == ALL {
+        return Err(UpdateError::ProtectedFolderError(folder))
+    }
+    let url = build_url(requester, ParseType::FolderRelease(folder_id as u64, id), username);
+    let text = read_response(requester.send(|client| client.post(&url))?)
+        .map_err(|e| match e {
+            UpdateError::NotFoundError => UpdateError::NoReleaseError(id),
+            e => e,
+        })?;
+    let added: response::Instance = parse_response(&text, "the new copy")?;
+
+    //* all Discogs says is the instance ID, so the copy is looked up afterwards
+    let url = build_url(requester, ParseType::CollectionRelease(id), username);
+    let copies = parse_releases(&query_discogs(requester, &url)?, false, false, "copies")?;
+    let release = copies.into_iter()
+        .find(|copy| copy.instance_id == Some(added.instance_id as i64))
+        .ok_or_else(|| UpdateError::ResponseError(format!(
+            "the copies of release {}, copy {} is missing", id, added.instance_id
+        )))?;
+    dbupdate::add_instance(db, &folder, &release)?;
+    Ok((release, folder))
+}
+
+/// Moves a copy of release `id` to `folder`: copy `copy` if given, otherwise the only one.
+/// Gives back the release and the names of the folders it moved between.
+pub fn move_to_folder(db: &Database, requester: &DiscogsClient, username: &str, id: i64, copy: Option<i64>, folder: &str) -> Result<(Release, String, String), UpdateError> {
+    let (from, from_id, instance_id) = pick_copy(db, id, copy)?;
+    let (to, to_id) = find_folder(db, folder)?;
+    if to_id == ALL {
+        return Err(UpdateError::ProtectedFolderError(to))
+    }
+    let release = query::owned(db, id)?.ok_or(UpdateError::NotInCollectionError(id))?;
+    if from_id == to_id {
+        return Ok((release, from, to))
+    }
+    let url = build_url(requester, ParseType::Instance(from_id as u64, id, instance_id), username);
+    post_json(requester, &url, json!({"folder_id": to_id}))?;
+    dbupdate::move_instance(db, instance_id, &to)?;
+    Ok((release, from, to))
+}
+
+/// Removes a copy of release `id` from the collection: copy `copy` if given, otherwise the only one.
+/// Gives back the release and the folder it was in.
+pub fn remove_from_collection(db: &Database, requester: &DiscogsClient, username: &str, id: i64, copy: Option<i64>) -> Result<(Release, String), UpdateError> {
+    let (folder, folder_id, instance_id) = pick_copy(db, id, copy)?;
+    let release = query::owned(db, id)?.ok_or(UpdateError::NotInCollectionError(id))?;
+    let url = build_url(requester, ParseType::Instance(folder_id as u64, id, instance_id), username);
+    match read_response(requester.send(|client| client.delete(&url))?) {
+        //* already gone upstream, so it shouldn't be here either
+        Ok(_) | Err(UpdateError::NotFoundError) => {}
+        Err(e) => return Err(e),
+    }
+    dbupdate::remove_instance(db, instance_id)?;
+    Ok((release, folder))
+}
+
+/// Rates release `id` from 1 to 5 stars, or clears its rating with 0.
+/// Gives back the release with its new rating.
+pub fn rate(db: &Database, requester: &DiscogsClient, username: &str, id: i64, rating: u8) -> Result<Release, UpdateError> {
+    let mut release = query::owned(db, id)?.ok_or(UpdateError::NotInCollectionError(id))?;
+    //* a rating belongs to the release, so rating any one copy rates them all
+    let (folder_id, instance_id) = match query::instances(db, id)?.into_iter().next() {
+        Some(Instance {folder_id: Some(folder_id), instance_id: Some(instance_id), ..}) => (folder_id, instance_id),
+        Some(_) => return Err(UpdateError::NotSyncedError),
+        None => return Err(UpdateError::NotInCollectionError(id)),
+    };
+    let url = build_url(requester, ParseType::Instance(folder_id as u64, id, instance_id), username);
+    post_json(requester, &url, json!({"rating": rating}))?;
+    release.rating = rating;
+    dbupdate::set_rating(db, &release)?;
+    Ok(release)
+}
+
+pub fn create_folder(db: &Database, requester: &DiscogsClient, username: &str, name: &str) -> Result<String, UpdateError> {
+    let name = name.trim().to_string();
+    if let Ok((existing, _)) = find_folder(db, &name) {
+        return Err(UpdateError::FolderExistsError(existing))
+    }
+    let url = build_url(requester, ParseType::Initial, username);
+    let text = post_json(requester, &url, json!({"name": name}))?;
+    let created: response::Folder = parse_response(&text, "the new folder")?;
+    dbupdate::add_folder(db, &created.name, created.id as i64)?;
+    Ok(created.name)
+}
+
+/// Renames `name` to `new_name`. Gives back the old name as Discogs has it.
+pub fn rename_folder(db: &Database, requester: &DiscogsClient, username: &str, name: &str, new_name: &str) -> Result<String, UpdateError> {
+    let (name, id) = find_folder(db, name)?;
+    if id == ALL || id == UNCATEGORIZED {
+        return Err(UpdateError::ProtectedFolderError(name))
+    }
+    let new_name = new_name.trim().to_string();
+    match find_folder(db, &new_name) {
+        //* changing the case of a name is still a rename
+        Ok((existing, _)) if existing != name => return Err(UpdateError::FolderExistsError(existing)),
+        _ => {}
+    }
+    let url = build_url(requester, ParseType::Folder(id as u64), username);
+    post_json(requester, &url, json!({"name": new_name}))?;
+    dbupdate::rename_folder(db, &name, &new_name)?;
+    Ok(name)
+}
+
+/// Deletes folder `name`, which has to be empty. Gives back its name as Discogs has it.
+pub fn delete_folder(db: &Database, requester: &DiscogsClient, username: &str, name: &str) -> Result<String, UpdateError> {
+    let (name, id) = find_folder(db, name)?;
+    if id == ALL || id == UNCATEGORIZED {
+        return Err(UpdateError::ProtectedFolderError(name))
+    }
+    //* Discogs won't delete a folder with anything in it either
+    if query::folder_size(db, &name)? > 0 {
+        return Err(UpdateError::FolderNotEmptyError(name))
+    }
+    let url = build_url(requester, ParseType::Folder(id as u64), username);
+    read_response(requester.send(|client| client.delete(&url))?)?;
+    dbupdate::remove_folder(db, &name)?;
+    Ok(name)
+}
+
+fn post_json(requester: &DiscogsClient, url: &str, body: Value) -> Result<String, UpdateError> {
+    let body = body.to_string();
+    read_response(requester.send(|client| {
+        client.post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+    })?)
+}
+
+// a folder by its exact name, or failing that, ignoring case
+fn find_folder(db: &Database, name: &str) -> Result<(String, i64), UpdateError> {
+    let folders = query::folder_ids(db)?;
+    let found = folders.get_key_value(name)
+        .or_else(|| folders.iter().find(|(stored, _)| stored.eq_ignore_ascii_case(name)));
+    match found {
+        Some((name, Some(id))) => Ok((name.clone(), *id)),
+        Some((_, None)) => Err(UpdateError::NotSyncedError),
+        None => Err(UpdateError::NoFolderError(name.to_string())),
+    }
+}
+
+// the folder, folder ID and instance ID of copy `copy` of release `id`,
+// or of its only copy if there's no `copy`
+fn pick_copy(db: &Database, id: i64, copy: Option<i64>) -> Result<(String, i64, i64), UpdateError> {
+    let instances = query::instances(db, id)?;
+    let picked = match (copy, instances.as_slice()) {
+        (_, []) => return Err(UpdateError::NotInCollectionError(id)),
+        (Some(copy), _) => instances.iter()
+            .find(|instance| instance.instance_id == Some(copy))
+            .ok_or(UpdateError::NoCopyError(id, copy))?,
+        (None, [only]) => only,
+        (None, many) => {
+            let copies: Vec<(i64, String)> = many.iter()
+                .filter_map(|instance| instance.instance_id.map(|copy| (copy, instance.folder.clone())))
+                .collect();
+            //* without their IDs there's nothing to pick from
+            if copies.len() < many.len() {
+                return Err(UpdateError::NotSyncedError)
+            }
+            return Err(UpdateError::MultipleInstancesError(id, copies))
+        }
+    };
+    match picked {
+        Instance {folder, folder_id: Some(folder_id), instance_id: Some(instance_id)} => {
+            Ok((folder.clone(), *folder_id, *instance_id))
+        }
+        _ => Err(UpdateError::NotSyncedError),
+    }
+}
@@ -1,10 +1,11 @@
-use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
 use std::process::exit;
+use std::rc::Rc;
 
 use cursive::{
     Cursive,
+    theme::Color,
     views::*,
     event::{Event, Key},
 };
@@ -13,28 +14,31 @@ use crate::app::{
         App, 
         ListenLogEntry,
         ListenLog,
+        Purchase,
     },
-    request::UpdateError,
     database::{
-        DBError,
+        Database,
         admin, 
         query,
         update as dbupdate, 
         query::QueryType,
     },
     message::{Message, MessageKind},
+    request::Auth,
     update,
+    edit,
 };
 use crate::utils;
+use crate::CONFIG;
 use crate::config::Config;
 use crate::screens::{
     Wantlist,
     popup,
     profile,
+    spending,
 };
 use crate::collection::Collection;
-use crate::commands::{Command, CommandError};
-use crate::APPEARANCE;
+use crate::commands::{Command, CommandError, Paid};
 
 const DB_NOT_INIT_MSG: &str =
 "Database not initialized. Would you like to initialize it now? [Y/n]";
@@ -42,43 +46,26 @@ const DB_NOT_INIT_MSG: &str =
 const DB_INTEGRITY_FAIL_MSG: &str =
 "Database integrity check failed, would you like to re-initialize it now? [Y/n]";
 
-const DB_ORPHAN_TABLES_MSG: &str =
-"Database contains orphan tables, would you like to clear them now? [Y/n]";
-
-fn on_init_fail(username: &str, token: &str, integ_fail: bool, orphan: bool) {
+fn on_init_fail(db: &Database, username: &str, auth: &Auth, integ_fail: bool) {
     let mut answer = String::new();
     print!(">>> "); io::stdout().flush().unwrap();
     io::stdin().read_line(&mut answer)
         .expect("Oops, could not read line.");
     match answer.to_lowercase().as_str().trim() {
         "y" | "yes" => {
-            if orphan {
-                match admin::remove_orphans() {
-                    Ok(()) => {}
-                    Err(e) => {
-                        eprintln!("\n{}", e);
-                        exit(1);
-                    }
-                }
-            } else {
-                println!("Beginning database initialization.");
-                match update::full(username, token, true, false) {
-                    Ok(()) => {}
-                    Err(e) => {
-                        eprintln!("\n{}", e);
-                        if let UpdateError::DBWriteError(_) = e {
-                            fs::remove_file(utils::database_file()).unwrap();
-                        }
-                        exit(1);
-                    }
+            println!("Beginning database initialization.");
+            match update::full(db, username, auth, true, false) {
+                Ok(_) => {}
+                Err(e) => {
+                    //* the sync rolled back, so the listening history is still there
+                    eprintln!("\n{}", e);
+                    exit(1);
                 }
             }
         },
         "n" | "no" => {
             if integ_fail {
                 println!("Run `cogsy reset` to reset your user database manually.")
-            } else if orphan {
-                println!("Run `cogsy database --orphan to remove orphan tables.")
             }
             exit(1);
         },
@@ -92,17 +79,24 @@ impl App {
             Config::first_init();
         }
         let config = Config::load();
-        let token = format!(
-            "Discogs token={}",
-            config.user.token
-        );
-        let dbfilepath = utils::database_file();
-
-        if !Path::new(&dbfilepath).exists() {
+        let auth = Auth::from_config(&config);
+        let db = Database::open().unwrap_or_else(|e| {
+            eprintln!("{}", Message::set(e.to_string(), MessageKind::Error));
+            exit(2);
+        });
+        if let Err(e) = admin::init_db(&db) {
+            eprintln!("{}", Message::set(e.to_string(), MessageKind::Error));
+            exit(2);
+        }
+        //* a database that has never been synced has no profile
+        if query::profile(&db).is_err() {
             println!("{}", Message::set(DB_NOT_INIT_MSG, MessageKind::Hint));
-            on_init_fail(&config.user.username, &token, false, false);
+            on_init_fail(&db, &config.user.username, &auth, false);
         }
-        if !utils::usernames_match() {
+        let synced_as = query::profile(&db)
+            .map(|profile| profile.username)
+            .unwrap_or_default();
+        if !utils::usernames_match(&config.user.username, &synced_as) {
             println!("{}", 
                 Message::set(
                     "The username in your config file seems to have changed.", 
@@ -110,29 +104,26 @@ impl App {
                 )
             );
             println!("Would you like to use the new username? [Y/n]");
-            on_init_fail(&config.user.username, &token, false, false);
+            on_init_fail(&db, &config.user.username, &auth, false);
         }
-        if let Err(e) = admin::check_integrity() {
-            eprintln!("{}", Message::set(&e.to_string(), MessageKind::Error));
-            if let DBError::OrphanTableErr = e {
-                eprintln!("{}", Message::set(DB_ORPHAN_TABLES_MSG, MessageKind::Hint));
-                on_init_fail(&config.user.username, &token, false, true);
-            } else {
-                eprintln!("{}", Message::set(DB_INTEGRITY_FAIL_MSG, MessageKind::Hint));
-                on_init_fail(&config.user.username, &token, true, false);
-            }
+        if let Err(e) = admin::check_integrity(&db) {
+            eprintln!("{}", Message::set(e.to_string(), MessageKind::Error));
+            eprintln!("{}", Message::set(DB_INTEGRITY_FAIL_MSG, MessageKind::Hint));
+            on_init_fail(&db, &config.user.username, &auth, true);
         }
+        let db = Rc::new(db);
 
         let mut app = App {
             user_id: config.user.username.clone(),
-            token,
+            auth,
             message: Message {
                 msg: format!("Cogsy v{}", env!("CARGO_PKG_VERSION")),
                 kind: MessageKind::Info
             },
-            collection: Collection::new(),
+            collection: Collection::new(db.clone()),
             modified: false,
             appearance: config.appearance.unwrap_or_default(),
+            db,
         };
 
         app.appearance.resolve();
@@ -150,14 +141,14 @@ impl App {
                         s.call_on_name("messagebox", |view: &mut TextView| {
                             view.set_content("Updating collection...");
                         });
-                        let updateres = update::full(&self.user_id, 
-                                                     &self.token, 
+                        let updateres = update::full(&self.db,
+                                                     &self.user_id, 
+                                                     &self.auth, 
                                                      false, false);
                         match updateres {
-                            Ok(()) => {
-                                self.collection.folders = query::collection(APPEARANCE.sort_by()).unwrap();
+                            Ok(summary) => {
                                 self.collection.refresh(s);
-                                view_content = "Database successfully updated.".to_string();
+                                view_content = format!("Database successfully updated: {}.", summary);
                                 view_style = self.appearance.success_col();
                             }
                             Err(e) => {
@@ -179,7 +170,7 @@ impl App {
                         self.modified = true;
                     }
                     Command::Random(nolog) => {
-                        match query::random() {
+                        match query::random(&self.db) {
                             Ok(random) => {
                                 if !nolog {
                                     let time_now = utils::get_utc_now();
@@ -188,7 +179,7 @@ impl App {
                                         title: &random.title,
                                         time: time_now,
                                     };
-                                    match dbupdate::listenlog(entry) {
+                                    match dbupdate::listenlog(&self.db, entry) {
                                         Ok(()) => {
                                             view_content = format!("You should play `{}` by {}", 
                                                 random.title, 
@@ -209,11 +200,22 @@ impl App {
                             }
                         };
                     }
-                    Command::Price(_album, _price) => {
-                        view_content = "Sorry, the price command is not supported at this time.".to_string();
+                    Command::Price(album, amount, paid) => {
+                        let (content, style) = self.record_price(&album, amount, paid, false);
+                        view_content = content;
+                        if let Some(style) = style {
+                            view_style = style;
+                        }
+                    }
+                    Command::PriceFix(album, amount, paid) => {
+                        let (content, style) = self.record_price(&album, amount, paid, true);
+                        view_content = content;
+                        if let Some(style) = style {
+                            view_style = style;
+                        }
                     }
                     Command::Listen(album, _time) => {
-                        match query::release(&album, QueryType::Collection) {
+                        match query::release(&self.db, &album, QueryType::Collection) {
                             Ok(results) => {
                                 match results.len() {
                                     1 => {
@@ -223,7 +225,7 @@ impl App {
                                             title: &results[0].title,
                                             time: time_now,
                                         };
-                                        match dbupdate::listenlog(entry) {
+                                        match dbupdate::listenlog(&self.db, entry) {
                                             Ok(()) => {view_content = format!("Listening to `{}` by {}", 
                                                 results[0].title, 
                                                 results[0].artist,
@@ -235,7 +237,7 @@ impl App {
                                         view_content = format!("Multiple results for `{}`", album);
                                         s.add_fullscreen_layer(
                                             //listenlog gets logged internally here
-                                            popup::multiple_results(results, true)
+                                            popup::multiple_results(&self.db, results, true)
                                         );
                                     }
                                     _ => {
@@ -246,52 +248,182 @@ impl App {
                             Err(e) => {view_content = format!("{}", e);}
                         }
                     }
-                    Command::Query(album) => {
-                        match query::release(&album, QueryType::Collection) {
+                    Command::Query(filter) => {
+                        match query::search(&self.db, &filter, QueryType::Collection) {
                             Ok(results) => {
-                                view_content = format!("Querying collection for `{}`", album);
+                                view_content = format!("Querying collection for `{}`", filter);
                                 match results.len() {
                                     1 => {
                                         s.add_fullscreen_layer(
-                                            popup::albuminfo(&results[0])
+                                            popup::albuminfo(&self.db, &results[0])
                                         );
                                     }
                                     n if n > 1 => {
                                         s.add_fullscreen_layer(
-                                            popup::multiple_results(results, false)
+                                            popup::multiple_results(&self.db, results, false)
                                         );
                                     }
                                     _ => {
-                                        view_content = format!("Unable to find results for `{}`", album);
+                                        view_content = format!("Unable to find results for `{}`", filter);
                                     }
                                 }
                             }
                             Err(e) => {view_content = format!("{}", e);}
                         }
                     }
-                    Command::QueryWantlist(album) => {
-                        match query::release(&album, QueryType::Wantlist) {
+                    Command::QueryWantlist(filter) => {
+                        match query::search(&self.db, &filter, QueryType::Wantlist) {
                             Ok(results) => {
-                                view_content = format!("Querying wantlist for `{}`", album);
+                                view_content = format!("Querying wantlist for `{}`", filter);
                                 match results.len() {
                                     1 => {
                                         s.add_fullscreen_layer(
-                                            popup::albuminfo(&results[0])
+                                            popup::albuminfo(&self.db, &results[0])
                                         );
                                     }
                                     n if n > 1 => {
                                         s.add_fullscreen_layer(
-                                            popup::multiple_results(results, false)
+                                            popup::multiple_results(&self.db, results, false)
                                         );
                                     }
                                     _ => {
-                                        view_content = format!("Unable to find results for `{}`", album);
+                                        view_content = format!("Unable to find results for `{}`", filter);
                                     }
                                 }
                             }
                             Err(e) => {view_content = format!("{}", e);}
                         }
                     }
+                    Command::WantAdd(id) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::add_want(&self.db, &requester, &self.user_id, id) {
+                            Ok(release) => {
+                                view_content = format!("Added `{}` by {} to your wantlist", release.title, release.artist);
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::WantRemove(id) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::remove_want(&self.db, &requester, &self.user_id, id) {
+                            Ok(removed) => {
+                                view_content = match removed {
+                                    Some(release) => format!("Removed `{}` by {} from your wantlist", release.title, release.artist),
+                                    None => format!("Removed release {} from your wantlist", id),
+                                };
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::CollectionAdd(id, folder) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::add_to_folder(&self.db, &requester, &self.user_id, id, &folder) {
+                            Ok((release, folder)) => {
+                                self.collection.refresh(s);
+                                view_content = format!("Added `{}` by {} to {}", release.title, release.artist, folder);
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::CollectionMove(id, copy, folder) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::move_to_folder(&self.db, &requester, &self.user_id, id, copy, &folder) {
+                            Ok((release, from, to)) => {
+                                self.collection.refresh(s);
+                                view_content = format!("Moved `{}` by {} from {} to {}", release.title, release.artist, from, to);
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::CollectionRemove(id, copy) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::remove_from_collection(&self.db, &requester, &self.user_id, id, copy) {
+                            Ok((release, folder)) => {
+                                self.collection.refresh(s);
+                                view_content = format!("Removed `{}` by {} from {}", release.title, release.artist, folder);
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::FolderCreate(name) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::create_folder(&self.db, &requester, &self.user_id, &name) {
+                            Ok(name) => {
+                                self.collection.refresh(s);
+                                view_content = format!("Created folder {}", name);
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::FolderRename(name, new_name) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::rename_folder(&self.db, &requester, &self.user_id, &name, &new_name) {
+                            Ok(name) => {
+                                self.collection.refresh(s);
+                                view_content = format!("Renamed folder {} to {}", name, new_name.trim());
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::FolderDelete(name) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::delete_folder(&self.db, &requester, &self.user_id, &name) {
+                            Ok(name) => {
+                                self.collection.refresh(s);
+                                view_content = format!("Deleted folder {}", name);
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
+                    Command::Rate(id, rating) => {
+                        let requester = update::build_client(&self.auth);
+                        match edit::rate(&self.db, &requester, &self.user_id, id, rating) {
+                            Ok(release) => {
+                                self.collection.refresh(s);
+                                view_content = match rating {
+                                    0 => format!("Cleared the rating of `{}` by {}", release.title, release.artist),
+                                    n => format!("Rated `{}` by {} {}/5", release.title, release.artist, n),
+                                };
+                                view_style = self.appearance.success_col();
+                            }
+                            Err(e) => {
+                                view_content = e.to_string();
+                                view_style = self.appearance.error_col();
+                            }
+                        }
+                    }
                     Command::Quit => {
                         view_content = String::from("Quitting...");
                         s.quit();
@@ -314,7 +446,80 @@ impl App {
         }
     }
 
-    pub fn add_callbacks(s: &mut Cursive) {
+    // records what was paid for `album`, or with `fixing`, corrects the price recorded last.
+    // gives back the message and its colour, if it isn't the default one
+    fn record_price(&self, album: &str, amount: i64, paid: Paid, fixing: bool) -> (String, Option<Color>) {
+        let results = match query::release(&self.db, album, QueryType::Collection) {
+            Ok(results) => results,
+            Err(e) => return (e.to_string(), None),
+        };
+        //* the exact title settles it when the search turns up more than one
+        let exact: Vec<_> = results.iter()
+            .filter(|release| release.title.eq_ignore_ascii_case(album))
+            .collect();
+        let release = match (results.len(), exact.len()) {
+            (0, _) => return (format!("Unable to find results for `{}`", album), None),
+            (1, _) => &results[0],
+            (_, 1) => exact[0],
+            _ => return (
+                format!("Multiple results for `{}`, try the full title", album),
+                Some(self.appearance.hint_col())
+            ),
+        };
+
+        let recorded = if fixing {
+            //* whatever the correction leaves out stays as it was
+            match query::last_purchase(&self.db, release.id) {
+                Ok(Some((id, last))) => {
+                    let purchase = Purchase {
+                        release_id: release.id,
+                        amount,
+                        currency: paid.currency.unwrap_or(last.currency),
+                        date: paid.date.unwrap_or(last.date),
+                        store: paid.store.or(last.store),
+                    };
+                    dbupdate::correct_purchase(&self.db, id, &purchase).map(|_| purchase)
+                }
+                Ok(None) => return (
+                    format!("No price recorded for `{}` yet, record one with `price`", release.title),
+                    Some(self.appearance.hint_col())
+                ),
+                Err(e) => Err(e),
+            }
+        } else {
+            //* the currency used last time, since it's usually the same one
+            let currency = paid.currency
+                .or_else(|| query::last_currency(&self.db).ok().flatten())
+                .unwrap_or_else(|| String::from("USD"));
+            let today = utils::get_utc_now()
+                .with_timezone(&CONFIG.timezone())
+                .date()
+                .naive_local();
+            let purchase = Purchase {
+                release_id: release.id,
+                amount,
+                currency,
+                date: paid.date.unwrap_or(today),
+                store: paid.store,
+            };
+            dbupdate::add_purchase(&self.db, &purchase).map(|_| purchase)
+        };
+        match recorded {
+            Ok(purchase) => (
+                format!("{} {} {} for `{}` by {}",
+                    if fixing {"Corrected the price to"} else {"Paid"},
+                    utils::format_price(purchase.amount),
+                    purchase.currency,
+                    release.title,
+                    release.artist,
+                ),
+                Some(self.appearance.success_col())
+            ),
+            Err(e) => (e.to_string(), Some(self.appearance.error_col())),
+        }
+    }
+
+    pub fn add_callbacks(s: &mut Cursive, db: Rc<Database>) {
         s.add_global_callback('q', |s| {
             //TODO: check app modified state and write to file
             s.quit();
@@ -346,37 +551,50 @@ impl App {
             }
         });
         //wantlist screen
-        s.add_global_callback('2', |s| {
+        let db2 = db.clone();
+        s.add_global_callback('2', move |s| {
             while s.screen().len() > 1 {
                 s.pop_layer();
             }
             if s.screen().len() == 1 {
-                s.add_fullscreen_layer(Wantlist::init().build());
+                s.add_fullscreen_layer(Wantlist::init(db2.clone()).build());
             }
         });
         //profile screen
-        s.add_global_callback('3', |s| {
+        let db3 = db.clone();
+        s.add_global_callback('3', move |s| {
+            while s.screen().len() > 1 {
+                s.pop_layer();
+            }
+            if s.screen().len() == 1 {
+                s.add_fullscreen_layer(profile::build(&db3));
+            }
+        });
+        let db4 = db.clone();
+        s.add_global_callback('4', move |s| {
             while s.screen().len() > 1 {
                 s.pop_layer();
             }
             if s.screen().len() == 1 {
-                s.add_fullscreen_layer(profile::build());
+                s.add_fullscreen_layer(ListenLog::init(&db4).build_sparkview(&db4));
             }
         });
-        s.add_global_callback('4', |s| {
+        //spending screen
+        let db5 = db.clone();
+        s.add_global_callback('5', move |s| {
             while s.screen().len() > 1 {
                 s.pop_layer();
             }
             if s.screen().len() == 1 {
-                s.add_fullscreen_layer(ListenLog::init().build_sparkview());
+                s.add_fullscreen_layer(spending::build(&db5));
             }
         });
-        s.add_global_callback('h', |s| {
+        s.add_global_callback('h', move |s| {
             while s.screen().len() > 1 {
                 s.pop_layer();
             }
             if s.screen().len() == 1 {
-                s.add_fullscreen_layer(ListenLog::init().build_history());
+                s.add_fullscreen_layer(ListenLog::init(&db).build_history());
             }
         });
     }
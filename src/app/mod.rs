@@ -2,20 +2,32 @@ pub mod impl_app;
 pub mod database;
 pub mod csv;
 pub mod request;
+pub mod oauth;
+pub mod ratelimit;
+#[cfg(test)]
+pub mod mock;
+#[cfg(test)]
+mod api_tests;
 pub mod message;
 pub mod response;
 pub mod update;
+pub mod edit;
 pub mod cli;
 
 use std::collections::BTreeMap;
 use std::hash::Hash;
 use std::fmt;
+use std::rc::Rc;
 
 use chrono::{
     DateTime,
+    NaiveDate,
     Utc,
 };
+use serde::{Serialize, Deserialize};
 use message::Message;
+use database::Database;
+use request::Auth;
 
 use crate::CONFIG;
 use crate::collection::Collection;
@@ -25,11 +37,12 @@ use crate::utils::{FormatTokenizer, FormatToken};
 #[derive(Debug, Clone)]
 pub struct App {
     pub user_id: String,
-    pub token: String,
+    pub auth: Auth,
     pub message: Message,
     pub collection: Collection,
     pub modified: bool,
     pub appearance: Appearance,
+    pub db: Rc<Database>,
 }
 
 #[derive(Debug, Clone)]
@@ -44,6 +57,37 @@ pub struct Profile {
     pub average_rating: f64,
 }
 
+/// What Discogs reckons the collection is worth, as of `fetched`.
+/// The amounts are as Discogs formats them, in the user's currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionValue {
+    pub fetched: DateTime<Utc>,
+    pub minimum: String,
+    pub median: String,
+    pub maximum: String,
+}
+
+/// What was paid for a release. Discogs doesn't keep this, so it only lives locally.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Purchase {
+    pub release_id: i64,
+    //* in hundredths of the currency, so they add up exactly
+    pub amount: i64,
+    pub currency: String,
+    pub date: NaiveDate,
+    pub store: Option<String>,
+}
+
+/// How much went on releases in one month, year or folder, in one currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spending {
+    pub group: String,
+    pub currency: String,
+    //* in hundredths, like the purchases
+    pub total: i64,
+    pub purchases: u32,
+}
+
 pub struct ListenLogEntry<'a> {
     pub id: i64,
     pub title: &'a str,
@@ -62,8 +106,91 @@ pub struct Release {
     pub artist: String,
     pub year: u32,
     pub labels: Vec<String>,
+    pub catnos: Vec<String>,
     pub formats: Vec<String>,
     pub date_added: DateTime<Utc>,
+    //* which copy this is; None for wants, CSV imports and rows from before it was stored
+    pub instance_id: Option<i64>,
+    //* 1 to 5 stars, 0 if it isn't rated
+    pub rating: u8,
+    pub genres: Vec<String>,
+    pub styles: Vec<String>,
+    pub master_id: Option<i64>,
+    //* image URLs, empty if Discogs has none
+    pub thumb: String,
+    pub cover_image: String,
+    //* the notes on this copy, like its condition; always empty for wants
+    pub notes: Vec<Note>,
+}
+
+/// A note on one copy in the collection: one of Discogs' collection
+/// fields (media condition, sleeve condition, notes) or a custom one.
+#[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Note {
+    //* None for custom fields imported from CSV, which only has their names
+    pub field_id: Option<i64>,
+    pub field: String,
+    pub value: String,
+}
+
+impl Note {
+    //* the fields every Discogs collection starts out with
+    pub const MEDIA_CONDITION: i64 = 1;
+    pub const SLEEVE_CONDITION: i64 = 2;
+    pub const NOTES: i64 = 3;
+
+    /// A note on field `field_id`, named as Discogs names it by default.
+    /// Custom fields get their real names once the field list is fetched.
+    pub fn new(field_id: i64, value: String) -> Self {
+        let field = match field_id {
+            Note::MEDIA_CONDITION => String::from("Media Condition"),
+            Note::SLEEVE_CONDITION => String::from("Sleeve Condition"),
+            Note::NOTES => String::from("Notes"),
+            n => format!("Field {}", n),
+        };
+        Note {field_id: Some(field_id), field, value}
+    }
+}
+
+/// What /releases/{id} has on a release that collection and wantlist
+/// entries leave out. Fetched when it's asked for and cached from then on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleaseDetails {
+    pub id: i64,
+    //* empty when Discogs doesn't have them
+    pub country: String,
+    pub released: String,
+    pub notes: String,
+    pub tracklist: Vec<Track>,
+    pub credits: Vec<Credit>,
+    pub identifiers: Vec<Identifier>,
+    pub fetched: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub position: String,
+    pub title: String,
+    pub duration: String,
+    //* headings split a tracklist into sides or parts, they aren't tracks
+    pub heading: bool,
+}
+
+/// Someone who worked on a release, e.g. "Mastered By".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credit {
+    pub name: String,
+    pub role: String,
+    //* which tracks, empty if it's all of them
+    pub tracks: String,
+}
+
+/// A barcode, matrix number, rights society and the like.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identifier {
+    pub kind: String,
+    pub value: String,
+    pub description: String,
 }
 
 impl Release {
@@ -79,9 +206,11 @@ impl Release {
                 Artist => {ret.push_str(&self.artist)}
                 Title => {ret.push_str(&self.title)}
                 Id => {ret.push_str(&self.id.to_string())}
-                Year => {ret.push_str(&self.id.to_string())}
+                Year => {ret.push_str(&self.year.to_string())}
                 Labels => {ret.push_str(&self.labels.join(", "))}
                 Formats => {ret.push_str(&self.formats.join(", "))}
+                Genres => {ret.push_str(&self.genres.join(", "))}
+                Styles => {ret.push_str(&self.styles.join(", "))}
                 DateAdded => {ret.push_str(&self.date_added.to_string())}
                 RawStr(s) => {ret.push_str(s)}
                 Unknown => {}
@@ -101,7 +230,7 @@ impl fmt::Display for Release {
             self.artist,
             self.year,
             self.labels.join(", "),
-            self.labels.join(", "),
+            self.formats.join(", "),
             display_time.format("%A %d %m %Y %R"),
         )
     }
@@ -110,6 +239,8 @@ impl fmt::Display for Release {
 #[derive(Debug, Clone)]
 pub struct Folders {
     pub contents: BTreeMap<String, Vec<Release>>,
+    //* Discogs folder IDs by name, when they came from Discogs
+    pub ids: BTreeMap<String, i64>,
 }
 
 #[allow(dead_code)]
@@ -119,6 +250,7 @@ impl Folders { //wrapper around a BTreeMap<String, Vec<Release>>
             BTreeMap<String, Vec<Release>> = BTreeMap::new();
         Folders {
             contents: new_self,
+            ids: BTreeMap::new(),
         }
     }
     pub fn contents(&mut self) -> BTreeMap<String, Vec<Release>> {
@@ -138,7 +270,7 @@ impl Folders { //wrapper around a BTreeMap<String, Vec<Release>>
 
     pub fn insert(&mut self, folder: String, release: Release) {
         self.contents.entry(folder)
-            .and_modify(|v| v.push(release))
-            .or_insert(vec![]);
+            .or_default()
+            .push(release);
     }
 }
\ No newline at end of file
@@ -0,0 +1,358 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, percent_decode_str, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{
+    Url,
+    blocking::Client,
+    header,
+};
+use sha1::Sha1;
+
+use crate::app::request::{self, UpdateError};
+
+/*
+* OAuth 1.0a, as Discogs does it (https://www.discogs.com/developers#page:authentication).
+* `cogsy auth` runs the out-of-band flow: get a request token, have the user
+* authorize it in their browser, trade the PIN they get for an access token.
+* After that every request carries its own HMAC-SHA1 signature.
+*/
+
+/// Where users authorize a request token. This is the website, not the API.
+pub const AUTHORIZE_URL: &str = "https://www.discogs.com/oauth/authorize";
+
+//* everything but the unreserved characters of RFC 3986
+const RESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// The consumer key and secret identify the application,
+/// the token and its secret identify the user.
+/// The token is empty until there is one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Credentials {
+    pub consumer_key: String,
+    pub consumer_secret: String,
+    pub token: String,
+    pub token_secret: String,
+}
+
+impl Credentials {
+    /// The Authorization header for a request, with a fresh nonce and timestamp.
+    pub fn authorization(&self, method: &str, url: &Url) -> String {
+        self.authorization_with(method, url, &[], &nonce(), timestamp())
+    }
+
+    /// Same as `authorization`, with any extra oauth_ parameters
+    /// (e.g. oauth_verifier) and a given nonce and timestamp.
+    pub fn authorization_with(
+        &self,
+        method: &str,
+        url: &Url,
+        extra: &[(&str, &str)],
+        nonce: &str,
+        timestamp: u64
+    ) -> String {
+        let timestamp = timestamp.to_string();
+        let mut oauth = vec![
+            ("oauth_consumer_key", self.consumer_key.as_str()),
+            ("oauth_nonce", nonce),
+            ("oauth_signature_method", "HMAC-SHA1"),
+            ("oauth_timestamp", &timestamp),
+            ("oauth_version", "1.0"),
+        ];
+        if !self.token.is_empty() {
+            oauth.push(("oauth_token", &self.token));
+        }
+        oauth.extend_from_slice(extra);
+
+        //* query parameters are signed too, but don't go in the header
+        let mut params: Vec<(String, String)> = oauth.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        params.extend(url.query_pairs().map(|(k, v)| (k.into_owned(), v.into_owned())));
+        let mut base = url.clone();
+        base.set_query(None);
+        base.set_fragment(None);
+
+        let signature = sign(method, base.as_str(), &params, &self.consumer_secret, &self.token_secret);
+        oauth.push(("oauth_signature", &signature));
+        let fields: Vec<String> = oauth.iter()
+            .map(|(k, v)| format!("{}=\"{}\"", encode(k), encode(v)))
+            .collect();
+        format!("OAuth {}", fields.join(", "))
+    }
+}
+
+/// Percent-encodes `s` the way OAuth wants it.
+pub fn encode(s: &str) -> String {
+    utf8_percent_encode(s, RESERVED).to_string()
+}
+
+/// The string that gets signed: the method, the URL without its query,
+/// and every parameter, encoded and sorted.
+pub fn signature_base(method: &str, url: &str, params: &[(String, String)]) -> String {
+    let mut encoded: Vec<(String, String)> = params.iter()
+        .map(|(k, v)| (encode(k), encode(v)))
+        .collect();
+    encoded.sort();
+    let joined = encoded.iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join("&");
+    format!("{}&{}&{}", method.to_uppercase(), encode(url), encode(&joined))
+}
+
+/// The base64 HMAC-SHA1 signature of a request.
+pub fn sign(
+    method: &str,
+    url: &str,
+    params: &[(String, String)],
+    consumer_secret: &str,
+    token_secret: &str
+) -> String {
+    let key = format!("{}&{}", encode(consumer_secret), encode(token_secret));
+    base64::encode(hmac_sha1(key.as_bytes(), signature_base(method, url, params).as_bytes()))
+}
+
+fn hmac_sha1(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key)
+        .expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+//* a nonce only has to be unique, not secret
+fn nonce() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = DefaultHasher::new();
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .hash(&mut hasher);
+    thread::current().id().hash(&mut hasher);
+    COUNTER.fetch_add(1, Ordering::SeqCst).hash(&mut hasher);
+    let first = hasher.finish();
+    first.hash(&mut hasher);
+    format!("{:016x}{:016x}", first, hasher.finish())
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Step 1: gets a request token for `consumer` to be authorized.
+pub fn request_token(api_url: &str, consumer: &Credentials) -> Result<Credentials, UpdateError> {
+    let url = endpoint(api_url, "request_token")?;
+    let authorization = consumer.authorization_with(
+        "GET", &url, &[("oauth_callback", "oob")], &nonce(), timestamp()
+    );
+    let response = Client::new().get(url)
+        .header(header::USER_AGENT, "cogsy")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(header::AUTHORIZATION, authorization)
+        .send()
+        .map_err(|_| UpdateError::NetworkError)?;
+    read_tokens(consumer, &request::read_response(response)?)
+}
+
+/// Step 2: the page where the user authorizes the request token and gets a PIN.
+pub fn authorize_url(request: &Credentials) -> String {
+    format!("{}?oauth_token={}", AUTHORIZE_URL, encode(&request.token))
+}
+
+/// Step 3: trades the authorized request token and the PIN for an access token.
+pub fn access_token(api_url: &str, request: &Credentials, verifier: &str) -> Result<Credentials, UpdateError> {
+    let url = endpoint(api_url, "access_token")?;
+    let authorization = request.authorization_with(
+        "POST", &url, &[("oauth_verifier", verifier)], &nonce(), timestamp()
+    );
+    let response = Client::new().post(url)
+        .header(header::USER_AGENT, "cogsy")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(header::AUTHORIZATION, authorization)
+        .send()
+        .map_err(|_| UpdateError::NetworkError)?;
+    read_tokens(request, &request::read_response(response)?)
+}
+
+fn endpoint(api_url: &str, name: &str) -> Result<Url, UpdateError> {
+    Url::parse(&format!("{}/oauth/{}", api_url.trim_end_matches('/'), name))
+        .map_err(|_| UpdateError::NetworkError)
+}
+
+//* both token endpoints answer with a form-encoded body, not JSON
+fn read_tokens(consumer: &Credentials, body: &str) -> Result<Credentials, UpdateError> {
+    let field = |name: &str| -> Result<String, UpdateError> {
+        body.trim().split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| percent_decode_str(value).decode_utf8_lossy().into_owned())
+            .ok_or_else(|| UpdateError::ResponseError(
+                format!("the token response, missing field `{}`", name)
+            ))
+    };
+    Ok(Credentials {
+        consumer_key: consumer.consumer_key.clone(),
+        consumer_secret: consumer.consumer_secret.clone(),
+        token: field("oauth_token")?,
+        token_secret: field("oauth_token_secret")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::mock::{MockServer, MockResponse};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    // the fields of an Authorization header, decoded
+    fn fields(header: &str) -> Vec<(String, String)> {
+        header.trim_start_matches("OAuth ").split(", ")
+            .filter_map(|field| field.split_once('='))
+            .map(|(k, v)| (k.to_string(), percent_decode_str(v.trim_matches('"')).decode_utf8_lossy().into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_hmac_sha1_vectors() {
+        // RFC 2202, test cases 1, 2 and 6
+        assert_eq!(hex(&hmac_sha1(&[0x0b; 20], b"Hi There")), "b617318655057264e28bc0b6fb378c8ef146be00");
+        assert_eq!(
+            hex(&hmac_sha1(b"Jefe", b"what do ya want for nothing?")),
+            "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"
+        );
+        assert_eq!(
+            hex(&hmac_sha1(&[0xaa; 80], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "aa4ae5e15272d00e95705637ce8a3b55ed402112"
+        );
+    }
+
+    #[test]
+    fn test_signature_vectors() {
+        // RFC 5849, section 3.4.1.1
+        let rfc = params(&[
+            ("b5", "=%3D"), ("a3", "a"), ("c@", ""), ("a2", "r b"),
+            ("oauth_consumer_key", "9djdj82h48djs9d2"), ("oauth_token", "kkk9d7dh3k39sjv7"),
+            ("oauth_signature_method", "HMAC-SHA1"), ("oauth_timestamp", "137131201"),
+            ("oauth_nonce", "7d8f3e4a"), ("c2", ""), ("a3", "2 q"),
+        ]);
+        assert_eq!(
+            signature_base("post", "http://example.com/request", &rfc),
+            "POST&http%3A%2F%2Fexample.com%2Frequest&a2%3Dr%2520b%26a3%3D2%2520q\
+            %26a3%3Da%26b5%3D%253D%25253D%26c%2540%3D%26c2%3D%26oauth_consumer_\
+            key%3D9djdj82h48djs9d2%26oauth_nonce%3D7d8f3e4a%26oauth_signature_m\
+            ethod%3DHMAC-SHA1%26oauth_timestamp%3D137131201%26oauth_token%3Dkkk\
+            9d7dh3k39sjv7"
+        );
+
+        // the worked example in Twitter's "Creating a signature" guide
+        let twitter = params(&[
+            ("status", "Hello Ladies + Gentlemen, a signed OAuth request!"),
+            ("include_entities", "true"),
+            ("oauth_consumer_key", "xvz1evFS4wEEPTGEFPHBog"),
+            ("oauth_nonce", "kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg"),
+            ("oauth_signature_method", "HMAC-SHA1"),
+            ("oauth_timestamp", "1318622958"),
+            ("oauth_token", "370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb"),
+            ("oauth_version", "1.0"),
+        ]);
+        assert_eq!(
+            sign(
+                "POST", "https://api.twitter.com/1.1/statuses/update.json", &twitter,
+                "kAcSOqF21Fu85e7zjz7ZN2U4ZRhfV3WpwPAoE3Z7kBw",
+                "LswwdoUaIvS8ltyTt5jkRh4J50vUPVVHtR2YPi5kE"
+            ),
+            "hCtSmYh+iHYCEqBWrE7C7hYmtUk="
+        );
+    }
+
+    #[test]
+    fn test_authorization_header() {
+        // RFC 5849 section 1.2, the photo request; query parameters are signed
+        let credentials = Credentials {
+            consumer_key: "dpf43f3p2l4k3l03".into(),
+            consumer_secret: "kd94hf93k423kf44".into(),
+            token: "nnch734d00sl2jdk".into(),
+            token_secret: "pfkkdhi9sl3r4s00".into(),
+        };
+        let url = Url::parse("http://photos.example.net/photos?file=vacation.jpg&size=original").unwrap();
+        let header = credentials.authorization_with("GET", &url, &[], "chapoH", 137131202);
+        let fields = fields(&header);
+        let field = |name: &str| fields.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+
+        assert!(header.starts_with("OAuth "));
+        assert_eq!(field("oauth_token"), Some("nnch734d00sl2jdk"));
+        assert_eq!(field("file"), None);
+
+        let signed = params(&[
+            ("oauth_consumer_key", "dpf43f3p2l4k3l03"), ("oauth_nonce", "chapoH"),
+            ("oauth_signature_method", "HMAC-SHA1"), ("oauth_timestamp", "137131202"),
+            ("oauth_version", "1.0"), ("oauth_token", "nnch734d00sl2jdk"),
+            ("file", "vacation.jpg"), ("size", "original"),
+        ]);
+        let expected = sign("GET", "http://photos.example.net/photos", &signed, "kd94hf93k423kf44", "pfkkdhi9sl3r4s00");
+        assert_eq!(field("oauth_signature"), Some(expected.as_str()));
+
+        // no two requests share a nonce
+        assert_ne!(nonce(), nonce());
+    }
+
+    #[test]
+    fn test_pin_flow() {
+        let server = MockServer::start(|request| {
+            match request.path.as_str() {
+                "/oauth/request_token" => MockResponse::ok(
+                    "oauth_token=request&oauth_token_secret=request%20secret&oauth_callback_confirmed=true"
+                ),
+                "/oauth/access_token" => MockResponse::ok("oauth_token=access&oauth_token_secret=access-secret"),
+                _ => MockResponse::status(404, "{}"),
+            }
+        });
+        let consumer = Credentials {
+            consumer_key: "key".into(),
+            consumer_secret: "secret".into(),
+            ..Credentials::default()
+        };
+
+        let request = request_token(server.url(), &consumer).unwrap();
+        assert_eq!((request.token.as_str(), request.token_secret.as_str()), ("request", "request secret"));
+        assert_eq!(authorize_url(&request), format!("{}?oauth_token=request", AUTHORIZE_URL));
+
+        let access = access_token(server.url(), &request, "12345").unwrap();
+        assert_eq!(access, Credentials {
+            token: "access".into(),
+            token_secret: "access-secret".into(),
+            ..consumer
+        });
+
+        let requests = server.requests();
+        let first = fields(requests[0].header("authorization").unwrap());
+        assert!(first.contains(&("oauth_callback".into(), "oob".into())));
+        assert!(!first.iter().any(|(k, _)| k == "oauth_token"));
+        let second = fields(requests[1].header("authorization").unwrap());
+        assert_eq!(requests[1].method, "POST");
+        assert!(second.contains(&("oauth_verifier".into(), "12345".into())));
+        assert!(second.contains(&("oauth_token".into(), "request".into())));
+    }
+
+    #[test]
+    fn test_rejected_pin() {
+        let server = MockServer::start(|_| MockResponse::status(401, "Invalid verifier."));
+        let request = Credentials {token: "request".into(), ..Credentials::default()};
+        assert!(matches!(access_token(server.url(), &request, "wrong"), Err(UpdateError::AuthorizationError)));
+    }
+}
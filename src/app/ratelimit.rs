@@ -0,0 +1,222 @@
+use std::{
+    sync::Mutex,
+    thread,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use reqwest::header::HeaderMap;
+
+/*
+* Keeps cogsy under the Discogs rate limit.
+* Every request takes a token from a bucket shared by all the update threads,
+* and the bucket is kept in line with the X-Discogs-Ratelimit headers
+* that come back with each response. Responses that still come back as
+* 429 or 5xx are retried after an exponential, jittered backoff.
+*/
+
+/// Requests per minute Discogs allows an authenticated client.
+pub const DEFAULT_PER_MINUTE: u32 = 60;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    //* tokens trickle back in evenly over the window
+    fn rate(&self) -> f64 {
+        self.capacity / WINDOW.as_secs_f64()
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate()).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+impl RateLimiter {
+    /// A full bucket allowing `per_minute` requests a minute.
+    pub fn new(per_minute: u32) -> Self {
+        let capacity = f64::from(per_minute.max(1));
+        RateLimiter {
+            bucket: Mutex::new(Bucket {
+                capacity,
+                tokens: capacity,
+                last_refill: Instant::now(),
+            })
+        }
+    }
+
+    /// Blocks until there is a token for the next request, then takes it.
+    pub fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                bucket.refill();
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate())
+            };
+            //* the lock is released while sleeping so the other threads can queue up
+            thread::sleep(wait);
+        }
+    }
+
+    /// Brings the bucket in line with what Discogs says is left.
+    /// Never hands out more than the server allows, but does not give back
+    /// tokens either, as responses from different threads arrive out of order.
+    pub fn observe(&self, headers: &HeaderMap) {
+        let limit = header_u32(headers, "x-discogs-ratelimit");
+        let remaining = header_u32(headers, "x-discogs-ratelimit-remaining");
+
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill();
+        if let Some(limit) = limit.filter(|limit| *limit > 0) {
+            bucket.capacity = f64::from(limit);
+            bucket.tokens = bucket.tokens.min(bucket.capacity);
+        }
+        if let Some(remaining) = remaining {
+            bucket.tokens = bucket.tokens.min(f64::from(remaining));
+        }
+    }
+
+    /// Empties the bucket, for when Discogs has already said no.
+    pub fn exhaust(&self) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill();
+        bucket.tokens = 0.0;
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_PER_MINUTE)
+    }
+}
+
+/// How often and how long to wait before retrying a 429 or 5xx.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub retries: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            retries: 5,
+        }
+    }
+}
+
+impl Backoff {
+    /// The wait before retry number `attempt`, counting from 0.
+    /// Doubles every attempt up to `max`, and lands somewhere in the upper half
+    /// of that so threads that failed together don't all retry together.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self.base
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.max)
+            .min(self.max);
+        let floor = ceiling / 2;
+        floor + jitter(ceiling - floor)
+    }
+}
+
+//* the clock mixed with the thread id is random enough to spread retries out
+fn jitter(max: Duration) -> Duration {
+    let max = max.as_nanos() as u64;
+    if max == 0 {
+        return Duration::from_nanos(0)
+    }
+    let mut hasher = DefaultHasher::new();
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .hash(&mut hasher);
+    thread::current().id().hash(&mut hasher);
+    Duration::from_nanos(hasher.finish() % max)
+}
+
+/// Reads a numeric header, e.g. X-Discogs-Ratelimit-Remaining or Retry-After.
+pub fn header_u32(headers: &HeaderMap, name: &str) -> Option<u32> {
+    headers.get(name)?
+        .to_str().ok()?
+        .trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    fn tokens(limiter: &RateLimiter) -> f64 {
+        limiter.bucket.lock().unwrap().tokens
+    }
+
+    #[test]
+    fn test_bucket_follows_the_headers() {
+        let limiter = RateLimiter::new(60);
+        limiter.observe(&headers(&[
+            ("X-Discogs-Ratelimit", "25"),
+            ("X-Discogs-Ratelimit-Remaining", "3"),
+        ]));
+        assert!(tokens(&limiter) < 3.1);
+        assert_eq!(limiter.bucket.lock().unwrap().capacity, 25.0);
+
+        // a stale response doesn't hand tokens back
+        limiter.observe(&headers(&[("X-Discogs-Ratelimit-Remaining", "20")]));
+        assert!(tokens(&limiter) < 3.1);
+    }
+
+    #[test]
+    fn test_empty_bucket_waits() {
+        // 6000 a minute is one token every 10ms
+        let limiter = RateLimiter::new(6000);
+        limiter.exhaust();
+
+        let start = Instant::now();
+        limiter.acquire();
+        limiter.acquire();
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let backoff = Backoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+            retries: 5,
+        };
+        for (attempt, ceiling) in [(0, 100), (1, 200), (2, 400), (3, 800), (4, 1000), (40, 1000)].iter() {
+            let delay = backoff.delay(*attempt);
+            assert!(delay >= Duration::from_millis(ceiling / 2), "attempt {}: {:?}", attempt, delay);
+            assert!(delay <= Duration::from_millis(*ceiling), "attempt {}: {:?}", attempt, delay);
+        }
+    }
+}
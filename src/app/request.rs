@@ -1,48 +1,86 @@
 use std::{
     fs::read_to_string,
     fmt,
-    rc::Rc,
+    sync::Arc,
+    thread,
+    time::Duration,
 };
 use reqwest::{
-    blocking::Client,
-    header::{self, HeaderMap},
+    blocking::{Client, RequestBuilder, Response},
+    header::{self, HeaderValue},
     StatusCode,
+    Url,
 };
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use chrono::{
     DateTime,
     Utc,
 };
-use unidecode::unidecode;
 
 use crate::app::{
     Release,
-    message::Message
+    Note,
+    message::Message,
+    oauth,
+    response::{self, Page, ParseFailure, FieldError},
+    ratelimit::{self, RateLimiter, Backoff},
 };
 use crate::utils;
 use crate::CONFIG;
+use crate::config::Config;
 
 #[derive(Debug, Clone)]
 pub enum ParseType {
     Initial,
     Profile,
-    Folders(String),
-    Collection,
+    Folders(u64),
     Wantlist,
+    Identity,
+    Want(i64),
+    Folder(u64),
+    //* folder id, release id
+    FolderRelease(u64, i64),
+    //* folder id, release id, instance id
+    Instance(u64, i64, i64),
+    //* every copy of one release, whatever folder it's in
+    CollectionRelease(i64),
+    //* the names of the notes fields on collection items
+    Fields,
+    //* everything Discogs has on a release, whoever owns it
+    Release(i64),
+    CollectionValue,
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum UpdateError {
     NetworkError,
     IOError,
     ServerError,
     NotFoundError,
     AuthorizationError,
+    RateLimitError,
     UnknownError,
     JSONParseError,
+    ResponseError(String),
     CSVParseError(String),
     ThreadPanicError,
     DBWriteError(String),
+    //* configured, then actual
+    UsernameMismatchError(String, String),
+    NoReleaseError(i64),
+    NotWantedError(i64),
+    NoFolderError(String),
+    FolderExistsError(String),
+    FolderNotEmptyError(String),
+    ProtectedFolderError(String),
+    NotInCollectionError(i64),
+    //* release id, then the ID and folder of each copy
+    MultipleInstancesError(i64, Vec<(i64, String)>),
+    //* release id, copy id
+    NoCopyError(i64, i64),
+    NotSyncedError,
 }
 
 impl std::error::Error for UpdateError {}
@@ -65,12 +103,18 @@ impl std::fmt::Display for UpdateError {
             UpdateError::AuthorizationError => {
                 write!(f, "Error: Discogs could not authorize your request. Check your token.")
             }
+            UpdateError::RateLimitError => {
+                write!(f, "Error: Discogs is still rate limiting requests. Wait a minute and try again.")
+            }
             UpdateError::UnknownError => {
                 write!(f, "An unknown error occurred. Check the logs for more info.")
             }
             UpdateError::JSONParseError => {
                 write!(f, "Error: Could not parse data from Discogs. Please try updating again.")
             }
+            UpdateError::ResponseError(s) => {
+                write!(f, "Error: Discogs sent something cogsy could not read in {}", s)
+            }
             UpdateError::CSVParseError(s) => {
                 write!(f, "Error: Could not parse CSV data: {}", s)
             }
@@ -78,7 +122,43 @@ impl std::fmt::Display for UpdateError {
                 write!(f, "Error: Update thread panicked. Please try again.")
             }
             UpdateError::DBWriteError(e) => {
-                write!(f, "Database error: {}", e.to_string())
+                write!(f, "Database error: {}", e)
+            }
+            UpdateError::NoReleaseError(id) => {
+                write!(f, "Error: Discogs has no release with the ID {}.", id)
+            }
+            UpdateError::NotWantedError(id) => {
+                write!(f, "Error: Release {} is not in your wantlist.", id)
+            }
+            UpdateError::NoFolderError(name) => {
+                write!(f, "Error: There is no folder called `{}`.", name)
+            }
+            UpdateError::FolderExistsError(name) => {
+                write!(f, "Error: There is already a folder called `{}`.", name)
+            }
+            UpdateError::FolderNotEmptyError(name) => {
+                write!(f, "Error: Folder `{}` is not empty. Move or remove its releases first.", name)
+            }
+            UpdateError::ProtectedFolderError(name) => {
+                write!(f, "Error: Folder `{}` is managed by Discogs and can't be changed.", name)
+            }
+            UpdateError::NotInCollectionError(id) => {
+                write!(f, "Error: Release {} is not in your collection.", id)
+            }
+            UpdateError::MultipleInstancesError(id, copies) => {
+                let copies: Vec<String> = copies.iter()
+                    .map(|(copy, folder)| format!("{}/{} in {}", id, copy, folder))
+                    .collect();
+                write!(f, "Error: Release {} is in your collection {} times. Pick a copy: {}.", id, copies.len(), copies.join(", "))
+            }
+            UpdateError::NoCopyError(id, copy) => {
+                write!(f, "Error: Copy {} of release {} is not in your collection.", copy, id)
+            }
+            UpdateError::NotSyncedError => {
+                write!(f, "Error: cogsy doesn't have the Discogs IDs of your folders yet. Run `update` first.")
+            }
+            UpdateError::UsernameMismatchError(configured, actual) => {
+                write!(f, "Error: Your credentials belong to `{}`, not `{}`. Check your username.", actual, configured)
             }
         }
     }
@@ -96,164 +176,384 @@ impl From<std::io::Error> for UpdateError {
     }
 }
 
-pub fn query_discogs(requester: &Client, url: &str) -> Result<String, UpdateError> {
-    match requester.get(url).send() {
-        Ok(response) => {
-            match response.status() {
-                StatusCode::NOT_FOUND => Err(UpdateError::NotFoundError),
-                StatusCode::UNAUTHORIZED => Err(UpdateError::AuthorizationError),
-                StatusCode::INTERNAL_SERVER_ERROR => Err(UpdateError::ServerError),
-                StatusCode::OK => Ok(response.text().unwrap()),
-                _ => {Err(UpdateError::UnknownError)}
-            }
+/// How requests say who they're from.
+#[derive(Debug, Clone)]
+pub enum Auth {
+    /// A personal access token, e.g. `Discogs token=abcd`.
+    Token(String),
+    /// Credentials from `cogsy auth`; every request is signed on its own.
+    OAuth(oauth::Credentials),
+}
+
+impl Auth {
+    /// A personal access token as it comes from the config.
+    pub fn token(token: &str) -> Self {
+        Auth::Token(format!("Discogs token={}", token))
+    }
+
+    /// OAuth credentials if `cogsy auth` has been run, otherwise the token.
+    pub fn from_config(config: &Config) -> Self {
+        config.discogs.as_ref()
+            .and_then(|discogs| discogs.oauth())
+            .map(Auth::OAuth)
+            .unwrap_or_else(|| Auth::token(&config.user.token))
+    }
+
+    fn header(&self, method: &str, url: &Url) -> String {
+        match self {
+            Auth::Token(token) => token.clone(),
+            Auth::OAuth(credentials) => credentials.authorization(method, url),
         }
-        Err(_) => {
-            Err(UpdateError::NetworkError)
+    }
+}
+
+/// An HTTP client that stays under the Discogs rate limit.
+/// Clones share the same rate limiter, so every thread of an update
+/// draws from the one budget.
+#[derive(Debug, Clone)]
+pub struct DiscogsClient {
+    base_url: String,
+    client: Client,
+    auth: Auth,
+    limiter: Arc<RateLimiter>,
+    backoff: Backoff,
+}
+
+impl DiscogsClient {
+    pub fn new(base_url: &str, auth: Auth, limiter: RateLimiter, backoff: Backoff) -> Self {
+        DiscogsClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: Client::builder()
+                .user_agent("cogsy")
+                .build()
+                .unwrap(),
+            auth,
+            limiter: Arc::new(limiter),
+            backoff,
         }
     }
+
+    /// Sends the request made by `build`, waiting on the rate limiter first.
+    /// 429s and 5xxs are retried until the backoff runs out of retries,
+    /// after which the last response is handed back as is.
+    pub fn send<F>(&self, build: F) -> Result<Response, UpdateError>
+    where
+        F: Fn(&Client) -> RequestBuilder
+    {
+        let mut attempt = 0;
+        loop {
+            self.limiter.acquire();
+            //* signed on every attempt, a retry can't reuse the nonce
+            let mut request = build(&self.client).build()
+                .map_err(|_| UpdateError::NetworkError)?;
+            let auth = self.auth.header(request.method().as_str(), request.url());
+            request.headers_mut().insert(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&auth).map_err(|_| UpdateError::AuthorizationError)?
+            );
+            let response = self.client.execute(request)
+                .map_err(|_| UpdateError::NetworkError)?;
+            self.limiter.observe(response.headers());
+
+            let status = response.status();
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS 
+                || status.is_server_error();
+            if !retryable || attempt >= self.backoff.retries {
+                return Ok(response)
+            }
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                self.limiter.exhaust();
+            }
+            //* Discogs may say how long to back off for, but never wait past the max
+            let asked = ratelimit::header_u32(response.headers(), "retry-after")
+                .map(|secs| Duration::from_secs(secs.into()).min(self.backoff.max))
+                .unwrap_or_default();
+            thread::sleep(self.backoff.delay(attempt).max(asked));
+            attempt += 1;
+        }
+    }
+}
+
+pub fn query_discogs(requester: &DiscogsClient, url: &str) -> Result<String, UpdateError> {
+    read_response(requester.send(|client| client.get(url))?)
+}
+
+/// The body of a successful response, or the error its status stands for.
+pub fn read_response(response: Response) -> Result<String, UpdateError> {
+    match response.status() {
+        StatusCode::NOT_FOUND => Err(UpdateError::NotFoundError),
+        StatusCode::UNAUTHORIZED => Err(UpdateError::AuthorizationError),
+        StatusCode::TOO_MANY_REQUESTS => Err(UpdateError::RateLimitError),
+        status if status.is_server_error() => Err(UpdateError::ServerError),
+        //* writes answer with 201 Created or 204 No Content
+        status if status.is_success() => response.text().map_err(|_| UpdateError::NetworkError),
+        _ => {Err(UpdateError::UnknownError)}
+    }
+}
+
+pub fn build_client(auth: &Auth) -> DiscogsClient {
+    DiscogsClient::new(&CONFIG.api_url(), auth.clone(), RateLimiter::default(), Backoff::default())
+}
+
+/// A client for `config` itself, for when CONFIG isn't loaded (or is being set up).
+pub fn client_for(config: &Config) -> DiscogsClient {
+    DiscogsClient::new(&config.api_url(), Auth::from_config(config), RateLimiter::default(), Backoff::default())
 }
 
-pub fn build_client(token: &str) -> Client {
-    let mut headers = HeaderMap::new();
-
-    headers.insert(
-        header::AUTHORIZATION,
-        header::HeaderValue::from_str(token).unwrap());
-    Client::builder()
-        .user_agent("cogsy")
-        .default_headers(headers)
-        .build()
-        .unwrap()
+/// Asks Discogs who the credentials belong to, and checks that it's `username`.
+/// Returns the username as Discogs has it.
+pub fn verify_identity(requester: &DiscogsClient, username: &str) -> Result<String, UpdateError> {
+    let url = build_url(requester, ParseType::Identity, username);
+    let identity: response::Identity = parse_response(&query_discogs(requester, &url)?, "your identity")?;
+    if utils::usernames_match(username, &identity.username) {
+        Ok(identity.username)
+    } else {
+        Err(UpdateError::UsernameMismatchError(username.trim().to_string(), identity.username))
+    }
 }
 
 //builds a url based on its parsetype and user id
-pub fn build_url(parse: ParseType, username: String) -> String {
+pub fn build_url(requester: &DiscogsClient, parse: ParseType, username: &str) -> String {
+    let base = &requester.base_url;
     match parse {
         ParseType::Initial => {
-            format!("https://api.discogs.com/users/{}/collection/folders", username)
+            format!("{}/users/{}/collection/folders", base, username)
         }
         ParseType::Profile => {
-            format!("https://api.discogs.com/users/{}", username)
-        }
-        //* Only use after Initial is called
-        ParseType::Folders(url) => {
-            format!("{}/releases?per_page=100", url)
+            format!("{}/users/{}", base, username)
         }
-        ParseType::Collection => {
-            format!("https://api.discogs.com/users/{}/collection/folders/0/releases?per_page=100", username)
+        //* Only use after Initial is called, with a folder id from there
+        ParseType::Folders(id) => {
+            format!("{}/users/{}/collection/folders/{}/releases?per_page=100", base, username, id)
         }
         ParseType::Wantlist => {
-            format!("https://api.discogs.com/users/{}/wants", username)
+            format!("{}/users/{}/wants?per_page=100", base, username)
+        }
+        ParseType::Want(id) => {
+            format!("{}/users/{}/wants/{}", base, username, id)
+        }
+        ParseType::Folder(id) => {
+            format!("{}/users/{}/collection/folders/{}", base, username, id)
+        }
+        ParseType::FolderRelease(folder, release) => {
+            format!("{}/users/{}/collection/folders/{}/releases/{}", base, username, folder, release)
+        }
+        ParseType::Instance(folder, release, instance) => {
+            format!("{}/users/{}/collection/folders/{}/releases/{}/instances/{}", 
+                base, username, folder, release, instance)
+        }
+        ParseType::CollectionRelease(id) => {
+            format!("{}/users/{}/collection/releases/{}", base, username, id)
+        }
+        ParseType::Fields => {
+            format!("{}/users/{}/collection/fields", base, username)
+        }
+        ParseType::Release(id) => {
+            format!("{}/releases/{}", base, id)
+        }
+        ParseType::CollectionValue => {
+            format!("{}/users/{}/collection/value", base, username)
+        }
+        //* the username is what this finds out
+        ParseType::Identity => {
+            format!("{}/oauth/identity", base)
         }
     }
 }
 
+/// Parses a response body into `T`, turning a field that didn't fit into an
+/// error that names it. `what` says whose field it was, e.g. "your profile".
+pub fn parse_response<T: DeserializeOwned>(text: &str, what: &str) -> Result<T, UpdateError> {
+    response::parse(text).map_err(|failure| match failure {
+        ParseFailure::Malformed => UpdateError::JSONParseError,
+        ParseFailure::Field(e) => UpdateError::ResponseError(format!("{}, {}", what, e)),
+    })
+}
+
 pub fn parse_releases(
-    parse: Rc<ParseType>, 
     text: &str, 
     from_file: bool, c: bool,
     name: &str
 ) -> Result<Vec<Release>, UpdateError> {
-    /*
-    *Step 1: Obtain the total item count
-    *Step 2: Index into "releases" and ensure it is an array
-    *Step 3: Iterate over the array and read each entry into a vec
-    *Step 4: Return the vec
-    */
-
-    let contents: String;
-    let mut releases = Vec::new();
-
     //reading the json file
-    if from_file {
-        contents = read_to_string(text)?;
+    let contents = if from_file {
+        read_to_string(text)?
     } else {
-        contents = text.to_string();
-    }
-    let response: Value = serde_json::from_str(&contents)?;
+        text.to_string()
+    };
+    let page: Page = parse_response(&contents, &format!("a page of {}", name))?;
 
-    let to_index = match parse.as_ref() {
-        ParseType::Collection => String::from("releases"),
-        ParseType::Wantlist => String::from("wants"),
-        _ => String::from("releases")
+    //* each release is parsed on its own so a bad one can be named
+    page.releases.iter().map(|entry| {
+        let release = parse_release(entry)?;
+        if c {
+            println!("'[{}] {}' by {}", Message::info(name), release.title, release.artist)
+        }
+        Ok(release)
+    }).collect()
+}
+
+/// Parses one entry of a folder or the wantlist.
+pub fn parse_release(entry: &Value) -> Result<Release, UpdateError> {
+    response::from_value::<response::Release>(entry)
+        .and_then(to_release)
+        .map_err(|e| UpdateError::ResponseError(
+            format!("{}, {}", response::describe(entry), e)
+        ))
+}
+
+fn to_release(release: response::Release) -> Result<Release, FieldError> {
+    let info = release.basic_information;
+    let artist = info.artists.into_iter().next()
+        .ok_or_else(|| FieldError {
+            field: String::from("basic_information.artists"),
+            reason: String::from("no artists"),
+        })?.name;
+    let added_date = DateTime::parse_from_rfc3339(&release.date_added)
+        .unwrap_or_else(|_| utils::get_utc_now()
+        .with_timezone(&CONFIG.timezone()));
+
+    let catnos = info.labels.iter()
+        .filter_map(|label| label.catno.clone())
+        .collect();
+    let labels = info.labels.into_iter()
+        .map(|label| label.name)
+        .collect();
+    let formats = info.formats.into_iter().map(|format| {
+        let mut name = format.name;
+        let mut qty = format.qty;
+        if name == "Vinyl" {
+            qty.push_str("LP");
+        }
+        name.push(' ');
+        name.push_str(&qty);
+        match format.text {
+            Some(text) if !text.is_empty() => format!("{} ({})", name, text),
+            _ => name,
+        }
+    }).collect();
+    let notes = match release.notes {
+        response::Notes::Fields(notes) => notes.into_iter()
+            .filter(|note| !note.value.is_empty())
+            .map(|note| Note::new(note.field_id as i64, note.value))
+            .collect(),
+        //* a want's notes aren't about a copy of it
+        response::Notes::Other(_) => Vec::new(),
     };
 
-    let releases_raw = response.get(&to_index).ok_or(UpdateError::JSONParseError)?;
-    if let Value::Array(result) = releases_raw {
-        let releaselist = result;
-
-        //deserialization happens here
-        for entry in releaselist {
-            let id_no = entry.get("id")
-                .ok_or(UpdateError::JSONParseError)?
-                .as_u64()
-                .ok_or(UpdateError::JSONParseError)?;
-            let date_raw = entry.get("date_added")
-                .ok_or(UpdateError::JSONParseError)?
-                .as_str().ok_or(UpdateError::JSONParseError)?;
-            //TODO: impl from for ParseResult
-            let added_date = DateTime::parse_from_rfc3339(date_raw)
-                .unwrap_or_else(|_| utils::get_utc_now()
-                .with_timezone(&CONFIG.timezone()));
-            let info = entry.get("basic_information").unwrap();
-
-            
-            //TODO: Figure out how to do this functionally
-            let mut label_names = Vec::<String>::new();
-            let labels = info["labels"].as_array()
-                .ok_or(UpdateError::JSONParseError)?;
-            for label in labels {
-                label_names.push(label["name"].as_str()
-                    .ok_or(UpdateError::JSONParseError)?
-                    .to_string());
-            }
+    Ok(Release {
+        id: release.id as i64,
+        search_string: utils::search_string(&info.title),
+        title: info.title,
+        artist,
+        year: info.year,
+        labels,
+        catnos,
+        formats,
+        date_added: DateTime::<Utc>::from_utc(
+            added_date.naive_utc(), Utc
+        ),
+        instance_id: release.instance_id.map(|id| id as i64),
+        rating: release.rating.min(5),
+        genres: info.genres,
+        styles: info.styles,
+        master_id: Some(info.master_id as i64).filter(|id| *id > 0),
+        thumb: info.thumb,
+        cover_image: info.cover_image,
+        notes,
+    })
+}
 
-            let mut formats = Vec::<String>::new();
-            let formatlist = info["formats"].as_array()
-                .ok_or(UpdateError::JSONParseError)?;
-            for format in formatlist {
-                let mut name = format["name"].as_str()
-                    .ok_or(UpdateError::JSONParseError)?.to_string();
-                let mut qty = format["qty"].as_str()
-                    .ok_or(UpdateError::JSONParseError)?.to_string();
-                let text = format["text"].as_str()
-                    .unwrap_or("").to_string();
-                if name == "Vinyl" {
-                    qty.push_str("LP");
-                }
-                name.push(' ');
-                name.push_str(&qty);
-                if !text.is_empty() {
-                    name.push_str(&format!(" ({})", text));
-                }
-                formats.push(name);
-            }
-            let title = info["title"].as_str()
-                .ok_or(UpdateError::JSONParseError)?.to_string();
-            let artist = info["artists"][0]["name"].as_str()
-                .ok_or(UpdateError::JSONParseError)?
-                .to_string();
-            let search_string = unidecode(&title)
-            .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], "");
-
-            if c {
-                println!("'[{}] {}' by {}", Message::info(name), title, artist)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::app::mock::{MockServer, MockResponse};
+
+    fn client() -> DiscogsClient {
+        // fast enough that the tests don't sit out a real minute
+        DiscogsClient::new("http://unused", Auth::token("test"), RateLimiter::new(6000), Backoff {
+            base: Duration::from_millis(1),
+            max: Duration::from_millis(10),
+            retries: 3,
+        })
+    }
+
+    #[test]
+    fn test_retries_rate_limited_and_failed_requests() {
+        let calls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => MockResponse::status(429, "{}")
+                    .header("X-Discogs-Ratelimit-Remaining", "0"),
+                1 => MockResponse::status(503, "{}"),
+                _ => MockResponse::ok("{\"ok\": true}")
+                    .header("X-Discogs-Ratelimit-Remaining", "57"),
             }
+        });
 
-            releases.push(Release {
-                id: id_no as i64,
-                search_string,
-                title,
-                artist,
-                year: info["year"].as_u64()
-                    .ok_or(UpdateError::JSONParseError)? as u32,
-                labels: label_names,
-                formats,
-                date_added: DateTime::<Utc>::from_utc(
-                    added_date.naive_utc(), Utc
-                )
-            });
+        let body = query_discogs(&client(), &format!("{}/users/test", server.url())).unwrap();
+        assert_eq!(body, "{\"ok\": true}");
+        assert_eq!(server.requests().len(), 3);
+        let first = &server.requests()[0];
+        assert_eq!((first.method.as_str(), first.path.as_str()), ("GET", "/users/test"));
+        assert!(first.body.is_empty());
+        assert_eq!(first.header("authorization"), Some("Discogs token=test"));
+    }
+
+    #[test]
+    fn test_oauth_requests_are_signed() {
+        use percent_encoding::percent_decode_str;
+
+        let server = MockServer::start(|_| MockResponse::ok("{}"));
+        let credentials = oauth::Credentials {
+            consumer_key: "key".into(),
+            consumer_secret: "secret".into(),
+            token: "token".into(),
+            token_secret: "token secret".into(),
+        };
+        let client = DiscogsClient::new(server.url(), Auth::OAuth(credentials), RateLimiter::new(6000), Backoff::default());
+        let url = format!("{}/users/test/wants?per_page=100&page=2", server.url());
+        query_discogs(&client, &url).unwrap();
+        query_discogs(&client, &url).unwrap();
+
+        let mut nonces = Vec::new();
+        for request in server.requests() {
+            let mut fields: Vec<(String, String)> = request.header("authorization").unwrap()
+                .trim_start_matches("OAuth ").split(", ")
+                .filter_map(|field| field.split_once('='))
+                .map(|(k, v)| (k.into(), percent_decode_str(v.trim_matches('"')).decode_utf8_lossy().into_owned()))
+                .collect();
+            let idx = fields.iter().position(|(k, _)| k == "oauth_signature").unwrap();
+            let (_, signature) = fields.remove(idx);
+
+            // the server's side of it: the same parameters, the same secrets
+            let mut params = fields.clone();
+            params.push(("per_page".into(), "100".into()));
+            params.push(("page".into(), "2".into()));
+            let base = format!("{}/users/test/wants", server.url());
+            assert_eq!(signature, oauth::sign("GET", &base, &params, "secret", "token secret"));
+            assert!(fields.contains(&("oauth_token".into(), "token".into())));
+            nonces.push(fields.into_iter().find(|(k, _)| k == "oauth_nonce").unwrap().1);
+        }
+        assert_ne!(nonces[0], nonces[1]);
+    }
+
+    #[test]
+    fn test_gives_up_after_the_last_retry() {
+        let server = MockServer::start(|_| MockResponse::status(429, "{}"));
+        match query_discogs(&client(), &format!("{}/users/test", server.url())) {
+            Err(UpdateError::RateLimitError) => {}
+            other => panic!("expected a rate limit error, got {:?}", other),
         }
-    } else {return Err(UpdateError::JSONParseError);}
-    Ok(releases)
+        // the first try and three retries
+        assert_eq!(server.requests().len(), 4);
+
+        let server = MockServer::start(|_| MockResponse::status(404, "{}"));
+        match query_discogs(&client(), &format!("{}/users/test", server.url())) {
+            Err(UpdateError::NotFoundError) => {}
+            other => panic!("expected a 404, got {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 1);
+    }
 }
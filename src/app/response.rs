@@ -1,100 +1,382 @@
-//* IMPORTANT: This file is deprecated and has no use at this point.
-//* I'm just keeping this around in case I ever need it.
+use std::fmt;
 
-use serde::Deserialize;
+use serde::{Deserialize, de::{DeserializeOwned, IgnoredAny}};
+use serde_json::Value;
+
+/*
+* Typed models of the Discogs responses cogsy reads.
+* Only the fields cogsy uses are here; serde skips the rest.
+* Everything goes through `parse` or `from_value`, which name the field
+* that didn't fit when a response isn't what we expected.
+*/
+
+#[derive(Debug, Deserialize)]
+pub struct Profile {
+    pub username: String,
+    pub name: String,
+    pub registered: String,
+    pub num_for_sale: u32,
+    pub num_collection: u32,
+    pub num_wantlist: u32,
+    pub releases_rated: u32,
+    pub rating_avg: f64,
+}
+
+/// Who a token or OAuth credentials belong to, from /oauth/identity.
+#[derive(Debug, Deserialize)]
+pub struct Identity {
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FolderList {
+    pub folders: Vec<Folder>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Folder {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Pagination {
+    pub pages: u64,
+}
+
+/// One page of a collection folder or the wantlist.
+/// The releases are kept as raw values so each one can be parsed
+/// on its own, and a bad one can be named.
+#[derive(Debug, Deserialize)]
+pub struct Page {
+    pub pagination: Pagination,
+    #[serde(alias = "wants")]
+    pub releases: Vec<Value>,
+}
 
 //sub-structs of Information
 #[derive(Debug, Deserialize)]
 pub struct Format {
-    name: String,
-    qty: String,
-    descriptions: Vec<String>,
+    pub name: String,
+    pub qty: String,
+    #[serde(default)]
+    pub text: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Label {
-    name: String,
-    catno: String,
-    entity_type: String,
-    entity_type_name: String,
-    id: u32,
-    resource_url: String,
+    pub name: String,
+    #[serde(default)]
+    pub catno: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Artist {
-    name: String,
-    anv: String,
-    join: String,
-    tracks: String,
-    id: u64,
-    resource_url: String,
+    pub name: String,
 }
 
 //sub-struct of Release
 #[derive(Debug, Deserialize)]
 pub struct Information {
-    id: u64,
-    master_id: u64,
-    master_url: String,
-    resource_url: String,
-    thumb: String,
-    cover_image: String,
-    title: String,
-    year: u32,
-    formats: Vec<Format>,
-    labels: Vec<Label>,
-    artists: Vec<Artist>,
-    genres: Vec<String>,
-    styles: Vec<String>,
+    pub title: String,
+    pub year: u32,
+    pub formats: Vec<Format>,
+    pub labels: Vec<Label>,
+    pub artists: Vec<Artist>,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    #[serde(default)]
+    pub styles: Vec<String>,
+    //* 0 when the release doesn't belong to a master
+    #[serde(default)]
+    pub master_id: u64,
+    #[serde(default)]
+    pub thumb: String,
+    #[serde(default)]
+    pub cover_image: String,
 }
 
-//sub-struct of Release
+//an entry in a collection folder or the wantlist
 #[derive(Debug, Deserialize)]
-pub struct Notes {
-    field_id: u32,
-    value: String,
+pub struct Release {
+    pub id: u64,
+    //* not there when a release has just been added to the wantlist
+    #[serde(default)]
+    pub date_added: String,
+    //* only collection entries have one
+    #[serde(default)]
+    pub instance_id: Option<u64>,
+    #[serde(default)]
+    pub rating: u8,
+    #[serde(default)]
+    pub notes: Notes,
+    pub basic_information: Information,
 }
 
-//main struct
+/// Collection entries have a list of field values.
+/// Wants have a plain string instead, which cogsy doesn't keep.
 #[derive(Debug, Deserialize)]
-pub struct Release {
-    id: u64,
-    instance_id: u64,
-    date_added: String,
-    rating: u8,
-    basic_information: Information,
-    folder_id: u32,
-    notes: Vec<Notes>,
+#[serde(untagged)]
+pub enum Notes {
+    Fields(Vec<Note>),
+    Other(IgnoredAny),
 }
 
-//sub-struct of Pagination
-#[derive( Debug, Deserialize)]
-pub struct UrlList {
-    // first: String,
-    // prev: String,
-    // next: String,
-    // last: String,
+impl Default for Notes {
+    fn default() -> Self {
+        Notes::Other(IgnoredAny)
+    }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct Note {
+    pub field_id: u64,
+    pub value: String,
+}
 
-//main struct
+/// The collection fields a user has, from /users/{u}/collection/fields.
 #[derive(Debug, Deserialize)]
-pub struct Pagination {
-    page: u32,
-    pages: u32,
-    per_page: u32,
-    items: u32,
-    urls: UrlList,
+pub struct FieldList {
+    pub fields: Vec<CollectionField>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollectionField {
+    pub id: u64,
+    pub name: String,
+}
+
+/// A release from /releases/{id}.
+#[derive(Debug, Deserialize)]
+pub struct ReleaseDetails {
+    pub id: u64,
+    //* any of these can be null
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub released: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub tracklist: Vec<Track>,
+    #[serde(default)]
+    pub extraartists: Vec<Credit>,
+    #[serde(default)]
+    pub identifiers: Vec<Identifier>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Track {
+    #[serde(default)]
+    pub position: String,
+    //* "track", "heading" or "index"
+    #[serde(default, rename = "type_")]
+    pub kind: String,
+    pub title: String,
+    #[serde(default)]
+    pub duration: String,
+    //* the tracks an index track is made of
+    #[serde(default)]
+    pub sub_tracks: Vec<Track>,
 }
 
-//ons struct to rule them all
 #[derive(Debug, Deserialize)]
-pub struct Response {
-    pagination: Pagination,
-    releases: Vec<Release>,
+pub struct Credit {
+    pub name: String,
+    #[serde(default)]
+    pub role: String,
+    #[serde(default)]
+    pub tracks: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Identifier {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// From /users/{u}/collection/value, formatted in the user's currency.
+#[derive(Debug, Deserialize)]
+pub struct CollectionValue {
+    pub minimum: String,
+    pub median: String,
+    pub maximum: String,
+}
+
+/// What Discogs answers with when a release is added to a folder.
+#[derive(Debug, Deserialize)]
+pub struct Instance {
+    pub instance_id: u64,
+}
+
+/// A field of a response that didn't deserialize.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}", self.reason)
+        } else {
+            write!(f, "field `{}`: {}", self.field, self.reason)
+        }
+    }
+}
+
+/// How a response failed to parse: either it isn't JSON at all,
+/// or it is and a field in it is missing or the wrong type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseFailure {
+    Malformed,
+    Field(FieldError),
+}
+
+/// Parses a whole response body.
+pub fn parse<T: DeserializeOwned>(text: &str) -> Result<T, ParseFailure> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|_| ParseFailure::Malformed)?;
+    from_value(&value).map_err(ParseFailure::Field)
+}
+
+/// Deserializes `value`, naming the field that didn't fit if it fails.
+pub fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T, FieldError> {
+    //* nearly everything fits, and then there's no need to find out where it didn't
+    if let Ok(parsed) = T::deserialize(value) {
+        return Ok(parsed)
+    }
+    //* serde only says where it gave up, not in which field, so the value
+    //* is pretty-printed (one field to a line) and the field read off that line
+    let pretty = serde_json::to_string_pretty(value).unwrap_or_default();
+    serde_json::from_str(&pretty).map_err(|e| {
+        let mut path = path_at(&pretty, e.line());
+        let mut reason = e.to_string();
+        //* the message is followed by " at line x column y", which means nothing to users
+        if let Some(idx) = reason.find(" at line ") {
+            reason.truncate(idx);
+        }
+        //* missing fields are reported on the closing brace of their object
+        if let Some(missing) = reason.strip_prefix("missing field `") {
+            path.push(missing.trim_end_matches('`').to_string());
+        }
+        FieldError {field: path.join("."), reason}
+    })
+}
+
+// walks up from `line` (1-based) collecting the keys of the enclosing objects and arrays
+fn path_at(pretty: &str, line: usize) -> Vec<String> {
+    let lines: Vec<&str> = pretty.lines().take(line).collect();
+    let mut path = Vec::new();
+    let mut indent = usize::MAX;
+    for (idx, text) in lines.iter().enumerate().rev() {
+        let trimmed = text.trim_start();
+        let depth = text.len() - trimmed.len();
+        let last = idx + 1 == lines.len();
+        if !last && depth >= indent {
+            continue
+        }
+        //* a closing bracket shares its depth with the line that opened it
+        indent = if last && (trimmed.starts_with('}') || trimmed.starts_with(']')) {
+            depth + 1
+        } else {
+            depth
+        };
+        if let Some(key) = key_of(trimmed) {
+            path.push(key);
+        }
+    }
+    path.reverse();
+    path
+}
+
+fn key_of(line: &str) -> Option<String> {
+    let rest = line.strip_prefix('"')?;
+    let end = rest.find("\":")?;
+    Some(rest[..end].to_string())
+}
+
+/// Names a release for error messages, as well as a broken one can be named.
+pub fn describe(entry: &Value) -> String {
+    let id = entry.get("id")
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| String::from("with no id"));
+    match entry.get("basic_information").and_then(|info| info.get("title")).and_then(|t| t.as_str()) {
+        Some(title) => format!("release {} ({})", id, title),
+        None => format!("release {}", id),
+    }
 }
 
-impl Response {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn release() -> Value {
+        json!({
+            "id": 32654,
+            "date_added": "2021-01-15T12:00:00-08:00",
+            "basic_information": {
+                "title": "Amber",
+                "year": 1994,
+                "formats": [{"name": "CD", "qty": "1"}],
+                "labels": [{"name": "Warp Records", "catno": "WARPCD25"}],
+                "artists": [{"name": "Autechre"}],
+            }
+        })
+    }
+
+    fn field_error(value: &Value) -> FieldError {
+        from_value::<Release>(value).unwrap_err()
+    }
+
+    #[test]
+    fn test_release_parses() {
+        let release: Release = from_value(&release()).unwrap();
+        assert_eq!(release.basic_information.title, "Amber");
+        assert_eq!(release.basic_information.labels[0].catno.as_deref(), Some("WARPCD25"));
+        assert_eq!(release.basic_information.formats[0].text, None);
+    }
+
+    #[test]
+    fn test_errors_name_the_field() {
+        let mut wrong_type = release();
+        wrong_type["basic_information"]["year"] = json!("1994");
+        let e = field_error(&wrong_type);
+        assert_eq!(e.field, "basic_information.year");
+        assert!(e.reason.starts_with("invalid type: string"), "{}", e.reason);
+
+        let mut missing = release();
+        missing["basic_information"].as_object_mut().unwrap().remove("title");
+        let e = field_error(&missing);
+        assert_eq!(e.field, "basic_information.title");
+        assert_eq!(e.reason, "missing field `title`");
+
+        let mut nested = release();
+        nested["basic_information"]["labels"][0]["name"] = Value::Null;
+        assert_eq!(field_error(&nested).field, "basic_information.labels.name");
+
+        let mut top = release();
+        top["id"] = json!(-1);
+        assert_eq!(field_error(&top).field, "id");
+    }
+
+    #[test]
+    fn test_malformed_responses() {
+        assert!(matches!(parse::<Page>("{\"pagination\": "), Err(ParseFailure::Malformed)));
+        match parse::<Page>("{\"releases\": []}") {
+            Err(ParseFailure::Field(e)) => assert_eq!(e.field, "pagination"),
+            other => panic!("expected a field error, got {:?}", other),
+        }
+    }
 
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(&release()), "release 32654 (Amber)");
+        assert_eq!(describe(&json!({})), "release with no id");
+    }
 }
@@ -1,48 +1,59 @@
 use std::{
     io::{self, Write},
-    path::Path,
     thread,
-    sync::Arc,
-    collections::HashMap,
+    sync::{
+        Arc, 
+        mpsc,
+        atomic::{AtomicU64, Ordering},
+    },
+    collections::{BTreeMap, HashMap},
 };
 
-use serde_json::Value;
-use reqwest::blocking::Client;
 use chrono::{DateTime, Utc};
 use indicatif::{MultiProgress, ProgressBar};
 
 use crate::app::{
-    {Release, Folders, Profile},
-    database::{admin, update, purge},
+    {Release, Folders, Profile, CollectionValue, ReleaseDetails, Track, Credit, Identifier},
+    database::{Database, DBError, admin, query, update::{self as dbupdate, SyncSummary}, purge},
     message::{Message, MessageKind},
+    response,
 };
 pub use crate::app::request::*;
 use crate::utils;
 
-pub fn full(username: &str, token: &str, from_cmd: bool, verbose: bool) -> Result<(), UpdateError> {
-    if Path::new(&utils::database_file()).exists() {
-        match admin::check_integrity() {
-            Ok(()) => {},
-            Err(e) => {
-                if from_cmd {
-                    println!("Error on integrity check: {}", e);
-                    println!("Database integrity check failed, purging and refreshing now.")
-                }
-                match purge::complete() {
-                    Ok(()) => {},
-                    Err(e) => {return Err(UpdateError::DBWriteError(e.to_string()))}
-                }
+pub fn full(db: &Database, username: &str, auth: &Auth, from_cmd: bool, verbose: bool) -> Result<SyncSummary, UpdateError> {
+    full_with(db, &build_client(auth), username, from_cmd, verbose)
+}
+
+/// Same as `full`, against whatever API `requester` points at.
+pub fn full_with(
+    db: &Database, 
+    requester: &DiscogsClient, 
+    username: &str, 
+    from_cmd: bool, 
+    verbose: bool
+) -> Result<SyncSummary, UpdateError> {
+    match admin::init_db(db).and_then(|_| admin::check_integrity(db)) {
+        //* purging takes its own snapshot
+        Ok(()) => {db.snapshot()?;},
+        // a newer cogsy wrote this, purging would throw its data away
+        Err(e @ DBError::SchemaVersionErr(_)) => {return Err(e.into())}
+        Err(e) => {
+            if from_cmd {
+                println!("Error on integrity check: {}", e);
+                println!("Database integrity check failed, rebuilding it from Discogs now.")
             }
+            //* listening history can't be pulled again, so it stays
+            purge::discogs(db)?;
         }
     }
     
-    admin::init_db()?;
+    admin::init_db(db)?;
     
     //* pulling data from Discogs
-    let requester = build_client(&token);
-    
+    let value = try_value(requester, username, from_cmd);
     if from_cmd {print!("Updating profile..."); io::stdout().flush().unwrap();}
-    let profile = get_profile(&requester, username)?;
+    let profile = get_profile(requester, username)?;
     if from_cmd {
         print!("{}", Message::set("     Success!", MessageKind::Success));
         if verbose {
@@ -74,7 +85,7 @@ pub fn full(username: &str, token: &str, from_cmd: bool, verbose: bool) -> Resul
         }
     }
     //threads are spawned from within the function
-    let collection = get_collection(requester, username, from_cmd, verbose)?;
+    let collection = get_collection(requester.clone(), username, from_cmd, verbose)?;
 
     if from_cmd {
         if verbose {
@@ -85,46 +96,49 @@ pub fn full(username: &str, token: &str, from_cmd: bool, verbose: bool) -> Resul
     }
     
     //* committing data to db
-    let mut dbhandle = update::DBHandle::new()?;
+    let mut tx = db.begin()?;
 
     if from_cmd {println!("\nWriting to database...\n")}
 
-    dbhandle.update_profile(profile)?;
-    dbhandle.update_wantlist(wantlist)?;
-    dbhandle.update_collection(collection)?;
+    tx.update_profile(profile)?;
+    if let Some(value) = value {
+        tx.add_value(value)?;
+    }
+    let summary = tx.update_wantlist(wantlist)?
+        .merge(tx.update_collection(collection)?);
 
-    //* final integrity check
-    admin::check_integrity()?;
+    //* final integrity check, nothing is written unless it passes
+    tx.commit()?;
     
-    Ok(())
+    Ok(summary)
 }
 
 //todo: pretty output
-pub fn profile(username: &str, token: &str, from_cmd: bool) -> Result<(), UpdateError> {
-    admin::init_db()?;
+pub fn profile(db: &Database, username: &str, auth: &Auth, from_cmd: bool) -> Result<(), UpdateError> {
+    admin::init_db(db)?;
 
     //* pulling data from Discogs
-    let requester = build_client(&token);
+    let requester = build_client(auth);
 
     if from_cmd {println!("Updating profile...")}
     let profile = get_profile(&requester, username)?;
 
     //* committing data to db
-    let mut dbhandle = update::DBHandle::new()?;
+    let mut tx = db.begin()?;
 
     if from_cmd {println!("Writing to database..")}
 
-    dbhandle.update_profile(profile)?;
+    tx.update_profile(profile)?;
 
     //* final integrity check
-    Ok(admin::check_integrity()?)
+    tx.commit()
 }
 
-pub fn wantlist(username: &str, token: &str, from_cmd: bool, verbose: bool) -> Result<(), UpdateError> {
-    admin::init_db()?;
+pub fn wantlist(db: &Database, username: &str, auth: &Auth, from_cmd: bool, verbose: bool) -> Result<SyncSummary, UpdateError> {
+    admin::init_db(db)?;
 
     //* pulling data from Discogs
-    let requester = build_client(&token);
+    let requester = build_client(auth);
 
     if from_cmd {println!("Updating wantlist...")}
 
@@ -139,80 +153,159 @@ pub fn wantlist(username: &str, token: &str, from_cmd: bool, verbose: bool) -> R
     };
 
     //* committing data to db
-    let mut dbhandle = update::DBHandle::new()?;
+    let mut tx = db.begin()?;
 
     if from_cmd {println!("Writing to database...")}
 
-    dbhandle.update_wantlist(wantlist)?;
+    let summary = tx.update_wantlist(wantlist)?;
 
     //* final integrity check
-    Ok(admin::check_integrity()?)
+    tx.commit()?;
+    Ok(summary)
 }
 
-pub fn collection(username: &str, token: &str, from_cmd: bool, verbose: bool) -> Result<(), UpdateError> {
-    admin::init_db()?;
+pub fn collection(db: &Database, username: &str, auth: &Auth, from_cmd: bool, verbose: bool) -> Result<SyncSummary, UpdateError> {
+    admin::init_db(db)?;
 
     //* pulling data from Discogs
-    let requester = build_client(&token);
+    let requester = build_client(auth);
 
     if from_cmd {println!("Updating collection...")}
 
     //threads are spawned from within the function
+    let value = try_value(&requester, username, from_cmd);
     let collection = get_collection(requester, username, from_cmd, verbose)?;
 
     //* committing data to db
-    let mut dbhandle = update::DBHandle::new()?;
+    let mut tx = db.begin()?;
 
     if from_cmd {println!("Writing to database...")}
 
-    dbhandle.update_collection(collection)?;
+    if let Some(value) = value {
+        tx.add_value(value)?;
+    }
+    let summary = tx.update_collection(collection)?;
 
     //* final integrity check
-    Ok(admin::check_integrity()?)
+    tx.commit()?;
+    Ok(summary)
 }
 
-fn get_profile(requester: &Client, username: &str) -> Result<Profile, UpdateError> {
-    let profile_url = build_url(ParseType::Profile, username.to_string());
-    let master_prof: Profile;
+/// The full details of release `id`: from the cache if they've been
+/// fetched before, from Discogs otherwise.
+pub fn details(db: &Database, requester: &DiscogsClient, id: i64) -> Result<ReleaseDetails, UpdateError> {
+    match query::details(db, id)? {
+        Some(details) => Ok(details),
+        None => refresh_details(db, requester, id),
+    }
+}
 
-    //pulling profile and deserialization
-    let response = query_discogs(&requester, &profile_url)?;
-    let profile_raw: Value = serde_json::from_str(&response)
-                .unwrap_or(Value::Null);
-    if let Value::Null = profile_raw {
-        return Err(UpdateError::JSONParseError)
+/// Fetches the details of release `id` again and replaces the cached ones.
+/// If Discogs can't be reached, the cache is left as it was.
+pub fn refresh_details(db: &Database, requester: &DiscogsClient, id: i64) -> Result<ReleaseDetails, UpdateError> {
+    let details = get_details(requester, id)?;
+    dbupdate::cache_details(db, &details)?;
+    Ok(details)
+}
+
+fn get_details(requester: &DiscogsClient, id: i64) -> Result<ReleaseDetails, UpdateError> {
+    //* releases are public, no username in the URL
+    let url = build_url(requester, ParseType::Release(id), "");
+    let text = query_discogs(requester, &url)
+        .map_err(|e| match e {
+            UpdateError::NotFoundError => UpdateError::NoReleaseError(id),
+            e => e,
+        })?;
+    let details: response::ReleaseDetails = parse_response(&text, &format!("release {}", id))?;
+
+    let mut tracklist = Vec::new();
+    for track in details.tracklist {
+        //* an index track is a heading over the tracks it's made of
+        let heading = track.kind == "heading" || track.kind == "index";
+        tracklist.push(Track {
+            position: track.position,
+            title: track.title,
+            duration: track.duration,
+            heading,
+        });
+        tracklist.extend(track.sub_tracks.into_iter().map(|sub| Track {
+            position: sub.position,
+            title: sub.title,
+            duration: sub.duration,
+            heading: false,
+        }));
     }
-    master_prof = Profile {
-        username: profile_raw["username"]
-            .as_str().ok_or(UpdateError::JSONParseError)?.to_string(),
-        real_name: profile_raw["name"]
-            .as_str().ok_or(UpdateError::JSONParseError)?.to_string(),
-        registered: DateTime::<Utc>::from_utc(
-            DateTime::parse_from_rfc3339(
-            profile_raw["registered"]
-            .as_str().ok_or(UpdateError::JSONParseError)?
-            ).unwrap().naive_utc(), Utc
-        ),
-        listings: profile_raw["num_for_sale"]
-            .as_u64().ok_or(UpdateError::JSONParseError)? as u32,
-        collection: profile_raw["num_collection"]
-            .as_u64().ok_or(UpdateError::JSONParseError)? as u32,
-        wantlist: profile_raw["num_wantlist"]
-            .as_u64().ok_or(UpdateError::JSONParseError)? as u32,
-        rated: profile_raw["releases_rated"]
-            .as_u64().ok_or(UpdateError::JSONParseError)? as u32,
-        average_rating: profile_raw["rating_avg"]
-            .as_f64().ok_or(UpdateError::JSONParseError)? as f64,
-    };
-    Ok(master_prof)
+    Ok(ReleaseDetails {
+        id: details.id as i64,
+        country: details.country.unwrap_or_default(),
+        released: details.released.unwrap_or_default(),
+        notes: details.notes.unwrap_or_default().trim().to_string(),
+        tracklist,
+        credits: details.extraartists.into_iter().map(|credit| Credit {
+            name: credit.name,
+            role: credit.role,
+            tracks: credit.tracks,
+        }).collect(),
+        identifiers: details.identifiers.into_iter().map(|identifier| Identifier {
+            kind: identifier.kind,
+            value: identifier.value,
+            description: identifier.description.unwrap_or_default(),
+        }).collect(),
+        fetched: utils::get_utc_now(),
+    })
 }
 
-fn get_wantlist(requester: Client, username: String, c: bool, v: bool) -> Result<Vec<Release>, UpdateError> {
-    let wantlist_url = build_url(ParseType::Wantlist, username);
+//* the value is one point on a trend, the sync goes ahead without it
+fn try_value(requester: &DiscogsClient, username: &str, from_cmd: bool) -> Option<CollectionValue> {
+    match get_value(requester, username) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            if from_cmd {println!("{}", Message::info(format!("Couldn't get your collection value, skipping it: {}", e)))}
+            None
+        }
+    }
+}
+
+fn get_value(requester: &DiscogsClient, username: &str) -> Result<CollectionValue, UpdateError> {
+    let url = build_url(requester, ParseType::CollectionValue, username);
+    let value: response::CollectionValue = parse_response(
+        &query_discogs(requester, &url)?, "your collection value"
+    )?;
+    Ok(CollectionValue {
+        fetched: utils::get_utc_now(),
+        minimum: value.minimum,
+        median: value.median,
+        maximum: value.maximum,
+    })
+}
+
+fn get_profile(requester: &DiscogsClient, username: &str) -> Result<Profile, UpdateError> {
+    let profile_url = build_url(requester, ParseType::Profile, username);
+
+    //pulling profile and deserialization
+    let response = query_discogs(requester, &profile_url)?;
+    let profile: response::Profile = parse_response(&response, "your profile")?;
+    let registered = DateTime::parse_from_rfc3339(&profile.registered)
+        .map_err(|e| UpdateError::ResponseError(
+            format!("your profile, field `registered`: {}", e)
+        ))?;
+    Ok(Profile {
+        username: profile.username,
+        real_name: profile.name,
+        registered: DateTime::<Utc>::from_utc(registered.naive_utc(), Utc),
+        listings: profile.num_for_sale,
+        collection: profile.num_collection,
+        wantlist: profile.num_wantlist,
+        rated: profile.releases_rated,
+        average_rating: profile.rating_avg,
+    })
+}
+
+fn get_wantlist(requester: DiscogsClient, username: String, c: bool, v: bool) -> Result<Vec<Release>, UpdateError> {
+    let wantlist_url = build_url(&requester, ParseType::Wantlist, &username);
     let pgb = if c {ProgressBar::new(60)} else {ProgressBar::hidden()};
     let master_wants = get_full(
-        Arc::new(requester), 
-        ParseType::Wantlist, 
+        requester, 
         wantlist_url, pgb, v, 
         "Wantlist".into()
     )?;
@@ -220,47 +313,33 @@ fn get_wantlist(requester: Client, username: String, c: bool, v: bool) -> Result
     Ok(master_wants)
 }
 
-fn get_collection(requester: Client, username: &str, c: bool, v: bool) -> Result<Folders, UpdateError> {
+fn get_collection(requester: DiscogsClient, username: &str, c: bool, v: bool) -> Result<Folders, UpdateError> {
     //* 1a: Enumerating folders
-    let initial_url = build_url(ParseType::Initial, username.to_string());
+    let initial_url = build_url(&requester, ParseType::Initial, username);
     let folders_raw = query_discogs(&requester, &initial_url)?;
-    let mut folders = HashMap::<String, String>::new();
+    let mut folders = HashMap::<String, u64>::new();
     let total_prog = MultiProgress::new();
 
-    let to_deserialize: Value = serde_json::from_str(&folders_raw)
-        .unwrap_or(Value::Null);
-    if let Value::Null = to_deserialize {
-        return Err(UpdateError::JSONParseError);
+    let folderlist: response::FolderList = parse_response(&folders_raw, "your folder list")?;
+    for folder in folderlist.folders {
+        //* resource_url always points at api.discogs.com, the id works anywhere
+        folders.insert(folder.name, folder.id);
     }
-    let folders_raw = to_deserialize.get("folders");
-    if let Some(Value::Array(result)) = folders_raw {
-        let folderlist = result;
-
-        for raw in folderlist.iter() {
-            let foldername = raw.get("name")
-                .ok_or(UpdateError::JSONParseError)?.as_str()
-                .ok_or(UpdateError::JSONParseError)?.to_string();
-            let folderurl = raw.get("resource_url")
-                .ok_or(UpdateError::JSONParseError)?.as_str()
-                .ok_or(UpdateError::JSONParseError)?.to_string();
-            folders.insert(foldername, folderurl);
-        } 
-    } else {return Err(UpdateError::JSONParseError);}
 
     let mut master_folders: Folders = Folders::new();
     let mut threads = Vec::new();
-    let requester = Arc::new(requester);
     // let (tx, rx) = mpsc::channel();
 
     //*1b: Pulling each folder
-    for (name, folderurl) in folders {
-        let owned_uname = username.to_string();
+    for (name, folderid) in folders {
+        master_folders.ids.insert(name.clone(), folderid as i64);
+        //* clones share one rate limiter, so the folders don't race each other into a 429
         let req_clone = requester.clone();
+        let collection_url = build_url(&requester, ParseType::Folders(folderid), username);
         let pb = if c { ProgressBar::new(60)} else {ProgressBar::hidden()};
         total_prog.add(pb.clone());
         threads.push(thread::spawn( move || -> Result<(String, Vec<Release>), UpdateError> {
-            let collection_url = build_url(ParseType::Folders(folderurl), owned_uname);
-            let releases = get_full(req_clone, ParseType::Collection, collection_url, pb, v, name.clone())?;
+            let releases = get_full(req_clone, collection_url, pb, v, name.clone())?;
             Ok((name, releases))
         }));
     }
@@ -272,57 +351,217 @@ fn get_collection(requester: Client, username: &str, c: bool, v: bool) -> Result
         master_folders.push(name, releases);
     };
     total_prog.join().unwrap();
+
+    //* notes only come with field IDs, the names are a request away
+    //* and not worth failing the sync over, the notes keep their default names instead
+    let fields_url = build_url(&requester, ParseType::Fields, username);
+    let names: HashMap<i64, String> = match query_discogs(&requester, &fields_url)
+        .and_then(|text| parse_response::<response::FieldList>(&text, "your collection fields"))
+    {
+        Ok(fields) => fields.fields.into_iter()
+            .map(|field| (field.id as i64, field.name))
+            .collect(),
+        Err(e) => {
+            if c {println!("{}", Message::info(format!("Couldn't get the names of your collection fields: {}", e)))}
+            HashMap::new()
+        }
+    };
+    for release in master_folders.contents.values_mut().flatten() {
+        for note in release.notes.iter_mut() {
+            if let Some(name) = note.field_id.and_then(|id| names.get(&id)) {
+                note.field = name.clone();
+            }
+        }
+    }
     Ok(master_folders)
 }
 
+/// How many pages of a folder are fetched at once.
+/// The shared rate limiter still decides how fast they actually go out.
+const PREFETCH_WORKERS: u64 = 4;
+
 fn get_full(
-    client: Arc<Client>, 
-    parse: ParseType, 
+    client: DiscogsClient, 
     starting_url: String, 
     _pb: ProgressBar,
     from_cli: bool,
     name: String,
 ) -> Result<Vec<Release>, UpdateError> {
-    use std::rc::Rc;
-
-    let mut url = starting_url;
-    let mut master_vec: Vec<Release> = Vec::new();
-    let parse = Rc::new(parse);
-
-    //main update loop
-    loop {
-        let text =  query_discogs(&client, &url)?;
-        let total: u64;
-        let response: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
-        if let Value::Null = response { //guard clause to return immediately if cannot read
-            return Err(UpdateError::JSONParseError);
-        }
-        let pagination = response.get("pagination").unwrap_or(&Value::Null);
-        if pagination == &Value::Null {
-            return Err(UpdateError::JSONParseError);
-        }
-        if let Value::Object(_) = pagination {
-            total = pagination.get("items")
-                .ok_or(UpdateError::JSONParseError)?
-                .as_u64()
-                .ok_or(UpdateError::JSONParseError)?;
-        } else {
-            return Err(UpdateError::JSONParseError);
+    //* the first page says how many pages there are,
+    //* the rest are fetched concurrently and put back in order
+    let text = query_discogs(&client, &starting_url)?;
+    let pages = page_count(&text)?;
+    let mut master_vec = parse_releases(&text, false, from_cli, &name)?;
+    if pages <= 1 {
+        return Ok(master_vec)
+    }
+
+    let next_page = Arc::new(AtomicU64::new(2));
+    let (tx, rx) = mpsc::channel();
+    let mut workers = Vec::new();
+    for _ in 0..PREFETCH_WORKERS.min(pages - 1) {
+        let client = client.clone();
+        let url = starting_url.clone();
+        let name = name.clone();
+        let next_page = next_page.clone();
+        let tx = tx.clone();
+        workers.push(thread::spawn(move || {
+            loop {
+                let page = next_page.fetch_add(1, Ordering::SeqCst);
+                if page > pages {
+                    break
+                }
+                let releases = query_discogs(&client, &page_url(&url, page))
+                    .and_then(|text| parse_releases(&text, false, from_cli, &name));
+                let failed = releases.is_err();
+                if tx.send((page, releases)).is_err() || failed {
+                    break
+                }
+            }
+        }));
+    }
+    drop(tx);
+
+    let mut fetched = BTreeMap::new();
+    for (page, releases) in rx {
+        match releases {
+            Ok(releases) => {fetched.insert(page, releases);}
+            Err(e) => {
+                //* no point fetching the rest
+                next_page.store(pages + 1, Ordering::SeqCst);
+                return Err(e)
+            }
         }
-        let mut releases = parse_releases(parse.clone(), &text, false, from_cli, &name)?; 
+    }
+    for worker in workers {
+        worker.join().map_err(|_| UpdateError::ThreadPanicError)?;
+    }
+    for (_, mut releases) in fetched {
         master_vec.append(&mut releases);
-        if master_vec.len() as u64 == total {
-            break;
-        } else {
-            //* Quite risky as wantlist is not paginated
-            //* and its json will not have this field
-            //* Should be safe as execution should never reach this branch
-            //* on wantlist update, but is still risky nonetheless
-            url = pagination["urls"]["next"]
-                                .as_str()
-                                .ok_or(UpdateError::JSONParseError)?
-                                .to_string();
-        }
     }
     Ok(master_vec)
-}
\ No newline at end of file
+}
+
+fn page_count(text: &str) -> Result<u64, UpdateError> {
+    let page: response::Page = parse_response(text, "a page of releases")?;
+    Ok(page.pagination.pages)
+}
+
+/// The URL of page `page`, worked out from the URL of the first one.
+fn page_url(url: &str, page: u64) -> String {
+    let (base, query) = match url.find('?') {
+        Some(idx) => (&url[..idx], &url[idx + 1..]),
+        None => (url, ""),
+    };
+    let mut params: Vec<String> = query.split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("page="))
+        .map(String::from)
+        .collect();
+    params.push(format!("page={}", page));
+    format!("{}?{}", base, params.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use serde_json::{json, Value};
+    use crate::app::{
+        mock::{MockServer, MockResponse},
+        ratelimit::{RateLimiter, Backoff},
+    };
+
+    const PER_PAGE: u64 = 3;
+
+    fn client() -> DiscogsClient {
+        DiscogsClient::new("http://unused", Auth::token("test"), RateLimiter::new(6000), Backoff {
+            base: Duration::from_millis(1),
+            max: Duration::from_millis(10),
+            retries: 1,
+        })
+    }
+
+    fn release(id: u64) -> Value {
+        json!({
+            "id": id,
+            "date_added": "2021-01-01T00:00:00-08:00",
+            "basic_information": {
+                "title": format!("Album {}", id),
+                "year": 2000,
+                "artists": [{"name": "Artist"}],
+                "labels": [{"name": "Label", "catno": format!("CAT{}", id)}],
+                "formats": [{"name": "Vinyl", "qty": "1"}],
+            }
+        })
+    }
+
+    // serves `items` releases, PER_PAGE to a page, slowest on the earliest pages
+    fn folder(items: u64) -> MockServer {
+        MockServer::start(move |request| {
+            let page: u64 = request.path.split(&['?', '&'][..])
+                .find_map(|param| param.strip_prefix("page="))
+                .map(|page| page.parse().unwrap())
+                .unwrap_or(1);
+            let pages = items.div_ceil(PER_PAGE);
+            std::thread::sleep(Duration::from_millis(10 * (pages - page)));
+            let first = (page - 1) * PER_PAGE + 1;
+            let releases: Vec<Value> = (first..=(page * PER_PAGE).min(items))
+                .map(release)
+                .collect();
+            MockResponse::ok(json!({
+                "pagination": {
+                    "page": page, "pages": pages, "per_page": PER_PAGE, "items": items,
+                    "urls": {},
+                },
+                "releases": releases,
+            }).to_string())
+        })
+    }
+
+    #[test]
+    fn test_get_full_fetches_every_page_in_order() {
+        let server = folder(14);
+        let url = format!("{}/users/test/collection/folders/0/releases?per_page=3", server.url());
+
+        let releases = get_full(
+            client(), url, ProgressBar::hidden(), false, "All".into()
+        ).unwrap();
+
+        let ids: Vec<i64> = releases.iter().map(|release| release.id).collect();
+        assert_eq!(ids, (1..=14).collect::<Vec<i64>>());
+
+        let mut paths: Vec<String> = server.requests().into_iter()
+            .map(|request| request.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 5);
+        assert!(paths.iter().all(|path| path.contains("per_page=3")));
+        assert!(paths[1..].iter().all(|path| path.contains("&page=")));
+    }
+
+    #[test]
+    fn test_get_full_fails_on_a_bad_page() {
+        let server = MockServer::start(|request| {
+            if request.path.contains("page=3") {
+                return MockResponse::ok("not json")
+            }
+            MockResponse::ok(json!({
+                "pagination": {"page": 1, "pages": 4, "per_page": 1, "items": 4, "urls": {}},
+                "releases": [release(1)],
+            }).to_string())
+        });
+        let url = format!("{}/users/test/collection/folders/0/releases", server.url());
+
+        let result = get_full(
+            client(), url, ProgressBar::hidden(), false, "All".into()
+        );
+        assert!(matches!(result, Err(UpdateError::JSONParseError)));
+    }
+
+    #[test]
+    fn test_page_url() {
+        assert_eq!(page_url("http://x/releases", 2), "http://x/releases?page=2");
+        assert_eq!(page_url("http://x/releases?per_page=100", 3), "http://x/releases?per_page=100&page=3");
+        assert_eq!(page_url("http://x/releases?page=1&per_page=100", 4), "http://x/releases?per_page=100&page=4");
+    }
+}
@@ -1,7 +1,10 @@
 use std::fmt;
 
+use chrono::NaiveDate;
 use regex::Regex;
 
+use crate::app::database::query::{Filter, Term, Field, Comparison};
+
 /*
 The architecture of this code (and a lot of the code itself) was shamelessly stolen from:
 github.com/NerdyPepper/dijo/blob/master/src/command.rs and modified to fit my app.
@@ -14,14 +17,37 @@ pub enum Command {
     UpdateID(String),       //username
     UpdateToken(String),    //token
     Random(bool),           //true = nolog
-    Price(String, f64),     //album name, price
+    Price(String, i64, Paid),   //album name, price in hundredths, the rest
+    PriceFix(String, i64, Paid),    //album name, price in hundredths, the rest
     Listen(String, String), //album name, time
-    Query(String),          //album name
-    QueryWantlist(String),
+    Query(Filter),          //album name or field query
+    QueryWantlist(Filter),
+    WantAdd(i64),           //release id
+    WantRemove(i64),        //release id
+    CollectionAdd(i64, String),     //release id, folder
+    CollectionMove(i64, Option<i64>, String),   //release id, copy id, folder
+    CollectionRemove(i64, Option<i64>),         //release id, copy id
+    FolderCreate(String),
+    FolderRename(String, String),   //folder, new name
+    FolderDelete(String),
+    Rate(i64, u8),                  //release id, stars
     Quit,
     Empty,
 }
 
+/// The optional parts of `price`. Whatever's left out is filled in when it's recorded.
+#[derive(PartialEq, Debug, Default)]
+pub struct Paid {
+    pub currency: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub store: Option<String>,
+}
+
+//* the currencies Discogs deals in; anything else after the price is taken for the store
+const CURRENCIES: &[&str] = &[
+    "USD", "GBP", "EUR", "CAD", "AUD", "JPY", "CHF", "MXN", "BRL", "NZD", "SEK", "ZAR",
+];
+
 #[derive(Debug)]
 pub enum CommandError {
     InvalidCommand(String),
@@ -29,6 +55,9 @@ pub enum CommandError {
     InvalidSyntax(String, String),
     NotEnoughArgs(String, u32),
     TooManyArgs(String, u32),
+    EmptyField(String),
+    InvalidFieldValue(String, String),
+    UnclosedQuote(String),
 }
 
 impl std::error::Error for CommandError {}
@@ -49,6 +78,15 @@ impl fmt::Display for CommandError {
             CommandError::TooManyArgs(s, n) => {
                 write!(f, "Error: Command `{}` only requires at most {} argument(s).", s, n)
             },
+            CommandError::EmptyField(s) => {
+                write!(f, "Error: Field `{}` needs a value, e.g. `{}:value`", s, s)
+            },
+            CommandError::InvalidFieldValue(n, s) => {
+                write!(f, "Error: Invalid value `{}` for field `{}`", s, n)
+            },
+            CommandError::UnclosedQuote(s) => {
+                write!(f, "Error: Unclosed double quote in `{}`", s)
+            },
         }
     }
 }
@@ -133,35 +171,46 @@ impl Command {
                 }
             },
             "price" => {
-                if strings.len() == 1 {
+                //* `price fix` corrects the price recorded last instead of adding one
+                let fixing = strings.get(1) == Some(&"fix");
+                let mut rest = input.trim()[strings[0].len()..].trim_start();
+                if fixing {
+                    rest = rest["fix".len()..].trim_start();
+                }
+                if rest.is_empty() {
                     return Err(CommandError::NotEnoughArgs(first, 2))
                 }
-                let argv = splitter(input.trim());
-                match argv {
-                    Some(mut args) => {
-                        if args.len() < 3 {
-                            return Err(CommandError::NotEnoughArgs(first, 2));
-                        }
-                        let extra_args: Vec<&str> = args[1].trim().split(' ').collect();
-                        if extra_args.len() > 1 {
-                            Err(CommandError::TooManyArgs(first, 2))
-                        } else if args[1].is_empty() {
-                            Err(CommandError::NotEnoughArgs(first, 2))
-                        } else {
-                            args[1].retain(|c| c != ' ');
-                            let price = args[1].parse::<f64>();
-                            match price {
-                                Ok(price) => {
-                                    Ok(Command::Price(args[2].clone(), price))},
-                                Err(_e) => {
-                                    Err(CommandError::InvalidSyntax(
-                                        args[0].clone(), 
-                                        args[1].clone()))
-                                }
-                            }
-                        }
+                if !rest.starts_with('"') {
+                    return Err(CommandError::InvalidAlbum)
+                }
+                let mut args = arguments(input, &strings)?;
+                if fixing {
+                    args.remove(0);
+                }
+                if args.len() < 2 {
+                    return Err(CommandError::NotEnoughArgs(first, 2));
+                }
+                let (price, mut currency) = parse_price(&args[1])
+                    .ok_or_else(|| CommandError::InvalidSyntax(first.clone(), args[1].clone()))?;
+                let mut date = None;
+                let mut rest = &args[2..];
+                //* currency and date can come in either order, the store is whatever's left
+                while let Some(arg) = rest.first() {
+                    if currency.is_none() && CURRENCIES.contains(&arg.to_uppercase().as_str()) {
+                        currency = Some(arg.to_uppercase());
+                    } else if date.is_none() && NaiveDate::parse_from_str(arg, "%Y-%m-%d").is_ok() {
+                        date = NaiveDate::parse_from_str(arg, "%Y-%m-%d").ok();
+                    } else {
+                        break
                     }
-                    None => Err(CommandError::InvalidAlbum)
+                    rest = &rest[1..];
+                }
+                let store = if rest.is_empty() {None} else {Some(rest.join(" "))};
+                let paid = Paid {currency, date, store};
+                if fixing {
+                    Ok(Command::PriceFix(args[0].clone(), price, paid))
+                } else {
+                    Ok(Command::Price(args[0].clone(), price, paid))
                 }
             },
             "listen" => {
@@ -186,30 +235,85 @@ impl Command {
                 }
             },
             "query" => {
-                if strings.len() == 1 {
-                    return Err(CommandError::NotEnoughArgs(first, 1));
-                }
+                let mut rest = input.trim()[strings[0].len()..].trim_start();
                 let mut from_wantlist = false;
-                if strings[1] == "-w" {
+                if strings.len() > 1 && (strings[1] == "-w" || strings[1] == "--wantlist") {
                     from_wantlist = true;
+                    rest = rest[strings[1].len()..].trim_start();
                 }
-                let argv = splitter(input.trim());
-                match argv {
-                    Some(args) => {
-                        let query = args[2]
-                        .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], "");
-                        
-                        if !args[1].is_empty() {
-                            Err(CommandError::TooManyArgs(first, 1))
-                        } else if args.len() < 3 {
-                            Err(CommandError::NotEnoughArgs(first, 1))
-                        } else if from_wantlist {
-                            Ok(Command::QueryWantlist(query))
-                        } else {
-                            Ok(Command::Query(query))
+                if rest.is_empty() {
+                    return Err(CommandError::NotEnoughArgs(first, 1));
+                }
+                let filter = parse_filter(rest)?;
+                if from_wantlist {
+                    Ok(Command::QueryWantlist(filter))
+                } else {
+                    Ok(Command::Query(filter))
+                }
+            },
+            "want" => {
+                match strings.len() {
+                    1 | 2 => Err(CommandError::NotEnoughArgs(first, 2)),
+                    3 => {
+                        let id = parse_release_id(strings[2])
+                            .ok_or_else(|| CommandError::InvalidSyntax(first.clone(), strings[2].to_string()))?;
+                        match strings[1] {
+                            "add" => Ok(Command::WantAdd(id)),
+                            "remove" | "rm" => Ok(Command::WantRemove(id)),
+                            _ => Err(CommandError::InvalidSyntax(first, strings[1].to_string())),
                         }
                     }
-                    None => Err(CommandError::InvalidAlbum)
+                    _ => Err(CommandError::TooManyArgs(first, 2)),
+                }
+            },
+            "collection" => {
+                let args = arguments(input, &strings)?;
+                if args.len() < 2 {
+                    return Err(CommandError::NotEnoughArgs(first, 2))
+                }
+                //* only moving and removing pick out one copy, adding always makes a new one
+                let (id, copy) = match args[0].as_str() {
+                    "move" | "mv" | "remove" | "rm" => parse_copy(&args[1]),
+                    _ => parse_release_id(&args[1]).map(|id| (id, None)),
+                }.ok_or_else(|| CommandError::InvalidSyntax(first.clone(), args[1].clone()))?;
+                match (args[0].as_str(), args.len()) {
+                    //* new copies go to Uncategorized unless told otherwise
+                    ("add", 2) => Ok(Command::CollectionAdd(id, String::from("Uncategorized"))),
+                    ("add", 3) => Ok(Command::CollectionAdd(id, args[2].clone())),
+                    ("move" | "mv", 2) => Err(CommandError::NotEnoughArgs(first, 3)),
+                    ("move" | "mv", 3) => Ok(Command::CollectionMove(id, copy, args[2].clone())),
+                    ("remove" | "rm", 2) => Ok(Command::CollectionRemove(id, copy)),
+                    ("add" | "move" | "mv" | "remove" | "rm", _) => Err(CommandError::TooManyArgs(first, 3)),
+                    (other, _) => Err(CommandError::InvalidSyntax(first, other.to_string())),
+                }
+            },
+            "folder" => {
+                let args = arguments(input, &strings)?;
+                if args.len() < 2 {
+                    return Err(CommandError::NotEnoughArgs(first, 2))
+                }
+                match (args[0].as_str(), args.len()) {
+                    ("create" | "new", 2) => Ok(Command::FolderCreate(args[1].clone())),
+                    ("rename", 2) => Err(CommandError::NotEnoughArgs(first, 3)),
+                    ("rename", 3) => Ok(Command::FolderRename(args[1].clone(), args[2].clone())),
+                    ("delete" | "rm", 2) => Ok(Command::FolderDelete(args[1].clone())),
+                    ("create" | "new" | "delete" | "rm", _) => Err(CommandError::TooManyArgs(first, 2)),
+                    ("rename", _) => Err(CommandError::TooManyArgs(first, 3)),
+                    (other, _) => Err(CommandError::InvalidSyntax(first, other.to_string())),
+                }
+            },
+            "rate" => {
+                match strings.len() {
+                    1 | 2 => Err(CommandError::NotEnoughArgs(first, 2)),
+                    3 => {
+                        let id = parse_release_id(strings[1])
+                            .ok_or_else(|| CommandError::InvalidSyntax(first.clone(), strings[1].to_string()))?;
+                        match strings[2].parse::<u8>() {
+                            Ok(rating) if rating <= 5 => Ok(Command::Rate(id, rating)),
+                            _ => Err(CommandError::InvalidSyntax(first, strings[2].to_string())),
+                        }
+                    }
+                    _ => Err(CommandError::TooManyArgs(first, 2)),
                 }
             },
             "quit" | "q" => {
@@ -223,4 +327,411 @@ impl Command {
             },
         }
     }
-}
\ No newline at end of file
+}
+
+//the arguments after the command name, with anything in double quotes kept together
+fn arguments(input: &str, strings: &[&str]) -> Result<Vec<String>, CommandError> {
+    let rest = input.trim()[strings[0].len()..].trim_start();
+    Ok(split_terms(rest)?.into_iter()
+        .map(|arg| arg.replace('"', ""))
+        .filter(|arg| !arg.trim().is_empty())
+        .collect())
+}
+
+//a price such as 25, 25.00, $25 or 25EUR in hundredths, with the currency if it says
+fn parse_price(s: &str) -> Option<(i64, Option<String>)> {
+    let symbols = [('$', "USD"), ('£', "GBP"), ('€', "EUR"), ('¥', "JPY")];
+    let mut currency = None;
+    let mut amount = s.trim();
+    for (symbol, code) in &symbols {
+        if let Some(stripped) = amount.strip_prefix(*symbol) {
+            currency = Some(code.to_string());
+            amount = stripped;
+        }
+    }
+    if currency.is_none() {
+        let digits = amount.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let code = amount[digits.len()..].to_uppercase();
+        if !code.is_empty() {
+            if !CURRENCIES.contains(&code.as_str()) {
+                return None
+            }
+            currency = Some(code);
+            amount = digits;
+        }
+    }
+    //* read digit by digit rather than through a float, so 0.29 is 29 and not 28
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if (whole.is_empty() && fraction.is_empty())
+        || fraction.len() > 2
+        || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return None
+    }
+    let whole: i64 = if whole.is_empty() {0} else {whole.parse().ok()?};
+    let fraction: i64 = format!("{:0<2}", fraction).parse().ok()?;
+    Some((whole.checked_mul(100)?.checked_add(fraction)?, currency))
+}
+
+/// A Discogs release ID, as a number or the way Discogs writes it (`[r1234]` or `r1234`).
+pub fn parse_release_id(s: &str) -> Option<i64> {
+    let s = s.trim().trim_start_matches('[').trim_end_matches(']');
+    s.strip_prefix('r').unwrap_or(s)
+        .parse().ok()
+        .filter(|id| *id > 0)
+}
+
+/// A release ID, optionally followed by the Discogs ID of one copy of it: `11583/501234567`.
+pub fn parse_copy(s: &str) -> Option<(i64, Option<i64>)> {
+    let s = s.trim().trim_start_matches('[').trim_end_matches(']');
+    match s.split_once('/') {
+        Some((id, copy)) => {
+            let copy = copy.trim().parse().ok().filter(|copy| *copy > 0)?;
+            Some((parse_release_id(id)?, Some(copy)))
+        }
+        None => Some((parse_release_id(s)?, None)),
+    }
+}
+
+/*
+*A query is a list of terms separated by whitespace, all of which have to match.
+*A term is either free text, matched against everything, or `field:value`.
+*Only the known field names count, so `Halo: Reach` is still free text.
+*Anything in double quotes is kept together, so `artist:"Boards of Canada"`
+*is one term, and so is `"Music Has The Right To Children"`.
+*Years can be compared: `year:1998`, `year:>1995`, `year:<=2000`.
+*/
+pub fn parse_filter(input: &str) -> Result<Filter, CommandError> {
+    let mut filter = Filter::default();
+
+    for word in split_terms(input)? {
+        //* a colon only introduces a field if everything before it is a plain word
+        let field = match word.find(':') {
+            Some(idx) if word[..idx].chars().all(|c| c.is_ascii_alphabetic()) && idx > 0 => {
+                Some((word[..idx].to_lowercase(), word[idx + 1..].replace('"', "")))
+            }
+            _ => None
+        };
+        let (name, value) = match field {
+            Some(field) => field,
+            None => {
+                filter.terms.push(Term::Text(word.replace('"', "")));
+                continue
+            }
+        };
+        //* year isn't matched as text, so it has no Field
+        let field = match name.as_str() {
+            "artist" => Some(Field::Artist),
+            "title" => Some(Field::Title),
+            "label" | "labels" => Some(Field::Label),
+            "format" | "formats" => Some(Field::Format),
+            "catno" => Some(Field::Catno),
+            "genre" | "genres" => Some(Field::Genre),
+            "style" | "styles" => Some(Field::Style),
+            "year" => None,
+            //* not a field after all, just a title with a colon in it
+            _ => {
+                filter.terms.push(Term::Text(word.replace('"', "")));
+                continue
+            }
+        };
+        if value.trim().is_empty() {
+            return Err(CommandError::EmptyField(name))
+        }
+        let term = match field {
+            Some(field) => Term::Field(field, value),
+            None => parse_year(&value)
+                .ok_or_else(|| CommandError::InvalidFieldValue(name.clone(), value.clone()))?,
+        };
+        filter.terms.push(term);
+    }
+
+    Ok(filter)
+}
+
+//splits on whitespace outside of double quotes, keeping the quotes in
+fn split_terms(input: &str) -> Result<Vec<String>, CommandError> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        return Err(CommandError::UnclosedQuote(input.to_string()))
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    Ok(terms)
+}
+
+fn parse_year(value: &str) -> Option<Term> {
+    let value = value.trim();
+    let (comparison, year) = if let Some(year) = value.strip_prefix(">=") {
+        (Comparison::Ge, year)
+    } else if let Some(year) = value.strip_prefix("<=") {
+        (Comparison::Le, year)
+    } else if let Some(year) = value.strip_prefix('>') {
+        (Comparison::Gt, year)
+    } else if let Some(year) = value.strip_prefix('<') {
+        (Comparison::Lt, year)
+    } else {
+        (Comparison::Eq, value.strip_prefix('=').unwrap_or(value))
+    };
+    year.trim().parse().ok().map(|year| Term::Year(comparison, year))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(field: Field, value: &str) -> Term {
+        Term::Field(field, value.to_string())
+    }
+
+    #[test]
+    fn test_query_with_fields() {
+        let cmd = Command::parse(
+            r#":query artist:"Boards of Canada" year:>1995 format:vinyl label:warp"#
+        ).unwrap();
+        assert_eq!(cmd, Command::Query(Filter {
+            terms: vec![
+                field(Field::Artist, "Boards of Canada"),
+                Term::Year(Comparison::Gt, 1995),
+                field(Field::Format, "vinyl"),
+                field(Field::Label, "warp"),
+            ]
+        }));
+    }
+
+    #[test]
+    fn test_query_genres_and_styles() {
+        assert_eq!(
+            Command::parse(r#"query genre:electronic styles:"trip hop""#).unwrap(),
+            Command::Query(Filter {
+                terms: vec![
+                    field(Field::Genre, "electronic"),
+                    field(Field::Style, "trip hop"),
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_query_plain_album_name() {
+        assert_eq!(
+            Command::parse(r#"query "Don't Stop""#).unwrap(),
+            Command::Query(Filter::text("Don't Stop"))
+        );
+        assert_eq!(
+            Command::parse(r#"query -w "Halo: Reach" year:<=2010"#).unwrap(),
+            Command::QueryWantlist(Filter {
+                terms: vec![
+                    Term::Text(String::from("Halo: Reach")),
+                    Term::Year(Comparison::Le, 2010),
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_want_commands() {
+        assert_eq!(Command::parse(":want add 11583").unwrap(), Command::WantAdd(11583));
+        assert_eq!(Command::parse("want remove [r11583]").unwrap(), Command::WantRemove(11583));
+        assert_eq!(Command::parse("want rm r11583").unwrap(), Command::WantRemove(11583));
+        assert!(matches!(Command::parse("want add"), Err(CommandError::NotEnoughArgs(_, 2))));
+        assert!(matches!(
+            Command::parse("want add Geogaddi"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "Geogaddi"
+        ));
+        assert!(matches!(
+            Command::parse("want keep 11583"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "keep"
+        ));
+        assert!(matches!(Command::parse("want add 1 2"), Err(CommandError::TooManyArgs(_, 2))));
+    }
+
+    #[test]
+    fn test_collection_commands() {
+        assert_eq!(
+            Command::parse(":collection add 11583").unwrap(),
+            Command::CollectionAdd(11583, String::from("Uncategorized"))
+        );
+        assert_eq!(
+            Command::parse(r#"collection move r11583 "Ambient Works""#).unwrap(),
+            Command::CollectionMove(11583, None, String::from("Ambient Works"))
+        );
+        assert_eq!(Command::parse("collection rm [r11583]").unwrap(), Command::CollectionRemove(11583, None));
+        assert_eq!(
+            Command::parse("collection mv r11583/501234567 Electronic").unwrap(),
+            Command::CollectionMove(11583, Some(501234567), String::from("Electronic"))
+        );
+        assert_eq!(
+            Command::parse("collection rm [r11583/501234567]").unwrap(),
+            Command::CollectionRemove(11583, Some(501234567))
+        );
+        assert!(matches!(
+            Command::parse("collection add 11583/501234567"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "11583/501234567"
+        ));
+        assert!(matches!(
+            Command::parse("collection rm 11583/"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "11583/"
+        ));
+        assert!(matches!(Command::parse("collection move 11583"), Err(CommandError::NotEnoughArgs(_, 3))));
+        assert!(matches!(Command::parse("collection rm 1 Electronic"), Err(CommandError::TooManyArgs(_, 3))));
+        assert!(matches!(
+            Command::parse("collection add Geogaddi"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "Geogaddi"
+        ));
+
+        assert_eq!(
+            Command::parse(r#"folder create "Ambient Works""#).unwrap(),
+            Command::FolderCreate(String::from("Ambient Works"))
+        );
+        assert_eq!(
+            Command::parse(r#"folder rename Electronic "Ambient Works""#).unwrap(),
+            Command::FolderRename(String::from("Electronic"), String::from("Ambient Works"))
+        );
+        assert_eq!(Command::parse("folder delete Electronic").unwrap(), Command::FolderDelete(String::from("Electronic")));
+        assert!(matches!(Command::parse("folder create"), Err(CommandError::NotEnoughArgs(_, 2))));
+        assert!(matches!(Command::parse("folder create Ambient Works"), Err(CommandError::TooManyArgs(_, 2))));
+        assert!(matches!(
+            Command::parse("folder empty Electronic"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "empty"
+        ));
+    }
+
+    #[test]
+    fn test_rate_command() {
+        assert_eq!(Command::parse(":rate 32654 4").unwrap(), Command::Rate(32654, 4));
+        assert_eq!(Command::parse("rate [r32654] 0").unwrap(), Command::Rate(32654, 0));
+        assert!(matches!(Command::parse("rate 32654"), Err(CommandError::NotEnoughArgs(_, 2))));
+        assert!(matches!(
+            Command::parse("rate 32654 6"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "6"
+        ));
+        assert!(matches!(
+            Command::parse("rate Amber 5"),
+            Err(CommandError::InvalidSyntax(_, s)) if s == "Amber"
+        ));
+    }
+
+    #[test]
+    fn test_price_command() {
+        assert_eq!(
+            Command::parse(r#":price "Music Has the Right to Children" 25.00"#).unwrap(),
+            Command::Price(String::from("Music Has the Right to Children"), 2500, Paid::default())
+        );
+        assert_eq!(
+            Command::parse(r#"price "Geogaddi" €32.50 2026-01-21 Rough Trade East"#).unwrap(),
+            Command::Price(String::from("Geogaddi"), 3250, Paid {
+                currency: Some(String::from("EUR")),
+                date: Some(NaiveDate::from_ymd(2026, 1, 21)),
+                store: Some(String::from("Rough Trade East")),
+            })
+        );
+        assert_eq!(
+            Command::parse(r#"price "Geogaddi" 30 2026-01-21 gbp "Phonica Records""#).unwrap(),
+            Command::Price(String::from("Geogaddi"), 3000, Paid {
+                currency: Some(String::from("GBP")),
+                date: Some(NaiveDate::from_ymd(2026, 1, 21)),
+                store: Some(String::from("Phonica Records")),
+            })
+        );
+        // three letters that aren't a currency belong to the store
+        assert_eq!(
+            Command::parse(r#"price "Geogaddi" 18CAD HMV"#).unwrap(),
+            Command::Price(String::from("Geogaddi"), 1800, Paid {
+                currency: Some(String::from("CAD")),
+                date: None,
+                store: Some(String::from("HMV")),
+            })
+        );
+        assert_eq!(
+            Command::parse(r#"price fix "Geogaddi" 31.50 Rough Trade"#).unwrap(),
+            Command::PriceFix(String::from("Geogaddi"), 3150, Paid {
+                currency: None,
+                date: None,
+                store: Some(String::from("Rough Trade")),
+            })
+        );
+        assert!(matches!(Command::parse(r#"price "Geogaddi""#), Err(CommandError::NotEnoughArgs(_, 2))));
+        assert!(matches!(Command::parse("price fix"), Err(CommandError::NotEnoughArgs(_, 2))));
+        assert!(matches!(Command::parse("price fix Geogaddi 25"), Err(CommandError::InvalidAlbum)));
+        assert!(matches!(Command::parse("price Geogaddi 25"), Err(CommandError::InvalidAlbum)));
+        assert_eq!(
+            Command::parse(r#"price "Geogaddi" 0.29"#).unwrap(),
+            Command::Price(String::from("Geogaddi"), 29, Paid::default())
+        );
+        for bad in &["cheap", "-5", "25XYZ", "25.505", "1e3", "."] {
+            assert!(matches!(
+                Command::parse(&format!(r#"price "Geogaddi" {}"#, bad)),
+                Err(CommandError::InvalidSyntax(_, s)) if s == *bad
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unquoted_titles_with_colons() {
+        assert_eq!(
+            Command::parse(":query Halo: Reach").unwrap(),
+            Command::Query(Filter {
+                terms: vec![
+                    Term::Text(String::from("Halo:")),
+                    Term::Text(String::from("Reach")),
+                ]
+            })
+        );
+        assert_eq!(
+            Command::parse("query Selected Ambient Works: Volume II year:1994").unwrap(),
+            Command::Query(Filter {
+                terms: vec![
+                    Term::Text(String::from("Selected")),
+                    Term::Text(String::from("Ambient")),
+                    Term::Text(String::from("Works:")),
+                    Term::Text(String::from("Volume")),
+                    Term::Text(String::from("II")),
+                    Term::Year(Comparison::Eq, 1994),
+                ]
+            })
+        );
+        // an unknown name is text too, rather than an error
+        assert_eq!(
+            Command::parse("query mood:ambient").unwrap(),
+            Command::Query(Filter::text("mood:ambient"))
+        );
+    }
+
+    #[test]
+    fn test_malformed_queries() {
+        assert!(matches!(
+            Command::parse("query artist:"),
+            Err(CommandError::EmptyField(f)) if f == "artist"
+        ));
+        assert!(matches!(
+            Command::parse("query year:>nineties"),
+            Err(CommandError::InvalidFieldValue(f, v)) if f == "year" && v == ">nineties"
+        ));
+        assert!(matches!(
+            Command::parse(r#"query artist:"Boards of Canada"#),
+            Err(CommandError::UnclosedQuote(_))
+        ));
+        assert!(matches!(
+            Command::parse("query -w"),
+            Err(CommandError::NotEnoughArgs(_, 1))
+        ));
+    }
+}
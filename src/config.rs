@@ -1,3 +1,4 @@
+use std::env;
 use std::fs::{read_to_string, OpenOptions};
 use std::io::{self, Write};
 use serde::{Deserialize, Serialize};
@@ -19,14 +20,20 @@ use cursive::theme::{
 };
 use crate::app::{
     database::query::SortOrder,
-    message::Message,
+    message::{Message, MessageKind},
+    oauth::Credentials,
+    request::{self, UpdateError},
 };
 use crate::utils;
 
+/// Where requests go unless the config or `COGSY_API_URL` says otherwise.
+pub const DEFAULT_API_URL: &str = "https://api.discogs.com";
+
 #[derive(Deserialize, Serialize)]
 pub struct Config {
     pub user: User,
     pub appearance: Option<Appearance>,
+    pub discogs: Option<Discogs>,
 }
 
 impl Config {
@@ -49,6 +56,19 @@ impl Config {
             std::process::exit(2);
         })
     }
+    /// The base URL of the Discogs API, without a trailing slash.
+    /// `COGSY_API_URL` overrides the config file.
+    pub fn api_url(&self) -> String {
+        self.api_url_with(env::var("COGSY_API_URL").ok())
+    }
+    // the same, with the environment variable passed in so it can be tested
+    fn api_url_with(&self, from_env: Option<String>) -> String {
+        from_env
+            .or_else(|| self.discogs.as_ref().and_then(|d| d.api_url.clone()))
+            .filter(|url| !url.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_API_URL.to_string())
+            .trim().trim_end_matches('/').to_string()
+    }
     pub fn timezone(&self) -> FixedOffset {
         let raw_tz = self.user.timezone;
         if raw_tz < 0.0 {
@@ -59,16 +79,38 @@ impl Config {
     }
     pub fn first_init() {
         println!("User information not initialized.");
-        println!("Username:");
-        let mut username = String::new();
-        print!(">>> "); io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut username)
-            .expect("Oops, could not read line.");
-        println!("Token:");
-        print!(">>> "); io::stdout().flush().unwrap();
-        let mut token = String::new();
-        io::stdin().read_line(&mut token)
-            .expect("Oops, could not read line.");
+        let (username, token) = loop {
+            let username = prompt("Username:");
+            let token = prompt("Token (leave empty to authorize with `cogsy auth` instead):");
+            if token.is_empty() {
+                println!("{}", Message::hint(
+                    "Run `cogsy auth` to authorize cogsy, then `cogsy config verify` to check it."
+                ));
+                break (username, token)
+            }
+            //* catch typos now rather than halfway through the first sync
+            println!("Checking your token with Discogs...");
+            let check = Config {
+                user: User {username: username.clone(), token: token.clone(), timezone: 0.0},
+                appearance: None,
+                discogs: None,
+            };
+            match request::verify_identity(&request::client_for(&check), &username) {
+                Ok(username) => {
+                    println!("{}", Message::success(format!("Token OK, signed in as `{}`.", username)));
+                    break (username, token)
+                }
+                Err(e @ UpdateError::AuthorizationError) | Err(e @ UpdateError::UsernameMismatchError(..)) => {
+                    eprintln!("{}", Message::set(e.to_string(), MessageKind::Error));
+                    println!("Please try again.");
+                }
+                Err(e) => {
+                    eprintln!("{}", Message::set(e.to_string(), MessageKind::Error));
+                    println!("{}", Message::hint("Could not check your token. Run `cogsy config verify` later."));
+                    break (username, token)
+                }
+            }
+        };
         println!("Timezone:");
         let mut timezone_raw = String::new(); let mut timezone: f32;
         loop {
@@ -92,28 +134,69 @@ impl Config {
         }
         let config = Config {
             user: User { 
-                username: username.trim().to_string(),
-                token: token.trim().to_string(),
+                username,
+                token,
                 timezone,
             },
             appearance: None,
+            discogs: None,
         };
-        if let Ok(new_config) = toml::to_string(&config) {
-            match OpenOptions::new().create(true).write(true).open(&utils::config_file()) {
-                Ok(ref mut file) => {file.write_all(new_config.as_bytes()).unwrap();},
-                Err(_) => {panic!("Could not create config file!");}
-            }
+        if config.save().is_err() {
+            panic!("Could not create config file!");
         }
     }
+    /// Writes the config back to the config file.
+    pub fn save(&self) -> io::Result<()> {
+        let new_config = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        OpenOptions::new().create(true).write(true).truncate(true)
+            .open(utils::config_file())?
+            .write_all(new_config.as_bytes())
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct User {
     pub username: String,
+    //* can be left out once `cogsy auth` has been run
+    #[serde(default)]
     pub token: String,
     pub timezone: f32,
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Discogs {
+    /// Points cogsy at something other than api.discogs.com, e.g. a local stand-in.
+    pub api_url: Option<String>,
+    /// The key and secret of an application registered with Discogs, for `cogsy auth`.
+    pub consumer_key: Option<String>,
+    pub consumer_secret: Option<String>,
+    /// Written by `cogsy auth`. When set, requests are signed with these
+    /// instead of sending the token.
+    pub oauth_token: Option<String>,
+    pub oauth_token_secret: Option<String>,
+}
+
+impl Discogs {
+    /// The application's credentials, without a user token yet.
+    pub fn consumer(&self) -> Option<Credentials> {
+        Some(Credentials {
+            consumer_key: self.consumer_key.clone().filter(|k| !k.is_empty())?,
+            consumer_secret: self.consumer_secret.clone().filter(|s| !s.is_empty())?,
+            ..Credentials::default()
+        })
+    }
+
+    /// Everything needed to sign requests, if `cogsy auth` has been run.
+    pub fn oauth(&self) -> Option<Credentials> {
+        Some(Credentials {
+            token: self.oauth_token.clone().filter(|t| !t.is_empty())?,
+            token_secret: self.oauth_token_secret.clone()?,
+            ..self.consumer()?
+        })
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Appearance {
     pub format: Option<String>,
@@ -170,7 +253,7 @@ impl Appearance {
             // ensuring all values are strings
             for (k, v) in self.messagecol().iter() {
                 if !v.is_str() {
-                    Message::error(&format!("Incorrect type for `{}`", k))
+                    Message::error(format!("Incorrect type for `{}`", k))
                 }
             }
 
@@ -333,6 +416,15 @@ fn gen_default_msg_cols() -> Value {
     Value::Table(table)
 }
 
+fn prompt(question: &str) -> String {
+    println!("{}", question);
+    print!(">>> "); io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)
+        .expect("Oops, could not read line.");
+    answer.trim().to_string()
+}
+
 impl Default for Config {
     fn default() -> Config {
         Config {
@@ -342,15 +434,11 @@ impl Default for Config {
                 timezone: 0.0,
             },
             appearance: Some(Appearance::default()),
+            discogs: None,
         }
     }
 }
 
-#[derive(Deserialize)]
-pub struct ColourScheme {
-
-}
-
 pub fn palette_gen(colours: &mut Appearance) -> Palette {
     colours.resolve();
 
@@ -397,6 +485,42 @@ mod tests {
         assert_eq!(testcfg.user.timezone, 8.0);
     }
 
+    #[test]
+    fn check_api_url() {
+        let mut config: Config = toml::from_str(
+            "[user]\nusername = \"test\"\ntoken = \"token\"\ntimezone = 0.0\n"
+        ).unwrap();
+        assert_eq!(config.api_url_with(None), DEFAULT_API_URL);
+        // an empty variable is no URL at all
+        assert_eq!(config.api_url_with(Some(" ".into())), DEFAULT_API_URL);
+
+        config.discogs = Some(Discogs {api_url: Some("http://localhost:8080/".into()), ..Discogs::default()});
+        assert_eq!(config.api_url_with(None), "http://localhost:8080");
+
+        assert_eq!(config.api_url_with(Some("http://127.0.0.1:9000".into())), "http://127.0.0.1:9000");
+    }
+
+    #[test]
+    fn check_oauth_credentials() {
+        let config: Config = toml::from_str(
+            "[user]\nusername = \"test\"\ntimezone = 0.0\n\
+            [discogs]\nconsumer_key = \"key\"\nconsumer_secret = \"secret\"\n"
+        ).unwrap();
+        let discogs = config.discogs.unwrap();
+        assert_eq!(discogs.consumer().unwrap().consumer_key, "key");
+        // not authorized yet
+        assert!(discogs.oauth().is_none());
+
+        let discogs = Discogs {
+            oauth_token: Some("token".into()),
+            oauth_token_secret: Some("token secret".into()),
+            ..discogs
+        };
+        let credentials = discogs.oauth().unwrap();
+        assert_eq!((credentials.consumer_secret.as_str(), credentials.token.as_str()), ("secret", "token"));
+        assert!(Discogs {consumer_key: None, ..discogs}.oauth().is_none());
+    }
+
     #[test]
     fn check_filepaths() {
         let mut homedir = home_dir().unwrap();
@@ -42,6 +42,12 @@ fn main() {
     if let Some(sub_m) = clapapp.subcommand_matches("database") {
         exit(cli::handle_database(sub_m).unwrap());
     }
+    if clapapp.subcommand_matches("auth").is_some() {
+        exit(cli::handle_auth().unwrap());
+    }
+    if let Some(sub_m) = clapapp.subcommand_matches("config") {
+        exit(cli::handle_config(sub_m).unwrap());
+    }
     let mut app = App::initialize();
     
     if let Some(status) = cli::parse_and_execute(clapapp, &app) {
@@ -51,6 +57,8 @@ fn main() {
     let mut siv = cursive::default();
     siv.set_theme(config::theme_gen(&mut app.appearance));
 
+    let db = app.db.clone();
+
     //initialize screen data
     let collectscreen = app.collection.build(
         app.appearance.folders() as usize
@@ -82,7 +90,7 @@ fn main() {
     
     siv.add_fullscreen_layer(main_layout);
 
-    App::add_callbacks(&mut siv);
+    App::add_callbacks(&mut siv, db);
 
     siv.run();
 }
\ No newline at end of file
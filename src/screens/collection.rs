@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use cursive::{
     Cursive,
     views::{
@@ -22,32 +24,34 @@ use crate::APPEARANCE;
 
 #[derive(Debug, Clone)]
 pub struct Collection {
-    pub folders: Folders
+    pub folders: Folders,
+    db: Rc<Database>,
 }
 
 impl Collection {
-    pub fn new() -> Self {
+    pub fn new(db: Rc<Database>) -> Self {
         Collection {
-            folders: query::collection(APPEARANCE.sort_by())
-                .unwrap_or_else(|_| Folders::new())
+            folders: query::collection(&db, APPEARANCE.sort_by())
+                .unwrap_or_else(|_| Folders::new()),
+            db,
         }
     }
     pub fn build(&self, folders_width: usize) -> NamedView<LinearLayout> {
+        let db = self.db.clone();
         let collection = LinearLayout::horizontal()
             .child(Panel::new(ResizedView::new(
                 SizeConstraint::Fixed(folders_width), 
                 SizeConstraint::Full,
                 ScrollView::new(
                     SelectView::<Vec<Release>>::new()
-                    .with_all(self.folders.contents
-                        .clone().into_iter())
+                    .with_all(self.folders.contents.clone())
                     .on_select(|s, item| {
                         s.call_on_name("albumlist",
                         |view: &mut SelectView<Release>| {
                             view.clear();
                             view.add_all(item.clone().into_iter()
                                 .map(|i| {
-                                    (i.format(&APPEARANCE.format.as_ref().unwrap()), i)
+                                    (i.format(APPEARANCE.format.as_ref().unwrap()), i)
                                 })
                             )   
                         });
@@ -63,11 +67,11 @@ impl Collection {
                     .with_all(self.folders.contents
                         .values().next().unwrap_or(&Vec::new())
                         .clone().into_iter().map(|i| {
-                        (i.format(&APPEARANCE.format.as_ref().unwrap()), i)
+                        (i.format(APPEARANCE.format.as_ref().unwrap()), i)
                     }))
-                    .on_submit(|s, item| {
+                    .on_submit(move |s, item| {
                         s.add_fullscreen_layer(
-                            popup::albuminfo(item)
+                            popup::albuminfo(&db, item)
                         );
                     })
                     .with_name("albumlist"))))
@@ -79,10 +83,29 @@ impl Collection {
 
     pub fn refresh(&mut self, s: &mut Cursive) {
         //update from database and reload its contents
-        //call database method here
-        s.call_on_name("folderlist", |view: &mut SelectView<Vec<Release>>| {
+        self.folders = query::collection(&self.db, APPEARANCE.sort_by())
+            .unwrap_or_else(|_| Folders::new());
+        //* the same folder stays selected if it's still there, and the album list follows it
+        let folders = self.folders.contents();
+        let selected = s.call_on_name("folderlist", |view: &mut SelectView<Vec<Release>>| {
+            let name = view.selected_id()
+                .and_then(|idx| view.get_item(idx))
+                .map(|(name, _)| name.to_string());
+            view.clear();
+            view.add_all(folders.clone());
+            let idx = name
+                .and_then(|name| folders.keys().position(|folder| *folder == name))
+                .unwrap_or(0);
+            view.set_selection(idx);
+            view.selection()
+        }).flatten();
+        s.call_on_name("albumlist", |view: &mut SelectView<Release>| {
             view.clear();
-            view.add_all(self.folders.contents().into_iter())
+            if let Some(releases) = selected {
+                view.add_all(releases.iter().cloned().map(|i| {
+                    (i.format(APPEARANCE.format.as_ref().unwrap()), i)
+                }));
+            }
         });
     }
 }
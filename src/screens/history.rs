@@ -14,7 +14,7 @@ use crate::utils;
 use crate::CONFIG;
 use crate::app::{
     ListenLog,
-    database::query,
+    database::{Database, query},
 };
 
 impl ListenLog { //wrapper around a BTreeMap
@@ -24,8 +24,8 @@ impl ListenLog { //wrapper around a BTreeMap
             contents: new_self,
         }
     }
-    pub fn init() -> Self {
-        match query::listenlog() {
+    pub fn init(db: &Database) -> Self {
+        match query::listenlog(db) {
             Ok(listenlog) => listenlog,
             Err(e) => panic!("{}", e.to_string())
         }
@@ -34,7 +34,7 @@ impl ListenLog { //wrapper around a BTreeMap
             ResizedView<
             ScrollView<
             SelectView<String>>>> {
-        let list: Vec<String> = self.contents.iter().map(|(k, _v)| {
+        let list: Vec<String> = self.contents.keys().map(|k| {
             let nk = k.with_timezone(&CONFIG.timezone());
             format!("{}", nk.format("%a %d %b %Y, %l:%M%P"))
         }).rev().collect();
@@ -64,11 +64,11 @@ impl ListenLog { //wrapper around a BTreeMap
             )
         ))
     }
-    pub fn build_sparkview(&self) -> Panel<
+    pub fn build_sparkview(&self, db: &Database) -> Panel<
         ResizedView<
         ScrollView<
         SelectView<String>>>> {
-            let list: Vec<String> = self.build_sparkview_btreemap()
+            let list: Vec<String> = self.build_sparkview_btreemap(db)
                 .into_iter().map(|(mut k, v)| {
                     k.truncate(40);
                     format!("{:40} | {}", k, v)
@@ -86,7 +86,7 @@ impl ListenLog { //wrapper around a BTreeMap
     //TODO: Optimise this
     //? What is its Big O / space-time complexity?
     #[allow(clippy::map_entry)]
-    pub fn build_sparkview_btreemap(&self) -> BTreeMap<String, String> {
+    pub fn build_sparkview_btreemap(&self, db: &Database) -> BTreeMap<String, String> {
         //* compressing ListenLog into date | listens on that day -> listengraph
         let mut listengraph = BTreeMap::<Date<Utc>, Vec<String>>::new();
         for (datetime, string) in &self.contents {
@@ -119,7 +119,7 @@ impl ListenLog { //wrapper around a BTreeMap
 
         //* drawing the sparkview
         let mut final_graph = BTreeMap::<String, String>::new();
-        for title in query::all_titles().unwrap_or_else(|_| vec![]) {
+        for title in query::all_titles(db).unwrap_or_else(|_| vec![]) {
             let mut listen_sparkview = String::new();
             for vec in listengraph.values() {
                 //listens for that album on that particular day
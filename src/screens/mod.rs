@@ -2,8 +2,8 @@ pub mod addlog;
 pub mod collection;
 pub mod history;
 pub mod profile;
+pub mod spending;
 pub mod wantlist;
 pub mod popup;
 
-pub use wantlist::Wantlist;
-pub use collection::Collection;
\ No newline at end of file
+pub use wantlist::Wantlist;
\ No newline at end of file
@@ -1,10 +1,15 @@
 use std::collections::HashSet;
+use std::rc::Rc;
 
 use cursive::{
+    Cursive,
     views::{
         ResizedView, 
         Dialog,
+        OnEventView,
+        ScrollView,
         SelectView,
+        TextContent,
         TextView,
     },
     view::SizeConstraint
@@ -14,8 +19,12 @@ use crate::utils;
 use crate::{CONFIG, APPEARANCE};
 use crate::app::{
     Release,
+    ReleaseDetails,
     ListenLogEntry,
-    database::{update, query},
+    Purchase,
+    edit,
+    database::{Database, update, query},
+    request::{self, Auth},
 };
 
 /* 
@@ -24,53 +33,33 @@ use crate::app::{
 */
 
 
-pub fn albuminfo(release: &Release) -> ResizedView<Dialog> {
-    //TODO: Format the Label and Formats fields properly
-    let set: HashSet<_> = release.labels.clone().drain(..).collect();
-    let mut labels: Vec<String> = Vec::new();
-    labels.extend(set.into_iter());
-
-    let formats = release.formats.clone();
-
-    let display_time = release.date_added
-    .with_timezone(&CONFIG.timezone());
-
-    let content = format!("
-    Artist: {}
-
-    Year Released: {}
-
-    Labels: {}
-
-    Formats: {}
-
-    Date Added: {}
-    
-    Discogs ID: {}",
-    release.artist,
-    release.year,
-    labels.join(", "),
-    formats.join(", "),
-    display_time.format("%A %d %m %Y %R"),
-    release.id,
-    );
+pub fn albuminfo(db: &Rc<Database>, release: &Release) -> OnEventView<ResizedView<Dialog>> {
+    //* the rating comes from the database, the release may have been rated since it was loaded
+    let rating = query::owned(db, release.id).ok().flatten().map(|owned| owned.rating);
+    let purchases = query::purchases(db, release.id).unwrap_or_default();
+    let content = TextContent::new(info(release, rating, &purchases));
 
     let title = release.title.clone();
     let title2 = title.clone();
     let artist = release.artist.clone();
     let id = release.id;
+    let db2 = db.clone();
+    let db3 = db.clone();
+    let db4 = db.clone();
+    let heading = format!("{} - {}", artist, title);
 
-    ResizedView::new(
+    let mut popup = OnEventView::new(ResizedView::new(
         SizeConstraint::Full,
         SizeConstraint::Full,
-        Dialog::text(content)
+        Dialog::around(TextView::new_with_content(content.clone()))
             .title(format!("{} - {}", 
             artist, title))
             .button("Ok", move |s| {
                 s.pop_layer();
             })
+            .button("Details", move |s| show_details(s, &db4, id, &heading))
             .button("History", move |s| {
-                match query::listenlog_by_title(&title2) {
+                match query::listenlog_by_title(&db2, &title2) {
                     Ok(log) => {
                         s.add_fullscreen_layer(
                             log.build_history_title()
@@ -91,7 +80,7 @@ pub fn albuminfo(release: &Release) -> ResizedView<Dialog> {
                     time: utils::get_utc_now(),
                 };
 
-                match update::listenlog(entry) {
+                match update::listenlog(&db3, entry) {
                     Ok(()) => {
                         s.call_on_name("messagebox", |view: &mut TextView| {
                             view.set_content(format!("Listening to `{}` by {}", title, artist))
@@ -106,34 +95,227 @@ pub fn albuminfo(release: &Release) -> ResizedView<Dialog> {
                     }
                 }
             })
+    ));
+
+    //* only what's in the collection can be rated; 0 clears the rating
+    if rating.is_some() {
+        for stars in 0..=5u8 {
+            let db = db.clone();
+            let release = release.clone();
+            let content = content.clone();
+            popup = popup.on_event((b'0' + stars) as char, move |s| {
+                rate(s, &db, &release, stars, &content)
+            });
+        }
+    }
+    popup
+}
+
+fn info(release: &Release, rating: Option<u8>, purchases: &[Purchase]) -> String {
+    //TODO: Format the Label and Formats fields properly
+    let set: HashSet<_> = release.labels.clone().drain(..).collect();
+    let mut labels: Vec<String> = Vec::new();
+    labels.extend(set);
+
+    let formats = release.formats.clone();
+
+    let display_time = release.date_added
+    .with_timezone(&CONFIG.timezone());
+
+    let mut content = format!("
+    Artist: {}
+
+    Year Released: {}
+
+    Labels: {}
+
+    Formats: {}
+
+    Date Added: {}
+    
+    Discogs ID: {}",
+    release.artist,
+    release.year,
+    labels.join(", "),
+    formats.join(", "),
+    display_time.format("%A %d %m %Y %R"),
+    release.id,
+    );
+    //* which copy this is, for moving or removing just this one
+    if let Some(instance_id) = release.instance_id {
+        content.push_str(&format!("\n\n    Copy ID: {}/{}", release.id, instance_id));
+    }
+    //* older releases don't have these until they're synced again
+    if !release.genres.is_empty() {
+        content.push_str(&format!("\n\n    Genres: {}", release.genres.join(", ")));
+    }
+    if !release.styles.is_empty() {
+        content.push_str(&format!("\n\n    Styles: {}", release.styles.join(", ")));
+    }
+    if let Some(master_id) = release.master_id {
+        content.push_str(&format!("\n\n    Master ID: {}", master_id));
+    }
+    if !release.cover_image.is_empty() {
+        content.push_str(&format!("\n\n    Cover: {}", release.cover_image));
+    }
+    if let Some(rating) = rating {
+        content.push_str(&format!("\n\n    Rating: {}", stars(rating)));
+    }
+    //* conditions and notes belong to this copy, not every copy of the release
+    for note in &release.notes {
+        content.push_str(&format!("\n\n    {}: {}", note.field, note.value));
+    }
+    for purchase in purchases {
+        content.push_str(&format!("\n\n    Paid: {} {} on {}",
+            utils::format_price(purchase.amount), purchase.currency, purchase.date.format("%d %m %Y")
+        ));
+        if let Some(store) = &purchase.store {
+            content.push_str(&format!(" at {}", store));
+        }
+    }
+    content
+}
+
+fn stars(rating: u8) -> String {
+    match rating {
+        0 => String::from("Not rated (press 1-5 to rate)"),
+        n => format!("{}{}", "★".repeat(n as usize), "☆".repeat(5 - n as usize)),
+    }
+}
+
+fn rate(s: &mut Cursive, db: &Database, release: &Release, stars: u8, content: &TextContent) {
+    let requester = request::build_client(&Auth::from_config(&CONFIG));
+    match edit::rate(db, &requester, &CONFIG.user.username, release.id, stars) {
+        Ok(rated) => {
+            let purchases = query::purchases(db, release.id).unwrap_or_default();
+            content.set_content(info(release, Some(rated.rating), &purchases));
+            s.call_on_name("messagebox", |view: &mut TextView| {
+                view.set_content(format!("Rated `{}` by {} {}/5", rated.title, rated.artist, rated.rating));
+                view.set_style(APPEARANCE.success_col());
+            });
+        }
+        Err(e) => {
+            s.add_layer(Dialog::info(e.to_string()).title("Error"));
+        }
+    }
+}
+
+//* cached details open straight away, even offline; the rest are fetched first
+fn show_details(s: &mut Cursive, db: &Rc<Database>, id: i64, heading: &str) {
+    let requester = request::build_client(&Auth::from_config(&CONFIG));
+    match crate::app::update::details(db, &requester, id) {
+        Ok(details) => s.add_fullscreen_layer(release_details(db, &details, heading)),
+        Err(e) => s.add_layer(Dialog::info(e.to_string()).title("Error")),
+    }
+}
+
+pub fn release_details(db: &Rc<Database>, details: &ReleaseDetails, heading: &str) -> ResizedView<Dialog> {
+    let content = TextContent::new(details_text(details));
+    let content2 = content.clone();
+    let db = db.clone();
+    let id = details.id;
+    ResizedView::new(
+        SizeConstraint::Full,
+        SizeConstraint::Full,
+        Dialog::around(ScrollView::new(TextView::new_with_content(content)))
+            .title(heading)
+            .button("Refresh", move |s| {
+                let requester = request::build_client(&Auth::from_config(&CONFIG));
+                match crate::app::update::refresh_details(&db, &requester, id) {
+                    Ok(details) => content2.set_content(details_text(&details)),
+                    //* the cached details are still there to look at
+                    Err(e) => s.add_layer(Dialog::info(e.to_string()).title("Error")),
+                }
+            })
+            .button("Back", |s| {
+                s.pop_layer();
+            })
     )
 }
 
-pub fn multiple_results(results: Vec<Release>, from_listen: bool) -> ResizedView<Dialog> {
+fn details_text(details: &ReleaseDetails) -> String {
+    let or_unknown = |s: &str| if s.is_empty() {String::from("Unknown")} else {s.to_string()};
+    let fetched = details.fetched.with_timezone(&CONFIG.timezone());
+    let mut content = format!("
+    Country: {}
+
+    Released: {}
+
+    Fetched: {} (Refresh to fetch again)
+
+    Tracklist:
+",
+    or_unknown(&details.country),
+    or_unknown(&details.released),
+    fetched.format("%A %d %m %Y %R"),
+    );
+    for track in &details.tracklist {
+        if track.heading {
+            content.push_str(&format!("\n      {}\n", track.title));
+            continue
+        }
+        content.push_str(&format!("      {:<6}{}", track.position, track.title));
+        if !track.duration.is_empty() {
+            content.push_str(&format!(" ({})", track.duration));
+        }
+        content.push('\n');
+    }
+    if !details.credits.is_empty() {
+        content.push_str("\n    Credits:\n");
+        for credit in &details.credits {
+            content.push_str(&format!("      {}: {}", credit.role, credit.name));
+            if !credit.tracks.is_empty() {
+                content.push_str(&format!(" (tracks {})", credit.tracks));
+            }
+            content.push('\n');
+        }
+    }
+    if !details.identifiers.is_empty() {
+        content.push_str("\n    Identifiers:\n");
+        for identifier in &details.identifiers {
+            content.push_str(&format!("      {}: {}", identifier.kind, identifier.value));
+            if !identifier.description.is_empty() {
+                content.push_str(&format!(" ({})", identifier.description));
+            }
+            content.push('\n');
+        }
+    }
+    if !details.notes.is_empty() {
+        content.push_str("\n    Notes:\n");
+        for line in details.notes.lines() {
+            content.push_str(&format!("      {}\n", line));
+        }
+    }
+    content
+}
+
+pub fn multiple_results(db: &Rc<Database>, results: Vec<Release>, from_listen: bool) -> ResizedView<Dialog> {
+    let db = db.clone();
     ResizedView::new(
         SizeConstraint::Full,
         SizeConstraint::Full,
         Dialog::around(
             SelectView::<Release>::new()
+            //* results come in ranked, so the best match is on top
             .with_all(
-                results.clone().into_iter().map(|i| {
-                    (format!("{} ({})", i.title.clone(), i.formats[0]), i)
+                results.into_iter().map(|i| {
+                    (format!("{} - {} ({})", i.artist, i.title, i.formats[0]), i)
                 })
             )
             .on_submit(move |s, item| {
                 s.pop_layer();
                 if !from_listen {
                     s.add_fullscreen_layer(
-                        albuminfo(item)
+                        albuminfo(&db, item)
                     );
                 } else {
                     let time_now = utils::get_utc_now();
                     let entry = ListenLogEntry {
-                        id: results[0].id,
-                        title: &results[0].title,
+                        id: item.id,
+                        title: &item.title,
                         time: time_now,
                     };
-                    update::listenlog(entry).unwrap_or_else(|_| {})
+                    update::listenlog(&db, entry).unwrap_or(())
                 }
             })
         ).title("Multiple results for query")
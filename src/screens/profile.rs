@@ -3,19 +3,25 @@ use cursive::{
     view::SizeConstraint
 };
 
+use chrono::{DateTime, Utc};
+
 use crate::CONFIG;
-use crate::app::database::query;
+use crate::utils;
+use crate::app::{
+    CollectionValue,
+    database::{Database, query},
+};
 
-pub fn build() -> ResizedView<Dialog> {
+pub fn build(db: &Database) -> ResizedView<Dialog> {
     //TODO: Implement default for DateTime so can call default if query fails
-    let profile = query::profile().unwrap_or_else(
+    let profile = query::profile(db).unwrap_or_else(
         |_s| panic!("Fatal: Could not load profile from database.")
     );
 
     let display_time = profile.registered
     .with_timezone(&CONFIG.timezone());
 
-    let content = format!("
+    let mut content = format!("
     Username: {}
     Name: {}
 
@@ -36,6 +42,8 @@ pub fn build() -> ResizedView<Dialog> {
     profile.rated,
     profile.average_rating,
     );
+    //* the history is only for show here, the profile is still worth showing without it
+    content.push_str(&value(&query::value_history(db).unwrap_or_default()));
 
     let screen = ResizedView::new(
         SizeConstraint::Full,
@@ -45,4 +53,69 @@ pub fn build() -> ResizedView<Dialog> {
             profile.username))
     );
     screen
-}
\ No newline at end of file
+}
+
+fn value(history: &[CollectionValue]) -> String {
+    let latest = match history.last() {
+        Some(latest) => latest,
+        None => return String::from("\n\n    Collection value: not fetched yet, update to fetch it"),
+    };
+    let mut content = format!("\n\n    Collection value: {} (minimum {}, maximum {})",
+        latest.median, latest.minimum, latest.maximum
+    );
+    //* medians that can't be read are left out, each one keeps the date it was fetched
+    let medians: Vec<(DateTime<Utc>, f64)> = history.iter()
+        .filter_map(|value| utils::parse_amount(&value.median).map(|median| (value.fetched, median)))
+        .collect();
+    if medians.len() > 1 {
+        let (first, start) = medians[0];
+        let (before, previous) = medians[medians.len() - 2];
+        let (last, now) = medians[medians.len() - 1];
+        let date = |fetched: DateTime<Utc>| fetched.with_timezone(&CONFIG.timezone()).format("%d %m %Y").to_string();
+        let adjacent = last == latest.fetched && before == history[history.len() - 2].fetched;
+        let values: Vec<f64> = medians.iter().map(|(_, median)| *median).collect();
+        content.push_str(&format!("\n    Trend: {} {} since {}, {} since {}",
+            //* the last 30 updates fit on most screens
+            utils::sparkline(&values[values.len().saturating_sub(30)..]),
+            utils::change(previous, now),
+            if adjacent {String::from("the last update")} else {date(before)},
+            utils::change(start, now),
+            date(first),
+        ));
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn snapshot(day: u32, median: &str) -> CollectionValue {
+        CollectionValue {
+            fetched: Utc.ymd(2026, 1, day).and_hms(12, 0, 0),
+            minimum: String::from("$0.00"),
+            median: median.to_string(),
+            maximum: String::from("$0.00"),
+        }
+    }
+
+    #[test]
+    fn test_value_trend_between_adjacent_updates() {
+        let content = value(&[snapshot(1, "$100.00"), snapshot(2, "$110.00"), snapshot(3, "$121.00")]);
+        assert!(content.contains("+11.00 (+10.0%) since the last update, +21.00 (+21.0%) since 01 01 2026"));
+    }
+
+    #[test]
+    fn test_value_trend_skips_unreadable_medians() {
+        // the first and the one before the latest can't be read,
+        // so the changes are dated with the snapshots they really compare
+        let content = value(&[
+            snapshot(1, "N/A"),
+            snapshot(2, "$100.00"),
+            snapshot(3, "1,234"),
+            snapshot(4, "$150.00"),
+        ]);
+        assert!(content.contains("+50.00 (+50.0%) since 02 01 2026, +50.00 (+50.0%) since 02 01 2026"));
+    }
+}
@@ -0,0 +1,71 @@
+use cursive::{
+    views::{ResizedView, Dialog, ScrollView, TextView},
+    view::SizeConstraint
+};
+
+use crate::utils;
+use crate::app::{
+    Spending,
+    database::{Database, DBError, query::{self, SpendingBy}},
+};
+
+const NOTHING_YET: &str = "Nothing spent yet. Record what you paid for an album with `price \"album\" amount`.";
+
+pub fn build(db: &Database) -> ResizedView<Dialog> {
+    let content = match report(db) {
+        Ok(Some(report)) => report,
+        Ok(None) => NOTHING_YET.to_string(),
+        Err(e) => e.to_string(),
+    };
+    ResizedView::new(
+        SizeConstraint::Full,
+        SizeConstraint::Full,
+        Dialog::around(ScrollView::new(TextView::new(content)))
+            .title("Spending")
+    )
+}
+
+/// The totals, then the spending by year, month and folder.
+/// None if nothing has been recorded.
+pub fn report(db: &Database) -> Result<Option<String>, DBError> {
+    let years = query::spending(db, SpendingBy::Year)?;
+    if years.is_empty() {
+        return Ok(None)
+    }
+    let mut report = format!("Total: {}\n\n", totals(&years));
+    for (heading, by) in &[
+        ("Year", SpendingBy::Year),
+        ("Month", SpendingBy::Month),
+        ("Folder", SpendingBy::Folder),
+    ] {
+        report.push_str(&table(heading, &query::spending(db, *by)?));
+        report.push('\n');
+    }
+    Ok(Some(report))
+}
+
+/// One row per group and currency, under `heading`.
+pub fn table(heading: &str, spending: &[Spending]) -> String {
+    let mut table = format!("{:<30} {:>12} {:<8} {:>9}\n", heading, "Spent", "Currency", "Purchases");
+    for row in spending {
+        table.push_str(&format!("{:<30} {:>12} {:<8} {:>9}\n",
+            row.group, utils::format_price(row.total), row.currency, row.purchases
+        ));
+    }
+    table
+}
+
+//* currencies aren't converted, so there's a total for each one
+fn totals(spending: &[Spending]) -> String {
+    let mut totals: Vec<(&str, i64)> = Vec::new();
+    for row in spending {
+        match totals.iter_mut().find(|(currency, _)| *currency == row.currency) {
+            Some((_, total)) => *total += row.total,
+            None => totals.push((&row.currency, row.total)),
+        }
+    }
+    totals.iter()
+        .map(|(currency, total)| format!("{} {}", utils::format_price(*total), currency))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
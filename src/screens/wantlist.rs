@@ -1,37 +1,50 @@
+use std::rc::Rc;
+
 use cursive::{
+    Cursive,
     views::*,
     view::SizeConstraint,
+    event::Key,
     traits::*
 };
 use crate::app::{
     {Release},
     database::*,
+    edit,
+    request::{self, Auth, UpdateError},
 };
 use crate::screens::popup;
-use crate::APPEARANCE;
+use crate::{APPEARANCE, CONFIG};
 
 //use crate::app::message::{Message, MessageKind};
 
 #[derive(Debug, Clone)]
 pub struct Wantlist {
-    wantlist: Vec<Release>
+    wantlist: Vec<Release>,
+    db: Rc<Database>,
 }
 
 impl Wantlist {
-    pub fn init() -> Self {
-        Wantlist{ //placeholder: will read from database
-            wantlist: query::wantlist(APPEARANCE.sort_by()).unwrap()
+    pub fn init(db: Rc<Database>) -> Self {
+        Wantlist {
+            wantlist: query::wantlist(&db, APPEARANCE.sort_by()).unwrap(),
+            db,
         }
     }
+    #[allow(clippy::type_complexity)]
     pub fn build(&self) -> Panel< //long-ass return type declaration
             ResizedView<
             ScrollView<
+            OnEventView<
             NamedView<
-            SelectView<Release>>>>> {
+            SelectView<Release>>>>>> {
+        let db = self.db.clone();
+        let db2 = self.db.clone();
+        let db3 = self.db.clone();
         Panel::new(ResizedView::new(
             SizeConstraint::Full,
             SizeConstraint::Full,
-            ScrollView::new(
+            ScrollView::new(OnEventView::new(
                 SelectView::<Release>::new()
                 .with_all(self.wantlist.clone().into_iter().map(|mut i| {
                     i.artist.truncate(30);
@@ -39,16 +52,58 @@ impl Wantlist {
                         i.artist, i.title);
                     (formatted, i)
                 }))
-                .on_submit(|s, item| {
+                .on_submit(move |s, item| {
                     s.add_fullscreen_layer(
-                        popup::albuminfo(item)
+                        popup::albuminfo(&db, item)
                     );
                 })
                 .with_name("wantlist"))
+                .on_event('d', move |s| confirm_removal(s, &db2))
+                .on_event(Key::Del, move |s| confirm_removal(s, &db3))
+            )
         ))
     }
 }
 
+//* removing takes it off Discogs as well, so ask first
+fn confirm_removal(s: &mut Cursive, db: &Rc<Database>) {
+    let selected = s.call_on_name("wantlist", |view: &mut SelectView<Release>| view.selection());
+    let release = match selected {
+        Some(Some(release)) => release,
+        _ => return,
+    };
+    let db = db.clone();
+    s.add_layer(Dialog::text(format!(
+        "Remove `{}` by {} from your wantlist on Discogs?", release.title, release.artist
+    ))
+        .title("Wantlist")
+        .button("Remove", move |s| {
+            s.pop_layer();
+            let requester = request::build_client(&Auth::from_config(&CONFIG));
+            match edit::remove_want(&db, &requester, &CONFIG.user.username, release.id) {
+                //* if Discogs didn't have it either, it's gone from both now
+                Ok(_) | Err(UpdateError::NotWantedError(_)) => {
+                    s.call_on_name("wantlist", |view: &mut SelectView<Release>| {
+                        let idx = view.iter().position(|(_, r)| r.id == release.id);
+                        if let Some(idx) = idx {
+                            let _ = view.remove_item(idx);
+                        }
+                    });
+                    s.call_on_name("messagebox", |view: &mut TextView| {
+                        view.set_content(format!(
+                            "Removed `{}` by {} from your wantlist", release.title, release.artist
+                        ));
+                        view.set_style(APPEARANCE.success_col());
+                    });
+                }
+                Err(e) => {
+                    s.add_layer(Dialog::info(e.to_string()).title("Error"));
+                }
+            }
+        })
+        .dismiss_button("Cancel"));
+}
+
 //TODO: Implement formatting of titles
 //formats a vector of Release structs into an iterator of formatted strings
 //might move this to a dedicated utils module if enough helper funcs are added
@@ -5,9 +5,8 @@ use std::iter::Enumerate;
 
 use directories::ProjectDirs;
 use chrono::{DateTime, Utc, Local};
+use unidecode::unidecode;
 
-use crate::config::Config;
-use crate::app::database::query;
 
 fn project_dirs() -> ProjectDirs {
     ProjectDirs::from("rs", "cartoon-raccoon", "cogsy")
@@ -32,8 +31,99 @@ pub fn database_file() -> PathBuf {
     datafile
 }
 
-pub fn usernames_match() -> bool {
-    Config::load().user.username == query::profile().unwrap().username
+/// Discogs usernames aren't case sensitive, and stray whitespace
+/// from the setup prompt shouldn't count either.
+pub fn usernames_match(configured: &str, actual: &str) -> bool {
+    configured.trim().to_lowercase() == actual.trim().to_lowercase()
+}
+
+/// Normalizes a title into the form stored in the search_string column.
+/// Queries go through this as well, so both sides always match up.
+pub fn search_string(title: &str) -> String {
+    unidecode(title)
+        .replace(&['(', ')', ',', '*', '\"', '.', ':', '!', '?', ';', '\''][..], "")
+}
+
+/// Reads an amount Discogs formatted for display, e.g. "$1,203.48" or "€1.234,56".
+/// Whichever of `.` and `,` comes last is the decimal separator and the other
+/// separates thousands. None if that can't be told, as in "1,234".
+pub fn parse_amount(amount: &str) -> Option<f64> {
+    let is_minus = |c: char| c == '-' || c == '\u{2212}';
+    let is_separator = |c: char| c == '.' || c == ',';
+    let start = amount.find(|c: char| c.is_ascii_digit())?;
+    let end = amount.rfind(|c: char| c.is_ascii_digit())? + 1;
+
+    //* the sign goes in front of the number, on either side of the currency
+    let negative = match amount[..start].chars().filter(|c| is_minus(*c)).count() {
+        0 => false,
+        1 => true,
+        _ => return None,
+    };
+    if amount[end..].contains(is_minus) {
+        return None
+    }
+
+    let number = &amount[start..end];
+    if !number.chars().all(|c| c.is_ascii_digit() || is_separator(c)) {
+        return None
+    }
+    let (whole, fraction) = match number.rfind(is_separator) {
+        None => (number, ""),
+        Some(idx) => {
+            let (whole, fraction) = (&number[..idx], &number[idx + 1..]);
+            if whole.contains(&number[idx..=idx]) {
+                //* the same separator twice only ever separates thousands
+                (number, "")
+            } else if whole.contains(is_separator) || fraction.len() != 3 {
+                (whole, fraction)
+            } else {
+                //* a thousand somewhere, one and a bit somewhere else
+                return None
+            }
+        }
+    };
+
+    //* thousands are all separated the same way, in groups of three
+    if whole.contains('.') && whole.contains(',') {
+        return None
+    }
+    let groups: Vec<&str> = whole.split(is_separator).collect();
+    if groups.len() > 1 && (groups[0].len() > 3 || groups[1..].iter().any(|group| group.len() != 3)) {
+        return None
+    }
+
+    let value: f64 = format!("{}.{}", groups.concat(), if fraction.is_empty() {"0"} else {fraction})
+        .parse().ok()?;
+    Some(if negative {-value} else {value})
+}
+
+/// A price kept in hundredths, written out with its two decimals, e.g. "12.50".
+pub fn format_price(hundredths: i64) -> String {
+    let sign = if hundredths < 0 {"-"} else {""};
+    format!("{}{}.{:02}", sign, (hundredths / 100).abs(), (hundredths % 100).abs())
+}
+
+/// A one-line chart of `values`, lowest to highest.
+pub fn sparkline(values: &[f64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    values.iter().map(|value| {
+        if max > min {
+            BARS[((value - min) / (max - min) * 7.0).round() as usize]
+        } else {
+            BARS[3]
+        }
+    }).collect()
+}
+
+/// How much `to` is up or down on `from`, e.g. "+12.40 (+2.1%)".
+pub fn change(from: f64, to: f64) -> String {
+    if from > 0.0 {
+        format!("{:+.2} ({:+.1}%)", to - from, (to - from) / from * 100.0)
+    } else {
+        format!("{:+.2}", to - from)
+    }
 }
 
 pub fn get_utc_now() -> DateTime<Utc> {
@@ -53,6 +143,8 @@ pub enum FormatToken<'a> {
     Year,
     Labels,
     Formats,
+    Genres,
+    Styles,
     DateAdded,
     RawStr(&'a str),
     Unknown,
@@ -93,11 +185,7 @@ impl<'a> Iterator for FormatTokenizer<'a> {
                     //     continue
                     // }
                     self.buf.clear();
-                    return Some(RawStr(if let Some(r) = self.inner.get(self.prev..idx) {
-                        r
-                    } else {
-                        ""
-                    }))
+                    return Some(RawStr(self.inner.get(self.prev..idx).unwrap_or_default()))
                 }
                 '}'  => {
                     self.in_token = false;
@@ -109,6 +197,8 @@ impl<'a> Iterator for FormatTokenizer<'a> {
                         "year" => {ret = Year}
                         "labels" => {ret = Labels}
                         "formats" => {ret = Formats}
+                        "genres" => {ret = Genres}
+                        "styles" => {ret = Styles}
                         "date" => {ret = DateAdded}
                         _ => {}
                     }
@@ -131,4 +221,46 @@ impl<'a> Iterator for FormatTokenizer<'a> {
         }
         None
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("$1,203.48"), Some(1203.48));
+        assert_eq!(parse_amount("€1.234,56"), Some(1234.56));
+        assert_eq!(parse_amount("£612.30"), Some(612.30));
+        assert_eq!(parse_amount("12,5 €"), Some(12.5));
+        assert_eq!(parse_amount("¥12000"), Some(12000.0));
+        assert_eq!(parse_amount("1.234.567"), Some(1234567.0));
+        assert_eq!(parse_amount("CHF 1'000.00"), None);
+        assert_eq!(parse_amount("-$5.00"), Some(-5.0));
+        assert_eq!(parse_amount("$-5.00"), Some(-5.0));
+        assert_eq!(parse_amount("-0,99 €"), Some(-0.99));
+    }
+
+    #[test]
+    fn test_format_price() {
+        assert_eq!(format_price(1250), "12.50");
+        assert_eq!(format_price(7), "0.07");
+        assert_eq!(format_price(-1205), "-12.05");
+        assert_eq!(format_price(-50), "-0.50");
+        assert_eq!(format_price(0), "0.00");
+    }
+
+    #[test]
+    fn test_parse_amount_rejects_ambiguous_input() {
+        // a thousand in one place, one and a bit in another
+        assert_eq!(parse_amount("1,234"), None);
+        assert_eq!(parse_amount("€1.234"), None);
+        assert_eq!(parse_amount("1,23,456.00"), None);
+        assert_eq!(parse_amount("1.234,567.89"), None);
+        assert_eq!(parse_amount("5.00-"), None);
+        assert_eq!(parse_amount("--5.00"), None);
+        assert_eq!(parse_amount("1.5.0"), None);
+        assert_eq!(parse_amount("N/A"), None);
+        assert_eq!(parse_amount(""), None);
+    }
+}
@@ -0,0 +1,85 @@
+-- A database written by cogsy 0.2.2, before schema migrations existed.
+-- Collection folders live in their own tables, named with a trailing `_`.
+
+CREATE TABLE profile (
+    username TEXT PRIMARY KEY,
+    real_name TEXT,
+    registered TEXT,
+    listings INTEGER,
+    collection INTEGER,
+    wantlist INTEGER,
+    rated INTEGER,
+    average_rating REAL
+);
+
+CREATE TABLE wantlist (
+    idx INTEGER PRIMARY KEY,
+    hash INTEGER,
+    id INTEGER,
+    search_string TEXT,
+    title TEXT NOT NULL,
+    artist TEXT,
+    year INTEGER,
+    labels TEXT,
+    formats TEXT,
+    date_added TEXT
+);
+
+CREATE TABLE folders (
+    name TEXT PRIMARY KEY
+);
+
+CREATE TABLE listenlog (
+    datetime TEXT PRIMARY KEY,
+    album_id INTEGER,
+    title TEXT
+);
+
+CREATE TABLE "All_" (
+    idx INTEGER PRIMARY KEY,
+    hash INTEGER,
+    id INTEGER,
+    search_string TEXT,
+    title TEXT NOT NULL,
+    artist TEXT,
+    year INTEGER,
+    labels TEXT,
+    formats TEXT,
+    date_added TEXT
+);
+
+CREATE TABLE "Uncategorized_" (
+    idx INTEGER PRIMARY KEY,
+    hash INTEGER,
+    id INTEGER,
+    search_string TEXT,
+    title TEXT NOT NULL,
+    artist TEXT,
+    year INTEGER,
+    labels TEXT,
+    formats TEXT,
+    date_added TEXT
+);
+
+INSERT INTO profile VALUES
+    ('cartoon-raccoon', 'Raccoon', '2020-01-01T00:00:00+00:00', 0, 3, 2, 0, 0.0);
+
+INSERT INTO wantlist VALUES
+    (1, 11, 7021, 'Geogaddi', 'Geogaddi', 'Boards Of Canada', 2002, 'Warp Records', 'Vinyl 3LP', '2020-02-01T00:00:00+00:00'),
+    (2, 12, 8041, 'Dont Stop', 'Don''t Stop', 'Status Quo', 1996, 'PolyGram', 'CD 1', '2020-02-02T00:00:00+00:00');
+
+INSERT INTO folders VALUES ('All_'), ('Uncategorized_');
+
+INSERT INTO "All_" VALUES
+    (1, 21, 1001, 'Music Has The Right To Children', 'Music Has The Right To Children', 'Boards Of Canada', 1998, 'Warp Records:Skam', 'Vinyl 2LP', '2020-03-01T00:00:00+00:00'),
+    (2, 22, 1002, 'Selected Ambient Works 85-92', 'Selected Ambient Works 85-92', 'Aphex Twin', 1992, 'Apollo', 'Vinyl 2LP', '2020-03-02T00:00:00+00:00'),
+    (3, 23, 1003, 'Blue Lines', 'Blue Lines', 'Massive Attack', 1991, 'Wild Bunch', 'CD 1', '2020-03-03T00:00:00+00:00');
+
+INSERT INTO "Uncategorized_" VALUES
+    (1, 21, 1001, 'Music Has The Right To Children', 'Music Has The Right To Children', 'Boards Of Canada', 1998, 'Warp Records:Skam', 'Vinyl 2LP', '2020-03-01T00:00:00+00:00'),
+    (2, 22, 1002, 'Selected Ambient Works 85-92', 'Selected Ambient Works 85-92', 'Aphex Twin', 1992, 'Apollo', 'Vinyl 2LP', '2020-03-02T00:00:00+00:00'),
+    (3, 23, 1003, 'Blue Lines', 'Blue Lines', 'Massive Attack', 1991, 'Wild Bunch', 'CD 1', '2020-03-03T00:00:00+00:00');
+
+INSERT INTO listenlog VALUES
+    ('2021-01-01T20:00:00+00:00', 1001, 'Music Has The Right To Children'),
+    ('2021-01-02T20:00:00+00:00', 1003, 'Blue Lines');